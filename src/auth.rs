@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderName, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::ApiError;
+
+/// The header clients send the API key in
+pub(crate) const API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+/// The header naming the user a request is made for
+pub(crate) const USER_ID: HeaderName = HeaderName::from_static("x-user-id");
+
+/// The user whose todos a request reads and changes, taken from the `X-User-Id` header.
+///
+/// Users only see their own todos. Requests without the header share the todos that don't
+/// belong to anyone, so clients that don't know about users keep working as before.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct UserId(Option<Arc<str>>);
+
+impl UserId {
+    /// The user owning `todo`
+    pub(crate) fn of(todo: &crate::Todo) -> Self {
+        Self(todo.user_id.as_deref().map(Into::into))
+    }
+
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    /// Whether `todo` belongs to this user
+    pub fn owns(&self, todo: &crate::Todo) -> bool {
+        self.as_deref() == todo.user_id.as_deref()
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for UserId {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(USER_ID) else {
+            return Ok(Self(None));
+        };
+
+        let user_id = value.to_str().map_err(|_| {
+            ApiError::bad_request("invalid_user_id", "the X-User-Id header must be ASCII text")
+                .into_response()
+        })?;
+        let user_id = user_id.trim();
+
+        Ok(Self((!user_id.is_empty()).then(|| user_id.into())))
+    }
+}
+
+/// Rejects requests that change todos with 401, unless they carry the configured API key.
+///
+/// Reading is always allowed, and every request is allowed if no key is configured.
+pub(crate) async fn require_api_key(
+    State(api_key): State<Option<Arc<str>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(api_key) = api_key else {
+        return next.run(request).await;
+    };
+
+    // WebSocket upgrades are GET requests, but clients can change todos through the socket
+    let is_read_only = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) && !request.headers().contains_key(header::UPGRADE);
+
+    let is_authorized = request
+        .headers()
+        .get(API_KEY)
+        .is_some_and(|key| constant_time_eq(key.as_bytes(), api_key.as_bytes()));
+
+    if is_read_only || is_authorized {
+        next.run(request).await
+    } else {
+        ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "missing or invalid API key",
+        )
+        .into_response()
+    }
+}
+
+/// Compares without returning early, so response times don't reveal how much of a key matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,327 @@
+use std::{
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::DEFAULT_RATE_LIMIT;
+
+/// The database file used unless another one is configured
+pub const DEFAULT_DB_PATH: &str = "todo_server_workshop_db.db";
+
+/// The address the server listens on unless another one is configured
+pub const DEFAULT_BIND_ADDR: SocketAddr =
+    SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::UNSPECIFIED), 8080);
+
+/// The number of connections opened to database files
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+/// The largest request body accepted unless another limit is configured, 1 MiB
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// How long a request may take unless another timeout is configured
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often completed todos are purged, when purging is turned on
+pub const DEFAULT_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The table todos are stored in unless another one is configured
+pub const DEFAULT_TABLE_NAME: &str = "todos";
+
+/// The longest table name accepted by [`TableName::new`]
+pub const MAX_TABLE_NAME_LENGTH: usize = 64;
+
+/// Names already taken by the tables that sit next to the default `todos` table
+const RESERVED_TABLE_NAMES: &[&str] = &[
+    "tags",
+    "todo_tags",
+    "idempotency_keys",
+    "todo_history",
+    "schema_version",
+];
+
+/// How the app is set up, created with [`AppConfig::builder`].
+///
+/// The defaults are the same as running the server without any `TODO_*` environment variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppConfig {
+    pub(crate) db_path: String,
+    pub(crate) table_name: TableName,
+    pub(crate) pool_size: usize,
+    pub(crate) bind_addr: SocketAddr,
+    pub(crate) socket_path: Option<PathBuf>,
+    pub(crate) cors_origins: Option<Vec<String>>,
+    pub(crate) api_key: Option<String>,
+    pub(crate) rate_limit: u32,
+    pub(crate) max_body_bytes: usize,
+    pub(crate) request_timeout: Duration,
+    pub(crate) seed_path: Option<PathBuf>,
+    pub(crate) max_todos: Option<u64>,
+    pub(crate) purge_completed_after: Option<Duration>,
+    pub(crate) purge_interval: Duration,
+}
+
+impl AppConfig {
+    pub fn builder() -> AppConfigBuilder {
+        AppConfigBuilder::default()
+    }
+
+    /// The path of the SQLite database file, or `:memory:`
+    pub fn db_path(&self) -> &str {
+        &self.db_path
+    }
+
+    /// The table todos are stored in
+    pub fn table_name(&self) -> &TableName {
+        &self.table_name
+    }
+
+    /// The number of connections opened to a database file
+    pub fn pool_size(&self) -> usize {
+        self.pool_size
+    }
+
+    /// The address to listen on, only used when running the server binary
+    pub fn bind_addr(&self) -> SocketAddr {
+        self.bind_addr
+    }
+
+    /// The Unix domain socket to listen on instead of [`AppConfig::bind_addr`], only used when
+    /// running the server binary
+    pub fn socket_path(&self) -> Option<&Path> {
+        self.socket_path.as_deref()
+    }
+
+    /// The origins allowed to call the API from a browser, or `None` if any origin is
+    pub fn cors_origins(&self) -> Option<&[String]> {
+        self.cors_origins.as_deref()
+    }
+
+    /// The key requests that change todos must send, or `None` if anyone may change them
+    pub fn api_key(&self) -> Option<&str> {
+        self.api_key.as_deref()
+    }
+
+    /// Requests per minute allowed from each IP, where 0 turns rate limiting off
+    pub fn rate_limit(&self) -> u32 {
+        self.rate_limit
+    }
+
+    /// The largest request body accepted, in bytes
+    pub fn max_body_bytes(&self) -> usize {
+        self.max_body_bytes
+    }
+
+    /// How long a request may take before it is answered with 408
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    /// A JSON file of todos loaded into the table on startup, if the table is empty
+    pub fn seed_path(&self) -> Option<&Path> {
+        self.seed_path.as_deref()
+    }
+
+    /// The most todos that may be stored, or `None` if there is no limit
+    pub fn max_todos(&self) -> Option<u64> {
+        self.max_todos
+    }
+
+    /// How long completed todos are kept before they are deleted for good, or `None` if they are
+    /// kept forever
+    pub fn purge_completed_after(&self) -> Option<Duration> {
+        self.purge_completed_after
+    }
+
+    /// How often completed todos are purged
+    pub fn purge_interval(&self) -> Duration {
+        self.purge_interval
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            db_path: DEFAULT_DB_PATH.into(),
+            table_name: TableName::default(),
+            pool_size: DEFAULT_POOL_SIZE,
+            bind_addr: DEFAULT_BIND_ADDR,
+            socket_path: None,
+            cors_origins: None,
+            api_key: None,
+            rate_limit: DEFAULT_RATE_LIMIT,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            seed_path: None,
+            max_todos: None,
+            purge_completed_after: None,
+            purge_interval: DEFAULT_PURGE_INTERVAL,
+        }
+    }
+}
+
+/// Builds an [`AppConfig`], every setting left out keeps its default
+#[derive(Debug, Clone, Default)]
+pub struct AppConfigBuilder {
+    config: AppConfig,
+}
+
+impl AppConfigBuilder {
+    pub fn db_path(mut self, db_path: impl Into<String>) -> Self {
+        self.config.db_path = db_path.into();
+        self
+    }
+
+    /// Stores todos in another table, so several apps can share one database file
+    pub fn table_name(mut self, table_name: TableName) -> Self {
+        self.config.table_name = table_name;
+        self
+    }
+
+    /// Ignored for `:memory:`, where every connection would get its own database
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.config.pool_size = pool_size;
+        self
+    }
+
+    pub fn bind_addr(mut self, bind_addr: SocketAddr) -> Self {
+        self.config.bind_addr = bind_addr;
+        self
+    }
+
+    /// Listens on a Unix domain socket at this path instead of on TCP, e.g. behind a proxy on
+    /// the same host
+    pub fn socket_path(mut self, socket_path: impl Into<PathBuf>) -> Self {
+        self.config.socket_path = Some(socket_path.into());
+        self
+    }
+
+    /// Only allows these origins to call the API from a browser, instead of any origin
+    pub fn cors_origins(mut self, origins: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.config.cors_origins = Some(origins.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Requires this key in the `X-API-Key` header of requests that change todos
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.config.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Requests per minute allowed from each IP, 0 turns rate limiting off
+    pub fn rate_limit(mut self, per_minute: u32) -> Self {
+        self.config.rate_limit = per_minute;
+        self
+    }
+
+    pub fn max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.config.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.config.request_timeout = request_timeout;
+        self
+    }
+
+    /// Loads the todos in this JSON file on startup, as long as there aren't any todos yet
+    pub fn seed_path(mut self, seed_path: impl Into<PathBuf>) -> Self {
+        self.config.seed_path = Some(seed_path.into());
+        self
+    }
+
+    /// Refuses to create todos once there are this many, deleted todos don't count
+    pub fn max_todos(mut self, max_todos: u64) -> Self {
+        self.config.max_todos = Some(max_todos);
+        self
+    }
+
+    /// Deletes todos for good once they have been completed for this long, checked every
+    /// [`purge_interval`](Self::purge_interval)
+    pub fn purge_completed_after(mut self, retention: Duration) -> Self {
+        self.config.purge_completed_after = Some(retention);
+        self
+    }
+
+    pub fn purge_interval(mut self, interval: Duration) -> Self {
+        self.config.purge_interval = interval;
+        self
+    }
+
+    pub fn build(self) -> AppConfig {
+        self.config
+    }
+}
+
+/// The name of a table to store todos in.
+///
+/// Names end up in SQL as is, so only plain identifiers are accepted. The tables, indexes and
+/// triggers belonging to a table are prefixed with its name, e.g. `work_tags` for `work`, except
+/// for the default `todos` table which keeps the names it has always had.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TableName(Arc<str>);
+
+impl TableName {
+    /// Checks that `name` is an ASCII identifier of at most [`MAX_TABLE_NAME_LENGTH`] characters
+    pub fn new(name: impl AsRef<str>) -> Result<Self, InvalidTableName> {
+        let name = name.as_ref();
+
+        let starts_with_letter = name
+            .chars()
+            .next()
+            .is_some_and(|first| first.is_ascii_alphabetic() || first == '_');
+        let is_identifier = starts_with_letter
+            && name.len() <= MAX_TABLE_NAME_LENGTH
+            && name
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || char == '_');
+        // SQLite keeps its own tables under the sqlite_ prefix
+        let is_reserved = RESERVED_TABLE_NAMES.contains(&name)
+            || name.to_ascii_lowercase().starts_with("sqlite_");
+
+        if !is_identifier || is_reserved {
+            return Err(InvalidTableName(name.into()));
+        }
+
+        Ok(Self(name.into()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        self.as_str() == DEFAULT_TABLE_NAME
+    }
+}
+
+impl Default for TableName {
+    fn default() -> Self {
+        Self(DEFAULT_TABLE_NAME.into())
+    }
+}
+
+impl fmt::Display for TableName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A table name that isn't a plain identifier, or clashes with a table the app already uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTableName(String);
+
+impl fmt::Display for InvalidTableName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid table name {:?}, use up to {MAX_TABLE_NAME_LENGTH} ASCII letters, digits \
+             and underscores, not starting with a digit or naming one of the app's own tables",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidTableName {}
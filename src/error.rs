@@ -0,0 +1,184 @@
+use std::fmt;
+
+use axum::{
+    extract::Request,
+    http::{header, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::ValidationError;
+
+/// An error returned by a handler, answered with its status and an [`ErrorBody`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ApiError {
+    status: StatusCode,
+    body: ErrorBody,
+}
+
+/// A JSON body describing why a request failed
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+pub(crate) struct ErrorBody {
+    /// A description of the error, meant for humans
+    error: String,
+    /// A stable identifier of the kind of error, meant for programs
+    code: &'static str,
+    /// The field that failed validation, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    /// The path no route matched, if that is why the request failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, code: &'static str, error: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: ErrorBody {
+                error: error.into(),
+                code,
+                field: None,
+                path: None,
+            },
+        }
+    }
+
+    pub(crate) fn bad_request(code: &'static str, error: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, error)
+    }
+
+    pub(crate) fn todo_not_found(id: u32) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "todo_not_found",
+            format!("todo {id} does not exist"),
+        )
+    }
+
+    /// The request body doesn't hold what the route expects, `field` is where in it, if anywhere
+    pub(crate) fn invalid_body(
+        code: &'static str,
+        error: impl Into<String>,
+        field: Option<String>,
+    ) -> Self {
+        Self {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            body: ErrorBody {
+                error: error.into(),
+                code,
+                field,
+                path: None,
+            },
+        }
+    }
+
+    /// No route matches the path
+    pub(crate) fn route_not_found(path: &str) -> Self {
+        let mut error = Self::new(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            format!("nothing is found at {path}"),
+        );
+        error.body.path = Some(path.to_owned());
+        error
+    }
+
+    pub(crate) fn internal(error: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", error)
+    }
+
+    /// The description of the error, for sending it somewhere other than an HTTP response
+    pub(crate) fn message(&self) -> &str {
+        &self.body.error
+    }
+}
+
+impl From<ValidationError> for ApiError {
+    fn from(ValidationError { field, error }: ValidationError) -> Self {
+        Self {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            body: ErrorBody {
+                error,
+                code: "validation_failed",
+                field: Some(field),
+                path: None,
+            },
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body)).into_response()
+    }
+}
+
+/// Answers requests to paths no route matches
+pub(crate) async fn not_found(uri: Uri) -> ApiError {
+    ApiError::route_not_found(uri.path())
+}
+
+/// Gives the empty 405 axum answers with for a method a route doesn't support an
+/// [`ErrorBody`], keeping the `Allow` header that lists the methods it does support
+pub(crate) async fn method_not_allowed(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+
+    let mut error = ApiError::new(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "method_not_allowed",
+        format!("{method} is not allowed here"),
+    )
+    .into_response();
+    if let Some(allow) = response.headers().get(header::ALLOW) {
+        error.headers_mut().insert(header::ALLOW, allow.clone());
+    }
+    error
+}
+
+/// Why the app could not be created
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub enum AppError {
+    /// The database file could not be opened, e.g. because its directory doesn't exist
+    OpenDatabase {
+        path: String,
+        source: tokio_rusqlite::Error,
+    },
+    /// The file is not a SQLite database, or failed the integrity check
+    CorruptDatabase { path: String, problems: String },
+    /// Configuring the connections or migrating the schema failed
+    Database(tokio_rusqlite::Error),
+}
+
+#[cfg(feature = "sqlite")]
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::OpenDatabase { path, source } => {
+                write!(f, "opening the database {path:?} failed: {source}")
+            }
+            AppError::CorruptDatabase { path, problems } => write!(
+                f,
+                "the database {path:?} is corrupt or not a SQLite database: {problems}"
+            ),
+            AppError::Database(error) => write!(f, "setting up the database failed: {error}"),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::OpenDatabase { source, .. } => Some(source),
+            AppError::CorruptDatabase { .. } => None,
+            AppError::Database(error) => Some(error),
+        }
+    }
+}
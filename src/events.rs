@@ -0,0 +1,76 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+use crate::{auth::UserId, AppState, Todo};
+
+/// How many events are buffered for slow subscribers before they start missing events
+pub(crate) const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// What happened to a todo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TodoAction {
+    Created,
+    Updated,
+    Toggled,
+    Deleted,
+}
+
+impl TodoAction {
+    fn name(self) -> &'static str {
+        match self {
+            TodoAction::Created => "created",
+            TodoAction::Updated => "updated",
+            TodoAction::Toggled => "toggled",
+            TodoAction::Deleted => "deleted",
+        }
+    }
+}
+
+/// A change to a todo, sent to everyone listening on `/todos/events`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+pub(crate) struct TodoEvent {
+    pub(crate) action: TodoAction,
+    /// The todo after the change, or as it was before being deleted
+    pub(crate) todo: Todo,
+}
+
+impl AppState {
+    /// Tells every connected client about a change, it's fine if nobody is listening
+    pub(crate) fn publish(&self, action: TodoAction, todo: Todo) {
+        let _ = self.events.send(TodoEvent { action, todo });
+    }
+}
+
+/// Streams every change to the user's todos as it happens, clients only get events that happen
+/// after they connect
+#[utoipa::path(
+    get,
+    path = "/todos/events",
+    responses(
+        (status = 200, description = "A stream of todo events", body = TodoEvent,
+            content_type = "text/event-stream"),
+    )
+)]
+pub(crate) async fn todo_events(
+    State(AppState { events, .. }): State<AppState>,
+    user: UserId,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // Clients that fall too far behind skip the events they missed rather than disconnecting
+    let stream = BroadcastStream::new(events.subscribe()).filter_map(move |event| {
+        let event = event.ok().filter(|event| user.owns(&event.todo))?;
+        Event::default()
+            .event(event.action.name())
+            .json_data(&event)
+            .ok()
+            .map(Ok)
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
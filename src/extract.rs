@@ -0,0 +1,153 @@
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        FromRequest, Request,
+    },
+    http::{header, HeaderMap, StatusCode},
+};
+use axum_macros::FromRequestParts;
+use serde::de::DeserializeOwned;
+
+use crate::error::ApiError;
+
+/// Like [`axum::extract::Path`], but an invalid path is rejected with an [`ApiError`]
+#[derive(Debug, Clone, Copy, Default, FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub(crate) struct Path<T>(pub(crate) T);
+
+/// Like [`axum::extract::Query`], but an invalid query string is rejected with an [`ApiError`]
+#[derive(Debug, Clone, Copy, Default, FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub(crate) struct Query<T>(pub(crate) T);
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        ApiError::new(rejection.status(), "invalid_path", rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::new(rejection.status(), "invalid_query", rejection.body_text())
+    }
+}
+
+/// Like [`axum::Json`], but a body that can't be deserialized is rejected with an [`ApiError`],
+/// naming the field at fault when there is one.
+///
+/// Both malformed JSON and JSON of the wrong shape are answered with 422.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ValidatedJson<T>(pub(crate) T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        ensure_json_content_type(request.headers())?;
+
+        let body = Bytes::from_request(request, state)
+            .await
+            .map_err(|rejection| {
+                ApiError::new(rejection.status(), "invalid_body", rejection.body_text())
+            })?;
+
+        deserialize(&body).map(ValidatedJson)
+    }
+}
+
+/// Deserializes a body that has already been read, for routes where the body is optional.
+///
+/// Fails the same way [`ValidatedJson`] does.
+pub(crate) fn from_body<T: DeserializeOwned>(
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<T, ApiError> {
+    ensure_json_content_type(headers)?;
+    deserialize(body)
+}
+
+fn ensure_json_content_type(headers: &HeaderMap) -> Result<(), ApiError> {
+    if has_json_content_type(headers) {
+        return Ok(());
+    }
+
+    Err(ApiError::new(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "unsupported_media_type",
+        "expected a request with `Content-Type: application/json`",
+    ))
+}
+
+/// Whether the content type is `application/json`, or another JSON type like
+/// `application/merge-patch+json`
+fn has_json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    media_type == "application/json"
+        || (media_type.starts_with("application/") && media_type.ends_with("+json"))
+}
+
+/// Deserializes JSON that has already been parsed, failing the same way [`ValidatedJson`] does
+pub(crate) fn from_value<T: DeserializeOwned>(value: serde_json::Value) -> Result<T, ApiError> {
+    serde_path_to_error::deserialize(value).map_err(|error| {
+        let path = error.path().to_string();
+        invalid_body(error.into_inner(), path)
+    })
+}
+
+fn deserialize<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|error| {
+        let path = error.path().to_string();
+        invalid_body(error.into_inner(), path)
+    })?;
+    // Trailing characters after the value
+    deserializer
+        .end()
+        .map_err(|error| invalid_body(error, ".".into()))?;
+
+    Ok(value)
+}
+
+/// Describes why the body failed to deserialize, `path` being where in the body it failed
+fn invalid_body(error: serde_json::Error, path: String) -> ApiError {
+    if error.is_syntax() || error.is_eof() {
+        return ApiError::invalid_body("invalid_json", format!("invalid JSON: {error}"), None);
+    }
+
+    let message = error.to_string();
+    // A missing field is reported at the object it is missing from
+    let missing_field = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split_once('`'))
+        .map(|(field, _)| field);
+
+    let field = match (missing_field, path.as_str()) {
+        (Some(field), ".") => Some(field.to_owned()),
+        (Some(field), path) => Some(format!("{path}.{field}")),
+        (None, ".") => None,
+        (None, path) => Some(path.to_owned()),
+    };
+
+    ApiError::invalid_body("invalid_body", message, field)
+}
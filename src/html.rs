@@ -0,0 +1,82 @@
+use axum::http::{header, HeaderMap};
+
+use crate::Todo;
+
+/// Whether the client prefers HTML over JSON, like browsers do when opening a page.
+///
+/// Only explicitly listed types count, so clients accepting anything still get JSON.
+pub(crate) fn prefers_html(headers: &HeaderMap) -> bool {
+    let mut html = 0.0;
+    let mut json = 0.0;
+
+    let media_ranges = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
+
+    for media_range in media_ranges {
+        let mut parts = media_range.split(';').map(str::trim);
+        let media_type = parts.next().unwrap_or_default();
+        let quality = parts
+            .filter_map(|param| param.strip_prefix("q="))
+            .find_map(|quality| quality.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if media_type.eq_ignore_ascii_case("text/html") {
+            html = quality;
+        } else if media_type.eq_ignore_ascii_case("application/json") {
+            json = quality;
+        }
+    }
+
+    html > json
+}
+
+/// Renders a page listing the todos, with a checkbox showing whether each is completed
+pub(crate) fn render_todos(todos: &[Todo]) -> String {
+    let mut page = String::from(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>Todos</title>\n\
+         </head>\n\
+         <body>\n\
+         <h1>Todos</h1>\n",
+    );
+
+    if todos.is_empty() {
+        page.push_str("<p>Nothing to do</p>\n");
+    } else {
+        page.push_str("<ul class=\"todos\">\n");
+        for todo in todos {
+            let checked = if todo.completed { " checked" } else { "" };
+            page.push_str(&format!(
+                "<li id=\"todo-{}\"><label><input type=\"checkbox\" disabled{checked}> {}</label></li>\n",
+                todo.id,
+                escape(&todo.name)
+            ));
+        }
+        page.push_str("</ul>\n");
+    }
+
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+/// Escapes the characters that would otherwise be read as markup
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
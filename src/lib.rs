@@ -1,21 +1,2303 @@
 #![allow(unused)] // Remove me when developing, if you want to
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, path::Path as FilePath, sync::Arc};
 
 use axum::{
-    extract::{Path, State},
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, State},
     http::StatusCode,
-    response::IntoResponse,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Uri},
+    response::{IntoResponse, Response},
     routing::{get, post, put},
     Json, Router,
 };
 
+mod auth;
+mod config;
+mod error;
+mod events;
+mod extract;
+mod html;
+mod metrics;
+mod openapi;
+#[cfg(feature = "sqlite")]
+mod pool;
+mod purge;
+mod rate_limit;
+mod repository;
+#[cfg(feature = "sqlite")]
 mod solutions;
 mod todo;
+#[cfg(unix)]
+mod unix;
+mod ws;
 
-pub use todo::Todo;
+pub use auth::UserId;
+pub use config::{
+    AppConfig, AppConfigBuilder, InvalidTableName, TableName, DEFAULT_BIND_ADDR, DEFAULT_DB_PATH,
+    DEFAULT_MAX_BODY_BYTES, DEFAULT_POOL_SIZE, DEFAULT_PURGE_INTERVAL, DEFAULT_REQUEST_TIMEOUT,
+    DEFAULT_TABLE_NAME, MAX_TABLE_NAME_LENGTH,
+};
+#[cfg(feature = "sqlite")]
+pub use error::AppError;
+use error::{ApiError, ErrorBody};
+use events::TodoAction;
+use extract::{Path, Query, ValidatedJson};
+pub use metrics::install_metrics_recorder;
+#[cfg(feature = "sqlite")]
+pub use pool::ConnectionPool;
+pub use purge::purge_completed;
+pub use rate_limit::DEFAULT_RATE_LIMIT;
+pub use repository::{
+    DeleteError, Filter, ImportSummary, Inserted, MoveTo, OnConflict, Page, ReorderError,
+    RepositoryError, SearchCriteria, SearchResults, Sort, SortField, SortOrder, TodoRepository,
+    Toggled, ToggledMany, UpdateError, Upserted,
+};
+#[cfg(feature = "sqlite")]
+use solutions::db;
+#[cfg(feature = "sqlite")]
+pub use solutions::SqliteRepository;
+pub use todo::{
+    ChangeAction, DayStats, NewTodo, Priority, Recurrence, Todo, TodoChange, TodoCounts, TodoPatch,
+    ValidationError,
+};
+use tokio::sync::{broadcast, mpsc};
+#[cfg(feature = "sqlite")]
 use tokio_rusqlite::Connection;
+use tokio_stream::wrappers::ReceiverStream;
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate, SizeAbove},
+        CompressionLayer,
+    },
+    cors::{AllowOrigin, CorsLayer},
+    limit::RequestBodyLimitLayer,
+    request_id::{MakeRequestId, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
+    timeout::TimeoutLayer,
+    trace::{DefaultOnResponse, TraceLayer},
+};
+use tracing::Level;
+#[cfg(unix)]
+pub use unix::serve_unix;
+
+#[derive(Clone)]
+struct AppState {
+    /// Where the todos are kept
+    repository: Arc<dyn TodoRepository>,
+    /// Publishes changes to the todos to everyone subscribed to `/todos/events`
+    events: broadcast::Sender<events::TodoEvent>,
+    /// The most todos that may be stored, if there is a limit
+    max_todos: Option<u64>,
+}
+
+/// Creates the app, opening the database the config points to
+#[cfg(feature = "sqlite")]
+pub async fn app(config: AppConfig) -> Result<Router, AppError> {
+    let pool = open_database(&config).await?;
+    app_with_pool(pool, config).await
+}
+
+/// Opens a pool of connections to the database the config points to, checking that the file is
+/// an intact SQLite database.
+///
+/// A file that doesn't exist yet is created empty.
+#[cfg(feature = "sqlite")]
+pub async fn open_database(config: &AppConfig) -> Result<ConnectionPool, AppError> {
+    // Every connection to ":memory:" gets its own database, so those can't be pooled
+    let size = if config.db_path == ":memory:" {
+        1
+    } else {
+        config.pool_size
+    };
+    let pool = ConnectionPool::open(&config.db_path, size)
+        .await
+        .map_err(|source| AppError::OpenDatabase {
+            path: config.db_path.clone(),
+            source,
+        })?;
+
+    // SQLite only reads the file once it is queried, so a corrupt file goes unnoticed until now
+    let problems = match db::integrity_check(&pool).await {
+        Ok(problems) => problems.join(", "),
+        Err(error) => error.to_string(),
+    };
+    if !problems.is_empty() {
+        return Err(AppError::CorruptDatabase {
+            path: config.db_path.clone(),
+            problems,
+        });
+    }
+
+    Ok(pool)
+}
+
+/// Creates the app backed by a fresh in-memory database, with the default config.
+///
+/// The database lives as long as the router, and is not shared with any other app.
+#[cfg(feature = "sqlite")]
+pub async fn app_in_memory() -> Router {
+    let connection = Connection::open_in_memory().await.unwrap();
+    app_with_connection(connection).await
+}
+
+/// Creates the app using an already opened connection, with the default config.
+///
+/// Panics if the database can't be set up, use [`app_with_pool`] to handle that instead.
+#[cfg(feature = "sqlite")]
+pub async fn app_with_connection(connection: Connection) -> Router {
+    app_with_pool(ConnectionPool::single(connection), AppConfig::default())
+        .await
+        .expect("setting up the database failed")
+}
+
+/// Loads the todos in the seed file, but only into an empty table so restarts don't undo changes.
+///
+/// The seeded todos don't belong to any user. The app starts either way, a seed file that can't
+/// be loaded is only warned about.
+async fn seed(repository: &dyn TodoRepository, path: &FilePath) {
+    match repository.count_stored_todos().await {
+        Ok(0) => {}
+        Ok(_) => return,
+        Err(error) => {
+            tracing::warn!(%error, "counting todos before seeding failed, skipping the seed file");
+            return;
+        }
+    }
+
+    let json = match std::fs::read(path) {
+        Ok(json) => json,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return,
+        Err(error) => {
+            tracing::warn!(%error, path = %path.display(), "reading the seed file failed");
+            return;
+        }
+    };
+
+    let todos: Vec<Todo> = match serde_json::from_slice(&json) {
+        Ok(todos) => todos,
+        Err(error) => {
+            tracing::warn!(%error, path = %path.display(), "the seed file is not a list of todos");
+            return;
+        }
+    };
+    if let Err(error) = todos.iter().try_for_each(todo::validate_todo) {
+        tracing::warn!(
+            field = %error.field,
+            error = %error.error,
+            path = %path.display(),
+            "the seed file contains an invalid todo"
+        );
+        return;
+    }
+
+    let user = UserId::default();
+    match repository
+        .import_todos(&user, todos, OnConflict::Skip)
+        .await
+    {
+        Ok(summary) => tracing::info!(inserted = summary.inserted, "seeded todos"),
+        Err(error) => tracing::warn!(%error, "storing the seeded todos failed"),
+    }
+}
+
+/// Creates the app using an already opened pool of connections, the database settings of the
+/// config are ignored.
+///
+/// Useful when the caller needs to keep a handle to the pool, e.g. to close it on shutdown.
+#[cfg(feature = "sqlite")]
+pub async fn app_with_pool(pool: ConnectionPool, config: AppConfig) -> Result<Router, AppError> {
+    let table = config.table_name.clone();
+
+    pool.call_each(|connection| Ok(db::configure(connection)?))
+        .await
+        .map_err(AppError::Database)?;
+
+    // Ensure the schema is up to date
+    db::migrate(&pool, &table)
+        .await
+        .map_err(|error| AppError::Database(error.into()))?;
+
+    Ok(app_with_repository(SqliteRepository::new(pool, table), config).await)
+}
+
+/// Creates the app keeping its todos in `repository`, the database settings of the config are
+/// ignored.
+///
+/// This is how todos are kept somewhere other than SQLite, or how the app runs without the
+/// `sqlite` feature at all.
+pub async fn app_with_repository(
+    repository: impl TodoRepository + 'static,
+    config: AppConfig,
+) -> Router {
+    let repository: Arc<dyn TodoRepository> = Arc::new(repository);
+
+    if let Some(seed_path) = config.seed_path() {
+        seed(repository.as_ref(), seed_path).await;
+    }
+
+    if let Some(retention) = config.purge_completed_after() {
+        purge::spawn_purge_task(
+            Arc::downgrade(&repository),
+            retention,
+            config.purge_interval(),
+        );
+    }
+
+    // The /metrics route needs a recorder, this is a no-op if main already installed it
+    install_metrics_recorder();
+
+    let (events, _) = broadcast::channel(events::EVENT_CHANNEL_CAPACITY);
+    let app_state = AppState {
+        repository,
+        events,
+        max_todos: config.max_todos,
+    };
+
+    let router = Router::new()
+        .route("/", get(empty))
+        .route("/health", get(health))
+        .route("/stats", get(stats))
+        .route("/export", get(export))
+        .route(
+            "/todos",
+            get(todos)
+                .post(create_todo)
+                .put(update_todo)
+                .delete(delete_todos),
+        )
+        .route("/todos/count", get(count_todos))
+        .route("/todos/random", get(random_todo))
+        .route("/todos/search", get(search_todos))
+        .route("/todos.csv", get(export_csv))
+        .route("/todos.ndjson", get(export_ndjson))
+        .route("/todos/events", get(events::todo_events))
+        .route("/todos/batch", post(create_todos))
+        .route("/todos/import", post(import_todos))
+        .route("/todos/toggle", post(toggle_many))
+        .route("/todos/toggle-all", post(toggle_all))
+        .route("/todos/reset", post(reset_todos))
+        .route("/todos/replace", post(replace_in_names))
+        .route("/todos/reorder", put(reorder_todos))
+        .route(
+            "/todos/:id",
+            get(get_todo)
+                .head(todo_exists)
+                .put(replace_todo)
+                .patch(patch_todo)
+                .delete(delete_todo),
+        )
+        .route("/todos/:id/restore", post(restore_todo))
+        .route("/todos/:id/move", post(move_todo))
+        .route("/todos/:id/complete", post(complete_todo))
+        .route("/todos/:id/incomplete", post(incomplete_todo))
+        .route("/todos/:id/completed", put(put_completed))
+        .route("/todos/:id/archive", post(archive_todo))
+        .route("/todos/:id/unarchive", post(unarchive_todo))
+        .route("/todos/:id/history", get(todo_history))
+        .route("/todos/:id/subtasks", get(todo_subtasks))
+        .route("/toggle/:id", post(toggle))
+        .route("/ws", get(ws::websocket))
+        // Only the routes above are measured, so scraping doesn't count itself
+        .route_layer(axum::middleware::from_fn(metrics::track_metrics))
+        .route("/metrics", get(metrics::render_metrics))
+        .route("/api-docs/openapi.json", get(openapi::openapi_json))
+        .fallback(error::not_found)
+        .with_state(app_state)
+        .layer(axum::middleware::from_fn(error::method_not_allowed))
+        .layer(axum::middleware::from_fn_with_state(
+            config.api_key.as_deref().map(Arc::from),
+            auth::require_api_key,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            rate_limit::rate_limiter(config.rate_limit),
+            rate_limit::rate_limit,
+        ))
+        // Replace axum's default limit with our own, larger bodies are rejected with 413
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(config.max_body_bytes))
+        // Answers with 408 instead of hanging when a request waits too long, e.g. on a stuck
+        // database connection
+        .layer(TimeoutLayer::new(config.request_timeout))
+        .layer(cors_layer(config.cors_origins.as_deref()))
+        .layer(compression_layer())
+        // Logs the method, path, id, status and latency of every request
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        // Sends the request id back, so the client can find the request in the logs
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID))
+        .layer(SetRequestIdLayer::new(REQUEST_ID, MakeUuidRequestId));
+
+    router
+}
+
+/// Identifies a request across services, generated unless the client sends one
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Gives requests without an id a random UUID
+#[derive(Clone, Copy)]
+struct MakeUuidRequestId;
+
+impl MakeRequestId for MakeUuidRequestId {
+    fn make_request_id<B>(&mut self, _request: &axum::http::Request<B>) -> Option<RequestId> {
+        let id = uuid::Uuid::new_v4().to_string();
+        HeaderValue::from_str(&id).ok().map(RequestId::new)
+    }
+}
+
+/// The span every request is logged in, with the fields of tower-http's default span plus the
+/// request id
+fn request_span(request: &axum::http::Request<Body>) -> tracing::Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id,
+    )
+}
+
+/// Responses smaller than this are sent as they are, compressing them isn't worth it
+const MIN_COMPRESSED_BYTES: u16 = 1024;
+
+/// Compresses responses with gzip or brotli, for clients that send `Accept-Encoding`.
+///
+/// The event stream is left alone so events aren't held back in the compressor's buffer.
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(
+        SizeAbove::new(MIN_COMPRESSED_BYTES)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE),
+    )
+}
+
+/// Allows browsers on other origins to call the API, any origin is allowed if `origins` is unset
+fn cors_layer(origins: Option<&[String]>) -> CorsLayer {
+    let allow_origin = match origins {
+        Some(origins) => AllowOrigin::list(
+            origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        ),
+        None => AllowOrigin::any(),
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            IDEMPOTENCY_KEY,
+            auth::API_KEY,
+            auth::USER_ID,
+            REQUEST_ID,
+        ])
+        .expose_headers([TOTAL_COUNT, REQUEST_ID])
+}
+
+async fn empty() {}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "The database is reachable"),
+        (status = 503, description = "The database is unreachable", body = ErrorBody),
+    )
+)]
+async fn health(
+    State(AppState { repository, .. }): State<AppState>,
+) -> Result<StatusCode, ApiError> {
+    repository
+        .ping()
+        .await
+        .map(|()| StatusCode::OK)
+        .map_err(|_| {
+            ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "database_unavailable",
+                "the database is unreachable",
+            )
+        })
+}
+
+/// The number of todos returned when no limit is given
+const DEFAULT_LIMIT: u32 = 50;
+/// The largest number of todos that can be fetched at once
+const MAX_LIMIT: u32 = 100;
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TodoFilter {
+    completed: Option<bool>,
+    /// Only return todos whose name contains this, ignoring case
+    q: Option<String>,
+    /// Only return incomplete todos whose due date has passed, or the opposite
+    overdue: Option<bool>,
+    /// Only return todos with this tag
+    tag: Option<String>,
+    /// Only return todos assigned to this person
+    assignee: Option<String>,
+    /// Return archived todos instead of the ones that aren't archived
+    #[serde(default)]
+    archived: bool,
+    /// Also return todos that have been deleted
+    #[serde(default)]
+    include_deleted: bool,
+    /// Only return todos that aren't subtasks of another todo, or only subtasks
+    top_level: Option<bool>,
+    /// Only return todos completed after this time, in RFC 3339
+    completed_after: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return the todos with these comma-separated ids, ids that don't exist are left out
+    ids: Option<String>,
+    #[serde(default)]
+    sort: SortField,
+    #[serde(default)]
+    order: SortOrder,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    /// Only return todos with an id above this, ordered by id. Can't be combined with `offset`,
+    /// `sort`, `order` or `envelope`. The `Link` header points to the next page while there are
+    /// more todos
+    after: Option<u32>,
+    /// Wrap the todos in an object along with how many match and which page this is
+    #[serde(default)]
+    envelope: bool,
+    /// Indent the JSON, for reading it in a terminal
+    #[serde(default)]
+    pretty: bool,
+}
+
+/// Like [`Json`], but serialized with indentation so it is easier to read
+struct PrettyJson<T>(T);
+
+impl<T: serde::Serialize> IntoResponse for PrettyJson<T> {
+    fn into_response(self) -> Response {
+        match serde_json::to_string_pretty(&self.0) {
+            Ok(body) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                )],
+                body,
+            )
+                .into_response(),
+            Err(_) => ApiError::internal("serializing the response failed").into_response(),
+        }
+    }
+}
+
+/// Serializes the value as JSON, indented if asked to be pretty
+fn json_response<T: serde::Serialize>(value: T, pretty: bool) -> Response {
+    if pretty {
+        PrettyJson(value).into_response()
+    } else {
+        Json(value).into_response()
+    }
+}
+
+/// A page of todos along with where it sits among all matching todos, returned by
+/// `GET /todos?envelope=true`
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+struct Envelope {
+    data: Vec<Todo>,
+    /// How many todos match, across all pages
+    total: u64,
+    limit: u32,
+    offset: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos",
+    params(TodoFilter),
+    responses(
+        (status = 200, description = "The matching todos, as a page when the client prefers HTML. \
+            With `envelope=true` the todos are wrapped in an `Envelope`, with `pretty=true` \
+            the JSON is indented",
+         content(("application/json" = [Todo]), ("text/html" = String))),
+        (status = 400, description = "The limit is too large, an id is invalid or there are \
+            too many, or `after` was combined with paging or sorting", body = ErrorBody),
+        (status = 500, description = "The todos could not be fetched", body = ErrorBody),
+    )
+)]
+async fn todos(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    Query(filter): Query<TodoFilter>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let ids = filter.ids.as_deref().map(parse_ids).transpose()?;
+    // Every requested todo fits on the page unless a smaller limit is asked for
+    let limit = filter.limit.or(ids.as_ref().map(|ids| ids.len() as u32));
+    let page = page(limit, filter.offset)?;
+
+    if filter.after.is_some() {
+        if filter.offset.is_some() {
+            return Err(ApiError::bad_request(
+                "after_with_offset",
+                "after and offset can't be used together",
+            ));
+        }
+        // Pages continue from the last id, which only works while the todos are ordered by it
+        let by_id = matches!(filter.sort, SortField::Position | SortField::Id)
+            && filter.order == SortOrder::Asc;
+        if !by_id {
+            return Err(ApiError::bad_request(
+                "after_with_sort",
+                "after always orders the todos by id, it can't be used with sort or order",
+            ));
+        }
+        if filter.envelope {
+            return Err(ApiError::bad_request(
+                "after_with_envelope",
+                "after and envelope can't be used together",
+            ));
+        }
+    }
+
+    let db_filter = Filter {
+        user,
+        completed: filter.completed,
+        name_contains: filter.q,
+        overdue: filter.overdue,
+        tag: filter.tag,
+        include_deleted: filter.include_deleted,
+        top_level: filter.top_level,
+        parent_id: None,
+        completed_after: filter.completed_after,
+        due_before: None,
+        due_after: None,
+        assignee: filter.assignee,
+        archived: Some(filter.archived),
+        ids,
+    };
+
+    if let Some(after) = filter.after {
+        return todos_after(
+            repository.as_ref(),
+            db_filter,
+            after,
+            page.limit,
+            &uri,
+            filter.pretty,
+        )
+        .await;
+    }
+
+    let sort = Sort {
+        field: filter.sort,
+        order: filter.order,
+    };
+
+    let fetch_failed = |_| ApiError::internal("fetching todos failed");
+
+    if filter.envelope {
+        let criteria = SearchCriteria {
+            filter: db_filter,
+            sort,
+            page: Some(page),
+        };
+        let results = repository
+            .search_todos(criteria)
+            .await
+            .map_err(fetch_failed)?;
+
+        let envelope = Envelope {
+            data: results.todos,
+            total: results.total,
+            limit: page.limit,
+            offset: page.offset,
+        };
+        return Ok(json_response(envelope, filter.pretty));
+    }
+
+    let todos = repository
+        .get_todos_filtered(db_filter, sort, Some(page))
+        .await
+        .map_err(fetch_failed)?;
+
+    if html::prefers_html(&headers) {
+        return Ok(axum::response::Html(html::render_todos(&todos)).into_response());
+    }
+
+    Ok(json_response(todos, filter.pretty))
+}
+
+/// Parses the comma-separated ids of `GET /todos?ids=`
+fn parse_ids(ids: &str) -> Result<Vec<u32>, ApiError> {
+    let ids = ids
+        .split(',')
+        .map(|id| {
+            id.trim().parse().map_err(|_| {
+                ApiError::bad_request("invalid_ids", format!("{id:?} is not a valid todo id"))
+            })
+        })
+        .collect::<Result<Vec<u32>, _>>()?;
+
+    if ids.len() > MAX_LIMIT as usize {
+        return Err(ApiError::bad_request(
+            "too_many_ids",
+            format!("at most {MAX_LIMIT} ids can be fetched at once"),
+        ));
+    }
+
+    Ok(ids)
+}
+
+/// Returns the todos matching the filter with an id above `after`, linking to the next page if
+/// there is one
+async fn todos_after(
+    repository: &dyn TodoRepository,
+    filter: Filter,
+    after: u32,
+    limit: u32,
+    uri: &Uri,
+    pretty: bool,
+) -> Result<Response, ApiError> {
+    // Fetching one extra todo tells whether another page follows without a second query
+    let mut todos = repository
+        .get_todos_after(filter, Some(after), limit.saturating_add(1))
+        .await
+        .map_err(|_| ApiError::internal("fetching todos failed"))?;
+
+    let next = if todos.len() > limit as usize {
+        todos.truncate(limit as usize);
+        todos.last().map(|last| last.id)
+    } else {
+        None
+    };
+
+    let mut response = json_response(todos, pretty);
+    if let Some(next) = next {
+        // The next page keeps the filters of this one
+        let mut query: String = uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| {
+                !pair.is_empty() && !pair.starts_with("after=") && !pair.starts_with("limit=")
+            })
+            .map(|pair| format!("{pair}&"))
+            .collect();
+        query.push_str(&format!("after={next}&limit={limit}"));
+        let link = format!("</todos?{query}>; rel=\"next\"");
+        response.headers_mut().insert(
+            header::LINK,
+            HeaderValue::from_str(&link).expect("the link is always a valid header"),
+        );
+    }
+    Ok(response)
+}
+
+/// Picks the page of todos to return, defaulting to the first [`DEFAULT_LIMIT`] todos
+fn page(limit: Option<u32>, offset: Option<u32>) -> Result<Page, ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if limit > MAX_LIMIT {
+        return Err(ApiError::bad_request(
+            "limit_too_large",
+            format!("limit must be at most {MAX_LIMIT}"),
+        ));
+    }
+
+    Ok(Page {
+        limit,
+        offset: offset.unwrap_or(0),
+    })
+}
+
+/// The number of todos matching a search, across all pages
+const TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchQuery {
+    /// Only return todos whose name contains this, ignoring case
+    q: Option<String>,
+    completed: Option<bool>,
+    /// Only return todos with this tag
+    tag: Option<String>,
+    /// Only return todos due before this time, in RFC 3339
+    due_before: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return todos due after this time, in RFC 3339
+    due_after: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    sort: SortField,
+    #[serde(default)]
+    order: SortOrder,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "A page of the matching todos", body = [Todo],
+            headers(("x-total-count" = u64, description = "How many todos match across all pages"))),
+        (status = 400, description = "The limit is too large", body = ErrorBody),
+        (status = 500, description = "The todos could not be searched", body = ErrorBody),
+    )
+)]
+async fn search_todos(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    Query(query): Query<SearchQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let criteria = SearchCriteria {
+        filter: Filter {
+            user,
+            completed: query.completed,
+            name_contains: query.q,
+            tag: query.tag,
+            due_before: query.due_before,
+            due_after: query.due_after,
+            ..Filter::default()
+        },
+        sort: Sort {
+            field: query.sort,
+            order: query.order,
+        },
+        page: Some(page(query.limit, query.offset)?),
+    };
+
+    let results = repository
+        .search_todos(criteria)
+        .await
+        .map_err(|_| ApiError::internal("searching todos failed"))?;
+
+    Ok((
+        [(TOTAL_COUNT, results.total.to_string())],
+        Json(results.todos),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/count",
+    responses(
+        (status = 200, description = "The number of todos", body = TodoCounts),
+        (status = 500, description = "The todos could not be counted", body = ErrorBody),
+    )
+)]
+async fn count_todos(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+) -> Result<Json<TodoCounts>, ApiError> {
+    repository
+        .count_todos(&user)
+        .await
+        .map(Json)
+        .map_err(|_| ApiError::internal("counting todos failed"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats",
+    responses(
+        (status = 200, description = "How many todos were created and completed on each day, \
+            keyed by the date in UTC. Days without either are left out",
+            body = BTreeMap<String, DayStats>),
+        (status = 500, description = "The statistics could not be gathered", body = ErrorBody),
+    )
+)]
+async fn stats(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+) -> Result<Json<BTreeMap<String, DayStats>>, ApiError> {
+    repository
+        .daily_stats(&user)
+        .await
+        .map(Json)
+        .map_err(|_| ApiError::internal("gathering statistics failed"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/random",
+    responses(
+        (status = 200, description = "One of the incomplete todos, picked at random", body = Todo),
+        (status = 404, description = "Every todo is completed", body = ErrorBody),
+        (status = 500, description = "A todo could not be picked", body = ErrorBody),
+    )
+)]
+async fn random_todo(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+) -> Result<Json<Todo>, ApiError> {
+    repository
+        .random_pending(&user)
+        .await
+        .map_err(|_| ApiError::internal("picking a todo failed"))?
+        .map(Json)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "no_pending_todos",
+                "there are no incomplete todos",
+            )
+        })
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos.csv",
+    responses(
+        (status = 200, description = "Every todo as CSV, with the columns id, name and completed",
+            body = String, content_type = "text/csv"),
+        (status = 500, description = "The todos could not be fetched", body = ErrorBody),
+    )
+)]
+async fn export_csv(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+) -> Result<impl IntoResponse, ApiError> {
+    let export_failed = || ApiError::internal("exporting todos failed");
+
+    let todos = repository
+        .get_todos(&user)
+        .await
+        .map_err(|_| export_failed())?;
+    let csv = todos_csv(todos).map_err(|_| export_failed())?;
+
+    Ok(([(header::CONTENT_TYPE, "text/csv; charset=utf-8")], csv))
+}
+
+/// Writes todos as CSV, the csv crate takes care of quoting names with commas, quotes or newlines
+fn todos_csv(todos: Vec<Todo>) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    writer.write_record(["id", "name", "completed"])?;
+    for todo in todos {
+        writer.write_record([todo.id.to_string(), todo.name, todo.completed.to_string()])?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|error| error.into_error().into())
+}
+
+/// How many todos the NDJSON export fetches from the database at a time
+const NDJSON_BATCH_SIZE: u32 = 100;
+
+#[utoipa::path(
+    get,
+    path = "/todos.ndjson",
+    responses(
+        (status = 200, description = "Every todo as a JSON object on its own line, ordered by id",
+            body = String, content_type = "application/x-ndjson"),
+    )
+)]
+async fn export_ndjson(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+) -> impl IntoResponse {
+    // At most one batch waits to be sent, so a slow client holds back reading the next one
+    // rather than letting the whole table pile up in memory
+    let (lines, receiver) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        let mut after = None;
+        loop {
+            let filter = Filter {
+                user: user.clone(),
+                ..Filter::default()
+            };
+            let batch = repository
+                .get_todos_after(filter, after, NDJSON_BATCH_SIZE)
+                .await;
+            let todos = match batch {
+                Ok(todos) => todos,
+                Err(error) => {
+                    tracing::warn!(%error, "exporting todos as NDJSON failed");
+                    // Ending the body with an error lets the client tell it apart from a full export
+                    let _ = lines.send(Err(error)).await;
+                    return;
+                }
+            };
+            let Some(last) = todos.last() else {
+                return;
+            };
+            after = Some(last.id);
+
+            let mut batch = String::new();
+            for todo in &todos {
+                batch.push_str(&serde_json::to_string(todo).expect("todos always serialize"));
+                batch.push('\n');
+            }
+            if lines.send(Ok(batch)).await.is_err() {
+                // The client went away
+                return;
+            }
+        }
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(ReceiverStream::new(receiver)),
+    )
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ClearFilter {
+    /// Must be `true`, to avoid deleting every todo by accident
+    completed: Option<bool>,
+    /// List the todos that would be deleted, without deleting them
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// The todos to delete with `DELETE /todos`
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+struct DeleteIds {
+    ids: Vec<u32>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+struct Deleted {
+    deleted: usize,
+}
+
+/// The todos a dry run found, which are left as they are
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+struct WouldDelete {
+    would_delete: usize,
+    todos: Vec<Todo>,
+}
+
+impl WouldDelete {
+    fn new(todos: Vec<Todo>) -> Self {
+        Self {
+            would_delete: todos.len(),
+            todos,
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/todos",
+    params(ClearFilter),
+    request_body(content = Option<DeleteIds>,
+        description = "The todos to delete, instead of the completed ones. Ids that don't exist \
+            aren't counted"),
+    responses(
+        (status = 200, description = "The todos were deleted, a dry run answers with a \
+            `WouldDelete` listing them instead", body = Deleted),
+        (status = 400, description = "Neither `completed=true` nor a list of ids was given, \
+            or both were", body = ErrorBody),
+        (status = 422, description = "The body is not a list of ids", body = ErrorBody),
+        (status = 500, description = "The todos could not be deleted", body = ErrorBody),
+    )
+)]
+async fn delete_todos(
+    State(state): State<AppState>,
+    user: UserId,
+    Query(filter): Query<ClearFilter>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let repository = &state.repository;
+    if !body.is_empty() {
+        if filter.completed.is_some() {
+            return Err(ApiError::bad_request(
+                "ids_with_filter",
+                "either list the ids to delete or pass completed=true, not both",
+            ));
+        }
+
+        let DeleteIds { ids } = extract::from_body(&headers, &body)?;
+        let deleted = repository
+            .delete_many(&user, ids, filter.dry_run)
+            .await
+            .map_err(|_| ApiError::internal("deleting the todos failed"))?;
+        if filter.dry_run {
+            return Ok(Json(WouldDelete::new(deleted)).into_response());
+        }
+
+        let count = deleted.len();
+        for todo in deleted {
+            state.publish(TodoAction::Deleted, todo);
+        }
+        return Ok(Json(Deleted { deleted: count }).into_response());
+    }
+
+    // Refuse to delete everything unless explicitly asked to clear completed todos
+    if filter.completed != Some(true) {
+        return Err(ApiError::bad_request(
+            "missing_filter",
+            "only completed todos can be cleared, pass completed=true",
+        ));
+    }
+
+    let deleted = repository
+        .delete_completed(&user, filter.dry_run)
+        .await
+        .map_err(|_| ApiError::internal("clearing completed todos failed"))?;
+    if filter.dry_run {
+        return Ok(Json(WouldDelete::new(deleted)).into_response());
+    }
+
+    let count = deleted.len();
+    for todo in deleted {
+        state.publish(TodoAction::Deleted, todo);
+    }
+    Ok(Json(Deleted { deleted: count }).into_response())
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+struct ToggleAll {
+    completed: bool,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+struct Updated {
+    updated: usize,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/toggle-all",
+    request_body = ToggleAll,
+    responses(
+        (status = 200, description = "Every todo was updated", body = Updated),
+        (status = 500, description = "The todos could not be updated", body = ErrorBody),
+    )
+)]
+async fn toggle_all(
+    State(state): State<AppState>,
+    user: UserId,
+    ValidatedJson(ToggleAll { completed }): ValidatedJson<ToggleAll>,
+) -> Result<Json<Updated>, ApiError> {
+    let updated = state
+        .repository
+        .set_all_completed(&user, completed)
+        .await
+        .map_err(|_| ApiError::internal("updating the todos failed"))?;
+
+    let count = updated.len();
+    for todo in updated {
+        state.publish(TodoAction::Toggled, todo);
+    }
+    Ok(Json(Updated { updated: count }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/reset",
+    responses(
+        (status = 200, description = "Every todo, none of them completed anymore", body = [Todo]),
+        (status = 500, description = "The todos could not be updated or fetched", body = ErrorBody),
+    )
+)]
+async fn reset_todos(
+    State(state): State<AppState>,
+    user: UserId,
+) -> Result<Json<Vec<Todo>>, ApiError> {
+    let reset = state
+        .repository
+        .set_all_completed(&user, false)
+        .await
+        .map_err(|_| ApiError::internal("resetting the todos failed"))?;
+    for todo in reset {
+        state.publish(TodoAction::Toggled, todo);
+    }
+
+    state
+        .repository
+        .get_todos(&user)
+        .await
+        .map(Json)
+        .map_err(|_| ApiError::internal("fetching todos failed"))
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+struct FindReplace {
+    /// The text to look for in the names, must not be empty
+    find: String,
+    /// What to put in its place
+    replace: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/replace",
+    request_body = FindReplace,
+    responses(
+        (status = 200, description = "How many todos were renamed, names that would become blank are left alone", body = Updated),
+        (status = 400, description = "The text to find is empty", body = ErrorBody),
+        (status = 422, description = "A name would become too long", body = ErrorBody),
+    )
+)]
+async fn replace_in_names(
+    State(state): State<AppState>,
+    user: UserId,
+    ValidatedJson(FindReplace { find, replace }): ValidatedJson<FindReplace>,
+) -> Result<Json<Updated>, ApiError> {
+    if find.is_empty() {
+        return Err(ApiError::bad_request(
+            "empty_find",
+            "the text to find must not be empty",
+        ));
+    }
+
+    let renamed = state
+        .repository
+        .replace_in_names(&user, find, replace)
+        .await
+        .map_err(|error| match error {
+            RepositoryError::NameTooLong => ValidationError {
+                field: "name".into(),
+                error: error.to_string(),
+            }
+            .into(),
+            _ => ApiError::internal("replacing in the names failed"),
+        })?;
+
+    let updated = renamed.len();
+    for todo in renamed {
+        state.publish(TodoAction::Updated, todo);
+    }
+    Ok(Json(Updated { updated }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/todos/reorder",
+    request_body(content = Vec<u32>,
+        description = "The ids of the todos in their new order, todos left out keep their place"),
+    responses(
+        (status = 204, description = "The todos were reordered"),
+        (status = 404, description = "One of the todos does not exist", body = ErrorBody),
+        (status = 422, description = "An id is listed more than once", body = ErrorBody),
+    )
+)]
+async fn reorder_todos(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    ValidatedJson(ids): ValidatedJson<Vec<u32>>,
+) -> Result<StatusCode, ApiError> {
+    let mut seen = std::collections::HashSet::new();
+    if let Some(id) = ids.iter().find(|id| !seen.insert(**id)) {
+        return Err(ValidationError {
+            field: "ids".into(),
+            error: format!("todo {id} is listed more than once"),
+        }
+        .into());
+    }
+
+    match repository.reorder_todos(&user, ids).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(ReorderError::NotFound(id)) => Err(ApiError::todo_not_found(id)),
+        Err(ReorderError::Repository(_)) => Err(ApiError::internal("reordering todos failed")),
+    }
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MoveQuery {
+    /// Whether to move the todo to the top or the bottom of the list
+    to: MoveTo,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/move",
+    params(("id" = u32, Path, description = "The id of the todo"), MoveQuery),
+    responses(
+        (status = 204, description = "The todo was moved"),
+        (status = 400, description = "Where to move the todo is missing or unknown"),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+    )
+)]
+async fn move_todo(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+    Query(query): Query<MoveQuery>,
+) -> Result<StatusCode, ApiError> {
+    match repository.move_todo(&user, id, query.to).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::todo_not_found(id)),
+        Err(_) => Err(ApiError::internal("moving the todo failed")),
+    }
+}
+
+/// Lets clients safely retry creating a todo, requests with a key that was seen before
+/// return the todo created by the first request
+const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// The body of `POST /todos`, either a single todo or a list of todos
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+#[serde(untagged)]
+enum NewTodos {
+    One(NewTodo),
+    Many(Vec<NewTodo>),
+}
+
+impl NewTodos {
+    /// Picks the kind of body by its shape before deserializing it, so errors name the field at
+    /// fault instead of saying that the body is neither kind
+    fn from_json(body: serde_json::Value) -> Result<Self, ApiError> {
+        if body.is_array() {
+            extract::from_value(body).map(NewTodos::Many)
+        } else {
+            extract::from_value(body).map(NewTodos::One)
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CreateOptions {
+    /// Refuse to create the todo if there already is one with the same name, ignoring case
+    #[serde(default)]
+    unique: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos",
+    params(
+        ("Idempotency-Key" = Option<String>, Header,
+            description = "Makes retries return the todo created by the first request, \
+                only supported when creating a single todo"),
+        CreateOptions,
+    ),
+    request_body = NewTodos,
+    responses(
+        (status = 201, description = "The created todos, a list of one if a single todo was sent",
+            body = [Todo],
+            headers(("Location" = String,
+                description = "The path of the created todo, only when a single todo was sent"))),
+        (status = 400, description = "An idempotency key or `unique` was sent with a list",
+            body = ErrorBody),
+        (status = 409, description = "A todo with the same id already exists, or with the same \
+            name when `unique` is set", body = ErrorBody),
+        (status = 422, description = "A todo is invalid", body = ErrorBody),
+        (status = 507, description = "There would be more todos than allowed", body = ErrorBody),
+    )
+)]
+async fn create_todo(
+    State(state): State<AppState>,
+    user: UserId,
+    Query(CreateOptions { unique }): Query<CreateOptions>,
+    headers: HeaderMap,
+    ValidatedJson(body): ValidatedJson<serde_json::Value>,
+) -> Result<Response, ApiError> {
+    let todos = NewTodos::from_json(body)?;
+    let idempotency_key = headers
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|key| key.to_str().ok())
+        .map(str::to_owned);
+
+    let todo = match todos {
+        NewTodos::One(todo) => todo,
+        NewTodos::Many(todos) => {
+            if idempotency_key.is_some() {
+                return Err(ApiError::bad_request(
+                    "idempotency_key_with_list",
+                    "idempotency keys are only supported when creating a single todo",
+                ));
+            }
+            if unique {
+                return Err(ApiError::bad_request(
+                    "unique_with_list",
+                    "unique names are only supported when creating a single todo",
+                ));
+            }
+
+            let created = create_many(&state, &user, todos).await?;
+            return Ok((StatusCode::CREATED, Json(created)).into_response());
+        }
+    };
+
+    todo::validate_name(&todo.name)?;
+
+    // A retry of a create that went through is answered before the capacity check, which the
+    // todo it created may be what fills up the list
+    let replayed = match &idempotency_key {
+        Some(key) => state
+            .repository
+            .idempotent_todo_id(&user, key)
+            .await
+            .map_err(|_| ApiError::internal("looking up the idempotency key failed"))?,
+        None => None,
+    };
+    let inserted = match replayed {
+        Some(id) => Inserted { id, replayed: true },
+        None => {
+            ensure_room(&state, 1).await?;
+            state
+                .repository
+                .insert_todo(&user, todo, idempotency_key, unique)
+                .await
+                .map_err(insert_error)?
+        }
+    };
+    let id = inserted.id;
+
+    // Read the todo back so the response reflects exactly what was persisted
+    let created = state
+        .repository
+        .get_todo(&user, id)
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| ApiError::internal("reading the created todo failed"))?;
+
+    if !inserted.replayed {
+        state.publish(TodoAction::Created, created.clone());
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/todos/{id}"))],
+        Json([created]),
+    )
+        .into_response())
+}
+
+/// Creates all todos in one transaction, returning them as they were stored
+async fn create_many(
+    state: &AppState,
+    user: &UserId,
+    todos: Vec<NewTodo>,
+) -> Result<Vec<Todo>, ApiError> {
+    for todo in &todos {
+        todo::validate_name(&todo.name)?;
+    }
+    ensure_room(state, todos.len()).await?;
+
+    let ids = state
+        .repository
+        .insert_todos(user, todos)
+        .await
+        .map_err(insert_error)?;
+
+    let mut created = Vec::with_capacity(ids.len());
+    for id in ids {
+        let todo = state
+            .repository
+            .get_todo(user, id)
+            .await
+            .ok()
+            .flatten()
+            .ok_or_else(|| ApiError::internal("reading the created todos failed"))?;
+
+        state.publish(TodoAction::Created, todo.clone());
+        created.push(todo);
+    }
+
+    Ok(created)
+}
+
+/// Refuses to create `adding` more todos if that would take the count past the configured limit.
+///
+/// The limit is on the todos of all users together. The count is read before inserting, so
+/// concurrent requests may overshoot the limit slightly.
+async fn ensure_room(state: &AppState, adding: usize) -> Result<(), ApiError> {
+    let Some(max_todos) = state.max_todos else {
+        return Ok(());
+    };
+
+    let total = state
+        .repository
+        .count_stored_todos()
+        .await
+        .map_err(|_| ApiError::internal("counting todos failed"))?;
+
+    if total + adding as u64 > max_todos {
+        return Err(ApiError::new(
+            StatusCode::INSUFFICIENT_STORAGE,
+            "too_many_todos",
+            format!("there can be at most {max_todos} todos"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Maps a failed insert to 409 if the todo or its name already exists, 422 if its parent doesn't
+/// or its name is too long, or 500 otherwise
+fn insert_error(error: RepositoryError) -> ApiError {
+    match error {
+        RepositoryError::Conflict => ApiError::new(
+            StatusCode::CONFLICT,
+            "todo_exists",
+            "a todo with that id already exists",
+        ),
+        RepositoryError::DuplicateName => ApiError::new(
+            StatusCode::CONFLICT,
+            "duplicate_name",
+            "a todo with that name already exists",
+        ),
+        RepositoryError::InvalidParent => ValidationError {
+            field: "parent_id".into(),
+            error: "the parent todo does not exist".into(),
+        }
+        .into(),
+        RepositoryError::NameTooLong => ValidationError {
+            field: "name".into(),
+            error: error.to_string(),
+        }
+        .into(),
+        RepositoryError::Other(_) => ApiError::internal("creating the todo failed"),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/batch",
+    request_body = [NewTodo],
+    responses(
+        (status = 201, description = "The ids of the created todos", body = [u32]),
+        (status = 409, description = "A todo with the same id already exists", body = ErrorBody),
+        (status = 422, description = "A todo is invalid", body = ErrorBody),
+        (status = 500, description = "The todos could not be created or read back",
+            body = ErrorBody),
+        (status = 507, description = "There would be more todos than allowed", body = ErrorBody),
+    )
+)]
+async fn create_todos(
+    State(state): State<AppState>,
+    user: UserId,
+    ValidatedJson(todos): ValidatedJson<Vec<NewTodo>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let created = create_many(&state, &user, todos).await?;
+    let ids: Vec<u32> = created.iter().map(|todo| todo.id).collect();
+
+    Ok((StatusCode::CREATED, Json(ids)))
+}
+
+/// The version of the [`Export`] format, bumped when it changes in a way older servers can't
+/// import
+const EXPORT_VERSION: u32 = 1;
+
+/// A backup of every todo, including deleted and archived ones, returned by `GET /export`.
+///
+/// Posting it to `/todos/import` brings the todos back.
+#[derive(Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+struct Export {
+    /// The version of this format
+    version: u32,
+    exported_at: chrono::DateTime<chrono::Utc>,
+    todos: Vec<Todo>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/export",
+    responses(
+        (status = 200, description = "Every todo, as a backup that can be imported again",
+            body = Export),
+        (status = 500, description = "The todos could not be fetched", body = ErrorBody),
+    )
+)]
+async fn export(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+) -> Result<Json<Export>, ApiError> {
+    let filter = Filter {
+        user,
+        include_deleted: true,
+        ..Filter::default()
+    };
+    // By id, so parents are imported before their subtasks
+    let sort = Sort {
+        field: SortField::Id,
+        order: SortOrder::Asc,
+    };
+
+    let todos = repository
+        .get_todos_filtered(filter, sort, None)
+        .await
+        .map_err(|_| ApiError::internal("exporting todos failed"))?;
+
+    Ok(Json(Export {
+        version: EXPORT_VERSION,
+        exported_at: chrono::Utc::now(),
+        todos,
+    }))
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ImportOptions {
+    /// Whether todos with an id that already exists are skipped or replace the existing todo
+    #[serde(default)]
+    on_conflict: OnConflict,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/import",
+    params(ImportOptions),
+    request_body(content = Export,
+        description = "A backup from `GET /export`, or just the array of todos to import"),
+    responses(
+        (status = 200, description = "The todos were imported", body = ImportSummary),
+        (status = 422, description = "A todo is invalid", body = ErrorBody),
+        (status = 500, description = "The todos could not be imported", body = ErrorBody),
+    )
+)]
+async fn import_todos(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    Query(ImportOptions { on_conflict }): Query<ImportOptions>,
+    ValidatedJson(body): ValidatedJson<serde_json::Value>,
+) -> Result<Json<ImportSummary>, ApiError> {
+    let todos: Vec<Todo> = if body.is_object() {
+        let export: Export = extract::from_value(body)?;
+        if export.version != EXPORT_VERSION {
+            return Err(ApiError::bad_request(
+                "unsupported_export_version",
+                format!(
+                    "exports of version {} can't be imported, only version {EXPORT_VERSION}",
+                    export.version
+                ),
+            ));
+        }
+        export.todos
+    } else {
+        extract::from_value(body)?
+    };
+
+    for todo in &todos {
+        todo::validate_todo(todo)?;
+    }
+
+    repository
+        .import_todos(&user, todos, on_conflict)
+        .await
+        .map(Json)
+        .map_err(|_| ApiError::internal("importing todos failed"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/{id}",
+    params(
+        ("id" = u32, Path, description = "The id of the todo"),
+        ("If-None-Match" = Option<String>, Header,
+            description = "ETags of versions the client already has"),
+    ),
+    responses(
+        (status = 200, description = "The todo", body = Todo,
+            headers(("ETag" = String, description = "Identifies this version of the todo"))),
+        (status = 304, description = "The todo matches one of the given ETags"),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+        (status = 500, description = "The todo could not be fetched", body = ErrorBody),
+    )
+)]
+async fn get_todo(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let todo = repository
+        .get_todo(&user, id)
+        .await
+        .map_err(|_| ApiError::internal("fetching the todo failed"))?
+        .ok_or(ApiError::todo_not_found(id))?;
+
+    let etag = etag(&todo);
+    let etag_header = [(header::ETAG, etag.clone())];
+
+    if if_none_match(&headers, &etag) {
+        Ok((StatusCode::NOT_MODIFIED, etag_header).into_response())
+    } else {
+        Ok((etag_header, Json(todo)).into_response())
+    }
+}
+
+#[utoipa::path(
+    head,
+    path = "/todos/{id}",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    responses(
+        (status = 200, description = "The todo exists"),
+        (status = 404, description = "The todo does not exist"),
+        (status = 500, description = "Whether the todo exists could not be checked"),
+    )
+)]
+async fn todo_exists(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+) -> StatusCode {
+    match repository.todo_exists(&user, id).await {
+        Ok(true) => StatusCode::OK,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// A strong ETag for the current content of a todo.
+///
+/// Uses FNV-1a rather than std's hasher, whose output may change between Rust versions.
+fn etag(todo: &Todo) -> String {
+    let json = serde_json::to_vec(todo).expect("todos always serialize");
+
+    let hash = json.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+
+    format!("\"{hash:016x}\"")
+}
+
+/// Whether the `If-None-Match` header matches `etag`, meaning the client's copy is up to date
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        // GET requests use weak comparison, so a weak ETag matches too
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[utoipa::path(
+    post,
+    path = "/toggle/{id}",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    responses(
+        (status = 200, description = "The todo was toggled", body = Completion),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+        (status = 500, description = "The todo could not be toggled", body = ErrorBody),
+    )
+)]
+async fn toggle(
+    State(state): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+) -> Result<Json<Completion>, ApiError> {
+    toggle_todo(&state, &user, id)
+        .await
+        .map_err(|_| ApiError::internal("toggling the todo failed"))?
+        .map(|completed| Json(Completion { completed }))
+        .ok_or(ApiError::todo_not_found(id))
+}
+
+/// Whether a todo is completed, returned by toggling it and sent to set it with
+/// `PUT /todos/{id}/completed`
+#[derive(Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+struct Completion {
+    completed: bool,
+}
+
+/// The todos to toggle with `POST /todos/toggle`
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+struct ToggleIds {
+    ids: Vec<u32>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+struct ToggledTodos {
+    /// The toggled todos, in the order they were asked for
+    todos: Vec<Todo>,
+    /// The ids of the todos that don't exist and were skipped
+    missing: Vec<u32>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/toggle",
+    request_body = ToggleIds,
+    responses(
+        (status = 200, description = "The todos were toggled", body = ToggledTodos),
+        (status = 422, description = "An id is listed more than once", body = ErrorBody),
+    )
+)]
+async fn toggle_many(
+    State(state): State<AppState>,
+    user: UserId,
+    ValidatedJson(ToggleIds { ids }): ValidatedJson<ToggleIds>,
+) -> Result<Json<ToggledTodos>, ApiError> {
+    // Toggling a todo twice would quietly undo the first toggle
+    let mut seen = std::collections::HashSet::new();
+    if let Some(id) = ids.iter().find(|id| !seen.insert(**id)) {
+        return Err(ValidationError {
+            field: "ids".into(),
+            error: format!("todo {id} is listed more than once"),
+        }
+        .into());
+    }
+
+    let toggled = state
+        .repository
+        .toggle_many(&user, ids)
+        .await
+        .map_err(|_| ApiError::internal("toggling the todos failed"))?;
+
+    let mut todos = Vec::with_capacity(toggled.todos.len());
+    for (todo, recurrence) in toggled.todos {
+        state.publish(TodoAction::Toggled, todo.clone());
+
+        if let Some(recurrence) = recurrence.filter(|_| todo.completed) {
+            create_next_occurrence(&state, &todo, recurrence).await;
+        }
+        todos.push(todo);
+    }
+
+    Ok(Json(ToggledTodos {
+        todos,
+        missing: toggled.missing,
+    }))
+}
+
+/// Flips whether a todo is completed and tells subscribers, returning whether it is completed now
+/// or `None` if there is no such todo
+async fn toggle_todo(
+    state: &AppState,
+    user: &UserId,
+    id: u32,
+) -> Result<Option<bool>, RepositoryError> {
+    let Some(toggled) = state.repository.toggle_todo(user, id).await? else {
+        return Ok(None);
+    };
+
+    // Fetch the todo for the completion time set by the database, unless it's gone already
+    if let Some(todo) = fetch_changed(state, user, id).await {
+        state.publish(TodoAction::Toggled, todo.clone());
+
+        if let Some(recurrence) = toggled.recurrence.filter(|_| toggled.completed) {
+            create_next_occurrence(state, &todo, recurrence).await;
+        }
+    }
+
+    Ok(Some(toggled.completed))
+}
+
+/// Fetches a todo that was just changed, to tell subscribers about it.
+///
+/// The change has already been made, so failing to fetch the todo is only logged.
+async fn fetch_changed(state: &AppState, user: &UserId, id: u32) -> Option<Todo> {
+    match state.repository.get_todo(user, id).await {
+        Ok(todo) => todo,
+        Err(error) => {
+            tracing::warn!("fetching todo {id} after changing it failed: {error}");
+            None
+        }
+    }
+}
+
+/// Creates a pending copy of a completed recurring todo, due one recurrence after it was.
+///
+/// Todos without a due date are due one recurrence after they were completed, and the copy
+/// belongs to the same user. The toggle has already happened, so failing to create the copy is
+/// only logged.
+async fn create_next_occurrence(state: &AppState, completed: &Todo, recurrence: Recurrence) {
+    let user = UserId::of(completed);
+    let due_date = completed
+        .due_date
+        .or(completed.completed_at)
+        .unwrap_or_else(chrono::Utc::now);
+
+    let next = NewTodo {
+        id: None,
+        name: completed.name.clone(),
+        completed: false,
+        due_date: Some(recurrence.next_due_date(due_date)),
+        priority: completed.priority,
+        tags: completed.tags.clone(),
+        parent_id: completed.parent_id,
+        recurrence: Some(recurrence),
+        assignee: completed.assignee.clone(),
+        description: completed.description.clone(),
+    };
+
+    let created = match state.repository.insert_todo(&user, next, None, false).await {
+        Ok(inserted) => fetch_changed(state, &user, inserted.id).await,
+        Err(error) => {
+            tracing::warn!(
+                "creating the next occurrence of todo {} failed: {error}",
+                completed.id
+            );
+            return;
+        }
+    };
+
+    if let Some(created) = created {
+        state.publish(TodoAction::Created, created);
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/complete",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    responses(
+        (status = 200, description = "The todo is completed", body = Todo),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+    )
+)]
+async fn complete_todo(
+    State(state): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+) -> Result<Json<Todo>, ApiError> {
+    set_completed(&state, &user, id, true).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/incomplete",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    responses(
+        (status = 200, description = "The todo is not completed", body = Todo),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+    )
+)]
+async fn incomplete_todo(
+    State(state): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+) -> Result<Json<Todo>, ApiError> {
+    set_completed(&state, &user, id, false).await.map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/todos/{id}/completed",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    request_body = Completion,
+    responses(
+        (status = 200, description = "The todo with its new completion status", body = Todo),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+    )
+)]
+async fn put_completed(
+    State(state): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+    ValidatedJson(Completion { completed }): ValidatedJson<Completion>,
+) -> Result<Json<Todo>, ApiError> {
+    set_completed(&state, &user, id, completed).await.map(Json)
+}
+
+/// Sets whether a todo is completed, unlike toggling this gives the same result when repeated.
+///
+/// Subscribers are only told when the todo actually changed.
+async fn set_completed(
+    state: &AppState,
+    user: &UserId,
+    id: u32,
+    completed: bool,
+) -> Result<Todo, ApiError> {
+    let previous = state
+        .repository
+        .set_completed(user, id, completed)
+        .await
+        .map_err(|_| ApiError::internal("updating the todo failed"))?
+        .ok_or(ApiError::todo_not_found(id))?;
+
+    let todo = state
+        .repository
+        .get_todo(user, id)
+        .await
+        .map_err(|_| ApiError::internal("fetching the updated todo failed"))?
+        .ok_or(ApiError::todo_not_found(id))?;
+
+    if previous.completed != completed {
+        state.publish(TodoAction::Toggled, todo.clone());
+
+        if let Some(recurrence) = previous.recurrence.filter(|_| completed) {
+            create_next_occurrence(state, &todo, recurrence).await;
+        }
+    }
+
+    Ok(todo)
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/archive",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    responses(
+        (status = 200, description = "The todo is archived", body = Todo),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+    )
+)]
+async fn archive_todo(
+    State(state): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+) -> Result<Json<Todo>, ApiError> {
+    set_archived(&state, &user, id, true).await.map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/unarchive",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    responses(
+        (status = 200, description = "The todo is not archived", body = Todo),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+    )
+)]
+async fn unarchive_todo(
+    State(state): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+) -> Result<Json<Todo>, ApiError> {
+    set_archived(&state, &user, id, false).await.map(Json)
+}
+
+/// Sets whether a todo is archived, subscribers are only told when the todo actually changed
+async fn set_archived(
+    state: &AppState,
+    user: &UserId,
+    id: u32,
+    archived: bool,
+) -> Result<Todo, ApiError> {
+    let previous = state
+        .repository
+        .set_archived(user, id, archived)
+        .await
+        .map_err(|_| ApiError::internal("updating the todo failed"))?
+        .ok_or(ApiError::todo_not_found(id))?;
+
+    let todo = state
+        .repository
+        .get_todo(user, id)
+        .await
+        .map_err(|_| ApiError::internal("fetching the updated todo failed"))?
+        .ok_or(ApiError::todo_not_found(id))?;
+
+    if previous.archived != archived {
+        state.publish(TodoAction::Updated, todo.clone());
+    }
+
+    Ok(todo)
+}
+
+#[utoipa::path(
+    put,
+    path = "/todos",
+    request_body = Todo,
+    responses(
+        (status = 204, description = "The todo was updated"),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+        (status = 409, description = "The todo was changed since the given version", body = ErrorBody),
+        (status = 422, description = "The todo is invalid", body = ErrorBody),
+        (status = 500, description = "The todo could not be updated", body = ErrorBody),
+    )
+)]
+async fn update_todo(
+    State(state): State<AppState>,
+    user: UserId,
+    ValidatedJson(updated_todo): ValidatedJson<Todo>,
+) -> Result<StatusCode, ApiError> {
+    todo::validate_todo(&updated_todo)?;
+
+    let id = updated_todo.id;
+    state
+        .repository
+        .update_todo(&user, updated_todo)
+        .await
+        .map_err(|error| update_error(error, id))?;
+
+    if let Some(todo) = fetch_changed(&state, &user, id).await {
+        state.publish(TodoAction::Updated, todo);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    put,
+    path = "/todos/{id}",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    request_body(content = Todo, description = "The todo, the id may be left out"),
+    responses(
+        (status = 204, description = "The existing todo was replaced"),
+        (status = 201, description = "There was no todo with the id, so it was created",
+            headers(("Location" = String, description = "The path of the created todo"))),
+        (status = 400, description = "The id in the body differs from the path", body = ErrorBody),
+        (status = 404, description = "The todo has been deleted", body = ErrorBody),
+        (status = 507, description = "Creating the todo would make more todos than allowed",
+            body = ErrorBody),
+        (status = 409, description = "The todo was changed since the given version", body = ErrorBody),
+        (status = 422, description = "The todo is invalid", body = ErrorBody),
+        (status = 500, description = "The todo could not be stored", body = ErrorBody),
+    )
+)]
+async fn replace_todo(
+    State(state): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+    ValidatedJson(mut body): ValidatedJson<serde_json::Value>,
+) -> Result<Response, ApiError> {
+    let Some(fields) = body.as_object_mut() else {
+        return Err(ApiError::invalid_body(
+            "invalid_body",
+            "the body must be a JSON object",
+            None,
+        ));
+    };
+
+    // The path decides which todo is replaced, the body may only repeat its id
+    match fields.get("id") {
+        None | Some(serde_json::Value::Null) => {}
+        Some(body_id) if body_id.as_u64() == Some(id.into()) => {}
+        Some(body_id) => {
+            return Err(ApiError::bad_request(
+                "id_mismatch",
+                format!("the body has id {body_id}, but the path has id {id}"),
+            ))
+        }
+    }
+    fields.insert("id".into(), id.into());
+
+    let todo = extract::from_value(body)?;
+    todo::validate_todo(&todo)?;
+    let exists = state
+        .repository
+        .todo_exists(&user, id)
+        .await
+        .map_err(|_| ApiError::internal("looking up the todo failed"))?;
+    if !exists {
+        ensure_room(&state, 1).await?;
+    }
+
+    let upserted = state
+        .repository
+        .upsert_todo(&user, todo)
+        .await
+        .map_err(|error| update_error(error, id))?;
+
+    let Ok(Some(todo)) = state.repository.get_todo(&user, id).await else {
+        return Err(ApiError::internal("reading the stored todo failed"));
+    };
+
+    match upserted {
+        Upserted::Created => {
+            state.publish(TodoAction::Created, todo);
+            Ok((
+                StatusCode::CREATED,
+                [(header::LOCATION, format!("/todos/{id}"))],
+            )
+                .into_response())
+        }
+        Upserted::Replaced => {
+            state.publish(TodoAction::Updated, todo);
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+    }
+}
+
+/// Maps a failed update to 404 if the todo doesn't exist, 409 if its version has moved on, or 500
+/// if the repository failed
+fn update_error(error: UpdateError, id: u32) -> ApiError {
+    match error {
+        UpdateError::NotFound => ApiError::todo_not_found(id),
+        UpdateError::VersionConflict => ApiError::new(
+            StatusCode::CONFLICT,
+            "version_conflict",
+            format!("todo {id} was changed since the given version"),
+        ),
+        UpdateError::InvalidParent => ValidationError {
+            field: "parent_id".into(),
+            error: "the parent must be an existing todo that isn't one of its subtasks".into(),
+        }
+        .into(),
+        UpdateError::Other(_) => ApiError::internal("updating the todo failed"),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/todos/{id}",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    request_body = TodoPatch,
+    responses(
+        (status = 200, description = "The patched todo", body = Todo),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+        (status = 409, description = "The todo was changed since the given version", body = ErrorBody),
+        (status = 422, description = "The todo is invalid", body = ErrorBody),
+        (status = 500, description = "The todo could not be updated", body = ErrorBody),
+    )
+)]
+async fn patch_todo(
+    State(state): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+    ValidatedJson(patch): ValidatedJson<TodoPatch>,
+) -> Result<Json<Todo>, ApiError> {
+    if let Some(name) = &patch.name {
+        todo::validate_name(name)?;
+    }
+
+    let patched = state
+        .repository
+        .patch_todo(&user, id, patch)
+        .await
+        .map_err(|error| update_error(error, id))?;
+
+    state.publish(TodoAction::Updated, patched.clone());
+    Ok(Json(patched))
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeleteOptions {
+    /// Also delete the subtasks of the todo, instead of refusing to delete a todo with subtasks
+    #[serde(default)]
+    cascade: bool,
+    /// List the todos that would be deleted, without deleting them
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/todos/{id}",
+    params(("id" = u32, Path, description = "The id of the todo"), DeleteOptions),
+    responses(
+        (status = 200, description = "The todo as it was before being deleted, so it can be \
+            restored. A dry run returns a `WouldDelete` listing the todos it would delete instead",
+            body = Todo),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+        (status = 409, description = "The todo has subtasks and `cascade` was not set",
+            body = ErrorBody),
+        (status = 500, description = "The todo could not be deleted", body = ErrorBody),
+    )
+)]
+async fn delete_todo(
+    State(state): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+    Query(DeleteOptions { cascade, dry_run }): Query<DeleteOptions>,
+) -> Result<Response, ApiError> {
+    let deleted = state
+        .repository
+        .delete_todo(&user, id, cascade, dry_run)
+        .await
+        .map_err(|error| match error {
+            DeleteError::NotFound => ApiError::todo_not_found(id),
+            DeleteError::HasSubtasks => ApiError::new(
+                StatusCode::CONFLICT,
+                "todo_has_subtasks",
+                format!("todo {id} has subtasks, pass cascade=true to delete them too"),
+            ),
+            DeleteError::Other(_) => ApiError::internal("deleting the todo failed"),
+        })?;
+
+    if dry_run {
+        return Ok(Json(WouldDelete::new(deleted)).into_response());
+    }
+
+    // The todo itself comes first, followed by any subtasks deleted with it
+    let todo = deleted[0].clone();
+    for todo in deleted {
+        state.publish(TodoAction::Deleted, todo);
+    }
+    Ok(Json(todo).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/restore",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    responses(
+        (status = 204, description = "The todo was restored"),
+        (status = 404, description = "The todo does not exist or is not deleted", body = ErrorBody),
+        (status = 500, description = "The todo could not be restored", body = ErrorBody),
+    )
+)]
+async fn restore_todo(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+) -> Result<StatusCode, ApiError> {
+    let restored = repository
+        .restore_todo(&user, id)
+        .await
+        .map_err(|_| ApiError::internal("restoring the todo failed"))?;
+    if !restored {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "todo_not_deleted",
+            format!("todo {id} does not exist or is not deleted"),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/{id}/history",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    responses(
+        (status = 200, description = "Every change to the todo, oldest first", body = [TodoChange]),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+    )
+)]
+async fn todo_history(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<TodoChange>>, ApiError> {
+    match repository.get_todo_history(&user, id).await {
+        Ok(Some(history)) => Ok(Json(history)),
+        Ok(None) => Err(ApiError::todo_not_found(id)),
+        Err(_) => Err(ApiError::internal("fetching the history failed")),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/todos/{id}/subtasks",
+    params(("id" = u32, Path, description = "The id of the todo")),
+    responses(
+        (status = 200, description = "The direct subtasks of the todo", body = [Todo]),
+        (status = 404, description = "The todo does not exist", body = ErrorBody),
+        (status = 500, description = "The subtasks could not be fetched", body = ErrorBody),
+    )
+)]
+async fn todo_subtasks(
+    State(AppState { repository, .. }): State<AppState>,
+    user: UserId,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<Todo>>, ApiError> {
+    repository
+        .get_todo(&user, id)
+        .await
+        .map_err(|_| ApiError::internal("fetching the todo failed"))?
+        .ok_or(ApiError::todo_not_found(id))?;
+
+    let filter = Filter {
+        user,
+        parent_id: Some(id),
+        ..Filter::default()
+    };
 
-pub async fn app(_db_path: String) -> Router {
-    Router::new()
+    repository
+        .get_todos_filtered(filter, Sort::default(), None)
+        .await
+        .map(Json)
+        .map_err(|_| ApiError::internal("fetching the subtasks failed"))
 }
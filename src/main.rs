@@ -1,9 +1,186 @@
-use todo_server_workshop::app;
+use std::{net::SocketAddr, path::Path, str::FromStr, time::Duration};
+
+use todo_server_workshop::{app_with_pool, install_metrics_recorder, open_database, AppConfig};
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() {
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
-    axum::serve(listener, app("todo_server_workshop_db.db".into()).await)
-        .await
-        .unwrap();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new("todo_server_workshop=info,tower_http=info")),
+        )
+        .init();
+
+    install_metrics_recorder();
+
+    let config = match config_from_env() {
+        Ok(config) => config,
+        Err(error) => {
+            tracing::error!("{error}");
+            std::process::exit(1);
+        }
+    };
+
+    let pool = match open_database(&config).await {
+        Ok(pool) => pool,
+        Err(error) => {
+            tracing::error!("{error}");
+            std::process::exit(1);
+        }
+    };
+
+    let bind_addr = config.bind_addr();
+    let socket_path = config.socket_path().map(Path::to_owned);
+    let app = match app_with_pool(pool.clone(), config).await {
+        Ok(app) => app,
+        Err(error) => {
+            tracing::error!("{error}");
+            std::process::exit(1);
+        }
+    };
+
+    match socket_path {
+        #[cfg(unix)]
+        Some(socket_path) => {
+            tracing::info!("listening on {}", socket_path.display());
+            // Without a client address the rate limiter lets every request through, the proxy
+            // in front is expected to limit them instead
+            todo_server_workshop::serve_unix(&socket_path, app, shutdown_signal())
+                .await
+                .unwrap();
+        }
+        _ => {
+            let listener = tokio::net::TcpListener::bind(bind_addr).await.unwrap();
+            tracing::info!("listening on {bind_addr}");
+            // Connection info gives the rate limiter the address of each client
+            let app = app.into_make_service_with_connect_info::<SocketAddr>();
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+                .unwrap();
+        }
+    }
+
+    // All in-flight requests have finished, so nothing is using the database anymore
+    if let Err(error) = pool.close().await {
+        tracing::error!("closing the database failed: {error}");
+    }
+}
+
+/// Reads the config from the `TODO_*` environment variables, unset ones keep their defaults.
+///
+/// An invalid bind address or a purge retention too long to represent is an error, other invalid
+/// values are warned about and ignored.
+fn config_from_env() -> Result<AppConfig, String> {
+    let mut config = AppConfig::builder();
+
+    if let Ok(db_path) = std::env::var("TODO_DB_PATH") {
+        config = config.db_path(db_path);
+    }
+
+    if let Ok(addr) = std::env::var("TODO_BIND_ADDR") {
+        if let Some(socket_path) = addr.strip_prefix("unix:") {
+            if socket_path.is_empty() {
+                return Err(format!(
+                    "invalid TODO_BIND_ADDR {addr:?}: the socket path is empty"
+                ));
+            }
+            if !cfg!(unix) {
+                return Err(format!(
+                    "invalid TODO_BIND_ADDR {addr:?}: Unix sockets aren't supported here"
+                ));
+            }
+            config = config.socket_path(socket_path);
+        } else {
+            let bind_addr: SocketAddr = addr
+                .parse()
+                .map_err(|error| format!("invalid TODO_BIND_ADDR {addr:?}: {error}"))?;
+            config = config.bind_addr(bind_addr);
+        }
+    }
+
+    if let Ok(origins) = std::env::var("TODO_CORS_ORIGINS") {
+        config = config.cors_origins(
+            origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty()),
+        );
+    }
+
+    if let Ok(api_key) = std::env::var("TODO_API_KEY") {
+        config = config.api_key(api_key);
+    }
+
+    if let Some(per_minute) = parse_var("TODO_RATE_LIMIT") {
+        config = config.rate_limit(per_minute);
+    }
+
+    if let Some(bytes) = parse_var("TODO_MAX_BODY_BYTES") {
+        config = config.max_body_bytes(bytes);
+    }
+
+    if let Some(seconds) = parse_var("TODO_REQUEST_TIMEOUT_SECS") {
+        config = config.request_timeout(Duration::from_secs(seconds));
+    }
+
+    if let Some(max_todos) = parse_var("TODO_MAX_TODOS") {
+        config = config.max_todos(max_todos);
+    }
+
+    if let Ok(seed_path) = std::env::var("TODO_SEED_PATH") {
+        config = config.seed_path(seed_path);
+    }
+
+    if let Some(days) = parse_var::<u64>("TODO_PURGE_COMPLETED_AFTER_DAYS") {
+        let seconds = days.checked_mul(24 * 60 * 60).ok_or_else(|| {
+            format!("invalid TODO_PURGE_COMPLETED_AFTER_DAYS {days}: too many days")
+        })?;
+        config = config.purge_completed_after(Duration::from_secs(seconds));
+    }
+
+    if let Some(seconds) = parse_var("TODO_PURGE_INTERVAL_SECS") {
+        config = config.purge_interval(Duration::from_secs(seconds));
+    }
+
+    Ok(config.build())
+}
+
+/// Parses the environment variable `name`, warning about invalid values so the default is used
+fn parse_var<T: FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        tracing::warn!("invalid {name} {value:?}, using the default");
+    }
+    parsed
+}
+
+/// Completes when the process is asked to stop, either by ctrl-c or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for ctrl-c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::info!("shutting down, waiting for in-flight requests to finish");
 }
@@ -0,0 +1,56 @@
+use std::{sync::OnceLock, time::Instant};
+
+use axum::{extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+static RECORDER: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Installs the global Prometheus recorder, returning a handle to render its metrics.
+///
+/// Only the first call installs the recorder, later calls return the same handle.
+pub fn install_metrics_recorder() -> &'static PrometheusHandle {
+    RECORDER.get_or_init(|| {
+        PrometheusBuilder::new()
+            .install_recorder()
+            .expect("installing the metrics recorder failed")
+    })
+}
+
+/// Renders all recorded metrics in the Prometheus text exposition format
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", content_type = "text/plain"),
+    )
+)]
+pub(crate) async fn render_metrics() -> String {
+    install_metrics_recorder().render()
+}
+
+/// Counts every request and records how long the handler took, labelled by route and status
+pub(crate) async fn track_metrics(request: Request<axum::body::Body>, next: Next) -> Response {
+    let started = Instant::now();
+
+    // Use the route pattern rather than the actual path, so every todo id shares one label
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+    let method = request.method().to_string();
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("path", path),
+        ("status", response.status().as_u16().to_string()),
+    ];
+
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels)
+        .record(started.elapsed().as_secs_f64());
+
+    response
+}
@@ -0,0 +1,94 @@
+use axum::Json;
+use utoipa::OpenApi;
+
+use std::collections::BTreeMap;
+
+use crate::{
+    events::{TodoAction, TodoEvent},
+    ChangeAction, Completion, DayStats, DeleteIds, Deleted, Envelope, ErrorBody, Export,
+    FindReplace, ImportSummary, MoveTo, NewTodo, NewTodos, OnConflict, Priority, Recurrence,
+    SortField, SortOrder, Todo, TodoChange, TodoCounts, TodoPatch, ToggleAll, ToggleIds,
+    ToggledTodos, Updated, ValidationError, WouldDelete,
+};
+
+/// The OpenAPI description of every route, served at `/api-docs/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Todo server workshop"),
+    paths(
+        crate::health,
+        crate::todos,
+        crate::create_todo,
+        crate::update_todo,
+        crate::replace_todo,
+        crate::delete_todos,
+        crate::count_todos,
+        crate::stats,
+        crate::random_todo,
+        crate::search_todos,
+        crate::export_csv,
+        crate::export_ndjson,
+        crate::events::todo_events,
+        crate::create_todos,
+        crate::export,
+        crate::import_todos,
+        crate::toggle_many,
+        crate::toggle_all,
+        crate::reset_todos,
+        crate::replace_in_names,
+        crate::reorder_todos,
+        crate::move_todo,
+        crate::get_todo,
+        crate::todo_exists,
+        crate::patch_todo,
+        crate::delete_todo,
+        crate::restore_todo,
+        crate::complete_todo,
+        crate::incomplete_todo,
+        crate::put_completed,
+        crate::archive_todo,
+        crate::unarchive_todo,
+        crate::todo_history,
+        crate::todo_subtasks,
+        crate::toggle,
+        crate::metrics::render_metrics,
+    ),
+    components(schemas(
+        Todo,
+        NewTodo,
+        NewTodos,
+        TodoPatch,
+        Priority,
+        Recurrence,
+        TodoCounts,
+        DayStats,
+        TodoChange,
+        ChangeAction,
+        ValidationError,
+        ErrorBody,
+        DeleteIds,
+        Deleted,
+        Completion,
+        WouldDelete,
+        Envelope,
+        Updated,
+        ToggleAll,
+        FindReplace,
+        ToggleIds,
+        ToggledTodos,
+        TodoEvent,
+        TodoAction,
+        SortField,
+        SortOrder,
+        MoveTo,
+        OnConflict,
+        ImportSummary,
+        Export,
+    ))
+)]
+struct ApiDoc;
+
+/// Serves the OpenAPI spec as JSON
+pub(crate) async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
@@ -0,0 +1,100 @@
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio_rusqlite::Connection;
+
+/// How long a connection waits for another connection to release its lock before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A fixed set of connections to the same database.
+///
+/// Every connection runs its queries on its own background thread, so spreading calls over
+/// several connections lets reads run concurrently instead of queueing behind each other.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    connections: Arc<[Connection]>,
+    next: Arc<AtomicUsize>,
+}
+
+impl ConnectionPool {
+    /// Opens `size` connections to the database at `path`.
+    ///
+    /// In-memory databases are private to a connection, so only ever open them with a size of one.
+    pub async fn open(path: impl AsRef<Path>, size: usize) -> tokio_rusqlite::Result<Self> {
+        assert!(size > 0, "a connection pool needs at least one connection");
+
+        let mut connections = Vec::with_capacity(size);
+        for _ in 0..size {
+            let connection = Connection::open(path.as_ref()).await?;
+            connection
+                .call(|connection| Ok(connection.busy_timeout(BUSY_TIMEOUT)?))
+                .await?;
+            connections.push(connection);
+        }
+
+        Ok(Self::from_connections(connections))
+    }
+
+    /// Wraps a single connection in a pool.
+    pub fn single(connection: Connection) -> Self {
+        Self::from_connections(vec![connection])
+    }
+
+    fn from_connections(connections: Vec<Connection>) -> Self {
+        Self {
+            connections: connections.into(),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Checks out the next connection, handing them out in turn
+    fn get(&self) -> &Connection {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len();
+        &self.connections[index]
+    }
+
+    /// Like [`Connection::call`], but runs on the next connection in the pool
+    pub(crate) async fn call<F, R>(&self, function: F) -> tokio_rusqlite::Result<R>
+    where
+        F: FnOnce(&mut rusqlite::Connection) -> tokio_rusqlite::Result<R> + 'static + Send,
+        R: Send + 'static,
+    {
+        self.get().call(function).await
+    }
+
+    /// Like [`Connection::call_unwrap`], but runs on the next connection in the pool
+    pub(crate) async fn call_unwrap<F, R>(&self, function: F) -> R
+    where
+        F: FnOnce(&mut rusqlite::Connection) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.get().call_unwrap(function).await
+    }
+
+    /// Runs `function` on every connection in the pool, e.g. to set per-connection pragmas
+    pub(crate) async fn call_each<F>(&self, function: F) -> tokio_rusqlite::Result<()>
+    where
+        F: Fn(&mut rusqlite::Connection) -> tokio_rusqlite::Result<()> + Clone + Send + 'static,
+    {
+        for connection in self.connections.iter() {
+            connection.call(function.clone()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Closes every connection in the pool
+    pub async fn close(self) -> tokio_rusqlite::Result<()> {
+        for connection in self.connections.iter() {
+            connection.clone().close().await?;
+        }
+
+        Ok(())
+    }
+}
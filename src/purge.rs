@@ -0,0 +1,50 @@
+use std::{sync::Weak, time::Duration};
+
+use tokio::time::MissedTickBehavior;
+
+use crate::{RepositoryError, TodoRepository};
+
+/// Deletes the todos that were completed more than `retention` ago for good, returning how many
+/// were deleted
+pub async fn purge_completed(
+    repository: &dyn TodoRepository,
+    retention: Duration,
+) -> Result<usize, RepositoryError> {
+    let cutoff = chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| chrono::Utc::now().checked_sub_signed(retention));
+    // A retention too long to subtract from now keeps every todo
+    let Some(cutoff) = cutoff else {
+        return Ok(0);
+    };
+
+    let purged = repository.purge_completed(cutoff).await?;
+    tracing::info!(purged, "purged completed todos");
+    Ok(purged)
+}
+
+/// Purges completed todos every `interval`, until the app holding the repository is dropped
+pub(crate) fn spawn_purge_task(
+    repository: Weak<dyn TodoRepository>,
+    retention: Duration,
+    interval: Duration,
+) {
+    // Tokio refuses intervals of zero
+    let mut ticks = tokio::time::interval(interval.max(Duration::from_millis(1)));
+    // A purge that takes longer than the interval delays the next one, rather than running
+    // the missed ones back to back
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    tokio::spawn(async move {
+        loop {
+            ticks.tick().await;
+
+            let Some(repository) = repository.upgrade() else {
+                return;
+            };
+            if let Err(error) = purge_completed(repository.as_ref(), retention).await {
+                tracing::warn!(%error, "purging completed todos failed");
+            }
+        }
+    });
+}
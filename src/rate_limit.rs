@@ -0,0 +1,117 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::ApiError;
+
+/// Requests per minute allowed from a single IP unless another limit is configured
+pub const DEFAULT_RATE_LIMIT: u32 = 60;
+
+/// Once this many clients are tracked, clients that have been quiet for a while are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Limits how many requests each IP can make, using a token bucket per IP.
+///
+/// Every IP can burst up to the limit, after which tokens refill evenly over a minute.
+pub(crate) struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refill_per_second(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+
+    /// Takes a token for `ip`, or returns how long until one is available
+    fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let capacity = f64::from(self.per_minute);
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            // A bucket that has been idle this long is full again, so forgetting it changes nothing
+            buckets
+                .retain(|_, bucket| now.duration_since(bucket.updated) < Duration::from_secs(60));
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_second()).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(Duration::from_secs_f64(missing / self.refill_per_second()))
+        }
+    }
+}
+
+/// Rejects requests with 429 once their IP has used up its requests.
+///
+/// Requests without a known peer address, e.g. in tests, are never limited.
+pub(crate) async fn rate_limit(
+    State(limiter): State<Option<Arc<RateLimiter>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    let (Some(limiter), Some(ip)) = (limiter, peer) else {
+        return next.run(request).await;
+    };
+
+    match limiter.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => (
+            // Round up, so clients retrying right on time get through
+            [(
+                header::RETRY_AFTER,
+                retry_after.as_secs_f64().ceil().to_string(),
+            )],
+            ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                "too many requests",
+            ),
+        )
+            .into_response(),
+    }
+}
+
+/// Creates the rate limiter for the allowed requests per minute, where 0 turns it off
+pub(crate) fn rate_limiter(per_minute: u32) -> Option<Arc<RateLimiter>> {
+    (per_minute > 0).then(|| Arc::new(RateLimiter::new(per_minute)))
+}
@@ -0,0 +1,432 @@
+use std::{collections::BTreeMap, fmt};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::{DayStats, NewTodo, Recurrence, Todo, TodoChange, TodoCounts, TodoPatch, UserId};
+
+/// Where the app keeps its todos.
+///
+/// The handlers only ever go through this trait and never touch a database themselves, so todos
+/// can be kept somewhere other than the SQLite database of the `sqlite` feature, e.g. Postgres,
+/// by passing another repository to [`app_with_repository`](crate::app_with_repository).
+///
+/// Every method only reads and changes the todos of the given user, other users' todos are
+/// treated as if they don't exist. Deleted todos are kept around so they can be restored, but are
+/// left out unless asked for.
+#[async_trait]
+pub trait TodoRepository: Send + Sync {
+    /// Checks that the repository can be reached
+    async fn ping(&self) -> Result<(), RepositoryError>;
+
+    /// Fetches a todo, or `None` if it doesn't exist or is deleted
+    async fn get_todo(&self, user: &UserId, id: u32) -> Result<Option<Todo>, RepositoryError>;
+
+    /// Whether a todo exists and isn't deleted, without fetching it
+    async fn todo_exists(&self, user: &UserId, id: u32) -> Result<bool, RepositoryError>;
+
+    /// Picks one of the incomplete todos that aren't archived at random, or `None` if there are
+    /// none
+    async fn random_pending(&self, user: &UserId) -> Result<Option<Todo>, RepositoryError>;
+
+    /// Every todo, in the default order
+    async fn get_todos(&self, user: &UserId) -> Result<Vec<Todo>, RepositoryError>;
+
+    /// Up to `limit` todos matching the filter with an id above `after`, or from the first todo
+    /// without one, ordered by id
+    async fn get_todos_after(
+        &self,
+        filter: Filter,
+        after: Option<u32>,
+        limit: u32,
+    ) -> Result<Vec<Todo>, RepositoryError>;
+
+    /// The todos matching the filter, in the given order and only those within the page if any
+    async fn get_todos_filtered(
+        &self,
+        filter: Filter,
+        sort: Sort,
+        page: Option<Page>,
+    ) -> Result<Vec<Todo>, RepositoryError>;
+
+    /// Like [`get_todos_filtered`](TodoRepository::get_todos_filtered), along with how many todos
+    /// match across all pages
+    async fn search_todos(
+        &self,
+        criteria: SearchCriteria,
+    ) -> Result<SearchResults, RepositoryError>;
+
+    /// Counts the todos, broken down by completion status
+    async fn count_todos(&self, user: &UserId) -> Result<TodoCounts, RepositoryError>;
+
+    /// Counts the todos of every user together, for enforcing the limit on stored todos
+    async fn count_stored_todos(&self) -> Result<u64, RepositoryError>;
+
+    /// How many todos were created and completed on each day, keyed by the date in UTC
+    async fn daily_stats(
+        &self,
+        user: &UserId,
+    ) -> Result<BTreeMap<String, DayStats>, RepositoryError>;
+
+    /// Every change to a todo, oldest first, or `None` if there is no such todo. Deleted todos
+    /// still have their history
+    async fn get_todo_history(
+        &self,
+        user: &UserId,
+        id: u32,
+    ) -> Result<Option<Vec<TodoChange>>, RepositoryError>;
+
+    /// Inserts a todo at the end of the list.
+    ///
+    /// If a todo was already created with the idempotency key, nothing is inserted and that
+    /// todo's id is returned instead. With `unique_name` set, the todo is only inserted if the
+    /// user has no todo with the same name, ignoring case.
+    async fn insert_todo(
+        &self,
+        user: &UserId,
+        todo: NewTodo,
+        idempotency_key: Option<String>,
+        unique_name: bool,
+    ) -> Result<Inserted, RepositoryError>;
+
+    /// The id of the todo the user created with the idempotency key, unless the key expired
+    async fn idempotent_todo_id(
+        &self,
+        user: &UserId,
+        idempotency_key: &str,
+    ) -> Result<Option<u32>, RepositoryError>;
+
+    /// Inserts all todos or none of them, returning their ids in order
+    async fn insert_todos(
+        &self,
+        user: &UserId,
+        todos: Vec<NewTodo>,
+    ) -> Result<Vec<u32>, RepositoryError>;
+
+    /// Imports todos with their ids, skipping ids that belong to another user
+    async fn import_todos(
+        &self,
+        user: &UserId,
+        todos: Vec<Todo>,
+        on_conflict: OnConflict,
+    ) -> Result<ImportSummary, RepositoryError>;
+
+    /// Replaces a todo as long as it is still at the version it has, incrementing the version
+    async fn update_todo(&self, user: &UserId, todo: Todo) -> Result<(), UpdateError>;
+
+    /// Creates the todo with its id, or replaces it like
+    /// [`update_todo`](TodoRepository::update_todo) if it exists
+    async fn upsert_todo(&self, user: &UserId, todo: Todo) -> Result<Upserted, UpdateError>;
+
+    /// Applies a partial update, returning the updated todo
+    async fn patch_todo(
+        &self,
+        user: &UserId,
+        id: u32,
+        patch: TodoPatch,
+    ) -> Result<Todo, UpdateError>;
+
+    /// Lists the todos in the given order, among the positions they already had
+    async fn reorder_todos(&self, user: &UserId, ids: Vec<u32>) -> Result<(), ReorderError>;
+
+    /// Moves a todo to the top or bottom of the list, leaving the others in place. Returns
+    /// whether there was such a todo
+    async fn move_todo(&self, user: &UserId, id: u32, to: MoveTo) -> Result<bool, RepositoryError>;
+
+    /// Flips whether a todo is completed, or `None` if there is no such todo. Completing a
+    /// recurring todo clears its recurrence
+    async fn toggle_todo(&self, user: &UserId, id: u32)
+        -> Result<Option<Toggled>, RepositoryError>;
+
+    /// Toggles each of the todos, skipping the ones that don't exist
+    async fn toggle_many(
+        &self,
+        user: &UserId,
+        ids: Vec<u32>,
+    ) -> Result<ToggledMany, RepositoryError>;
+
+    /// Sets whether a todo is completed, returning it as it was before or `None` if there is no
+    /// such todo. Completing a recurring todo clears its recurrence
+    async fn set_completed(
+        &self,
+        user: &UserId,
+        id: u32,
+        completed: bool,
+    ) -> Result<Option<Todo>, RepositoryError>;
+
+    /// Sets whether a todo is archived, returning it as it was before or `None` if there is no
+    /// such todo
+    async fn set_archived(
+        &self,
+        user: &UserId,
+        id: u32,
+        archived: bool,
+    ) -> Result<Option<Todo>, RepositoryError>;
+
+    /// Sets whether every todo is completed, returning the updated todos
+    async fn set_all_completed(
+        &self,
+        user: &UserId,
+        completed: bool,
+    ) -> Result<Vec<Todo>, RepositoryError>;
+
+    /// Replaces every occurrence of `find` in the names of the user's todos, returning the
+    /// renamed todos
+    async fn replace_in_names(
+        &self,
+        user: &UserId,
+        find: String,
+        replace: String,
+    ) -> Result<Vec<Todo>, RepositoryError>;
+
+    /// Deletes a todo, along with its subtasks if `cascade` is set, returning every deleted todo
+    /// starting with the todo itself. A dry run only looks them up
+    async fn delete_todo(
+        &self,
+        user: &UserId,
+        id: u32,
+        cascade: bool,
+        dry_run: bool,
+    ) -> Result<Vec<Todo>, DeleteError>;
+
+    /// Brings back a deleted todo, returning whether there was one to restore
+    async fn restore_todo(&self, user: &UserId, id: u32) -> Result<bool, RepositoryError>;
+
+    /// Deletes every completed todo, returning the deleted todos. A dry run only looks them up
+    async fn delete_completed(
+        &self,
+        user: &UserId,
+        dry_run: bool,
+    ) -> Result<Vec<Todo>, RepositoryError>;
+
+    /// Deletes every user's todos that were completed before `completed_before` for good, along
+    /// with their history. Returns how many were deleted, todos with subtasks are kept
+    async fn purge_completed(
+        &self,
+        completed_before: DateTime<Utc>,
+    ) -> Result<usize, RepositoryError>;
+
+    /// Deletes the todos with the given ids, all or none of them, returning the deleted todos.
+    /// Ids that don't exist are left out. A dry run only looks them up
+    async fn delete_many(
+        &self,
+        user: &UserId,
+        ids: Vec<u32>,
+        dry_run: bool,
+    ) -> Result<Vec<Todo>, RepositoryError>;
+}
+
+/// Why a [`TodoRepository`] could not carry out an operation
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// A todo with the same id already exists
+    Conflict,
+    /// The parent todo doesn't exist, or belongs to another user
+    InvalidParent,
+    /// The name is longer than the storage allows
+    NameTooLong,
+    /// A todo with the same name already exists, and names were asked to be unique
+    DuplicateName,
+    /// Anything else, e.g. the database being unreachable
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepositoryError::Conflict => f.write_str("a todo with that id already exists"),
+            RepositoryError::InvalidParent => f.write_str("the parent todo does not exist"),
+            RepositoryError::DuplicateName => f.write_str("a todo with that name already exists"),
+            RepositoryError::NameTooLong => write!(
+                f,
+                "name must be at most {} characters",
+                crate::todo::MAX_NAME_LENGTH
+            ),
+            RepositoryError::Other(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RepositoryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RepositoryError::Other(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// The outcome of [`TodoRepository::insert_todo`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Inserted {
+    pub id: u32,
+    /// Whether the todo already existed for the idempotency key, so nothing was inserted
+    pub replayed: bool,
+}
+
+/// What to do when an imported todo has the same id as an existing one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+    /// Keep the existing todo
+    #[default]
+    Skip,
+    /// Overwrite the existing todo with the imported one
+    Replace,
+}
+
+/// How many todos an import inserted, replaced and skipped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+pub struct ImportSummary {
+    pub inserted: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+}
+
+/// Narrows down which todos are returned, unset fields match every todo
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// The user the todos belong to, this is always matched
+    pub user: UserId,
+    pub completed: Option<bool>,
+    /// Case-insensitive substring the name must contain
+    pub name_contains: Option<String>,
+    /// Whether the todo is incomplete and its due date has passed
+    pub overdue: Option<bool>,
+    /// A tag the todo must have
+    pub tag: Option<String>,
+    /// Whether soft-deleted todos are included as well
+    pub include_deleted: bool,
+    /// Whether the todo is not a subtask of another todo
+    pub top_level: Option<bool>,
+    /// The todo this must be a direct subtask of
+    pub parent_id: Option<u32>,
+    /// The earliest time the todo may have been completed at, excluding the time itself
+    pub completed_after: Option<DateTime<Utc>>,
+    /// The time the todo must be due before, todos without a due date never match
+    pub due_before: Option<DateTime<Utc>>,
+    /// The time the todo must be due after, todos without a due date never match
+    pub due_after: Option<DateTime<Utc>>,
+    /// Who the todo must be assigned to
+    pub assignee: Option<String>,
+    /// Whether the todo is archived
+    pub archived: Option<bool>,
+    /// The ids the todo must have one of
+    pub ids: Option<Vec<u32>>,
+}
+
+/// The fields todos can be sorted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    /// The order set by reordering, new todos come last
+    #[default]
+    Position,
+    Id,
+    Name,
+    Completed,
+    CreatedAt,
+    Priority,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Where to move a todo in the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveTo {
+    /// Before every other todo
+    Top,
+    /// After every other todo
+    Bottom,
+}
+
+/// How to order the list of todos
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sort {
+    pub field: SortField,
+    pub order: SortOrder,
+}
+
+/// A window into the list of todos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub limit: u32,
+    pub offset: u32,
+}
+
+/// What to search for, and which page of the results to return
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchCriteria {
+    pub filter: Filter,
+    pub sort: Sort,
+    pub page: Option<Page>,
+}
+
+/// A page of todos matching a search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResults {
+    pub todos: Vec<Todo>,
+    /// How many todos match, across all pages
+    pub total: u64,
+}
+
+/// Why a todo could not be updated
+#[derive(Debug)]
+pub enum UpdateError {
+    NotFound,
+    /// The todo was changed by someone else since the given version
+    VersionConflict,
+    /// The parent doesn't exist, or is the todo itself or one of its subtasks
+    InvalidParent,
+    Other(RepositoryError),
+}
+
+/// Whether [`TodoRepository::upsert_todo`] created the todo or replaced an existing one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upserted {
+    Created,
+    Replaced,
+}
+
+/// Why todos could not be reordered
+#[derive(Debug)]
+pub enum ReorderError {
+    /// The todo with this id doesn't exist or is deleted
+    NotFound(u32),
+    Repository(RepositoryError),
+}
+
+/// Why a todo could not be deleted
+#[derive(Debug)]
+pub enum DeleteError {
+    NotFound,
+    /// The todo has subtasks, and deleting them as well wasn't asked for
+    HasSubtasks,
+    Other(RepositoryError),
+}
+
+/// The outcome of toggling a todo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Toggled {
+    /// Whether the todo is completed now
+    pub completed: bool,
+    /// The recurrence the todo had, its next occurrence takes it over if the todo got completed
+    pub recurrence: Option<Recurrence>,
+}
+
+/// The outcome of toggling several todos at once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToggledMany {
+    /// The toggled todos in the order they were asked for, along with the recurrence each had
+    /// before, which the next occurrence takes over if the todo got completed
+    pub todos: Vec<(Todo, Option<Recurrence>)>,
+    /// The ids of the todos that don't exist and were skipped
+    pub missing: Vec<u32>,
+}
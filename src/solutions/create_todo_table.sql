@@ -1,5 +1,8 @@
-CREATE TABLE IF NOT EXISTS todos (
-  id        INTEGER PRIMARY KEY NOT NULL,
-  name      TEXT NOT NULL,
-  completed BOOL NOT NULL
+CREATE TABLE IF NOT EXISTS {todos} (
+  id         INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  name       TEXT NOT NULL,
+  completed  BOOL NOT NULL,
+  due_date   TEXT,
+  priority   INTEGER NOT NULL DEFAULT 1,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
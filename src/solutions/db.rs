@@ -1,88 +1,2229 @@
-use tokio_rusqlite::Connection;
+use std::{collections::BTreeMap, future::Future, time::Duration};
 
-use crate::Todo;
+use chrono::{DateTime, Utc};
+use rusqlite::{
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Value, ValueRef},
+    OptionalExtension, ToSql,
+};
+
+use crate::{
+    ChangeAction, ConnectionPool, DayStats, DeleteError, Filter, ImportSummary, Inserted, MoveTo,
+    NewTodo, OnConflict, Page, Priority, Recurrence, ReorderError, RepositoryError, SearchCriteria,
+    SearchResults, Sort, SortField, SortOrder, TableName, Todo, TodoChange, TodoCounts, TodoPatch,
+    Toggled, ToggledMany, UpdateError, Upserted, UserId,
+};
 
 const CREATE_TODO_TABLE_SQL: &str = include_str!("./create_todo_table.sql");
 
-pub(crate) async fn create_todos_table(connection: &Connection) {
-    connection
-        .call(|conn| Ok(conn.execute(CREATE_TODO_TABLE_SQL, [])?))
-        .await
-        .expect("creating todo table failed");
+/// Selects every column of a todo, in the order expected by [`todo_from_row`].
+///
+/// Tags are joined into a single column, separated by [`TAG_SEPARATOR`].
+const SELECT_TODOS: &str = "SELECT id, name, completed, due_date, priority, \
+                            (SELECT group_concat({tags}.name, char(31)) FROM {todo_tags} \
+                            JOIN {tags} ON {tags}.id = {todo_tags}.tag_id WHERE {todo_tags}.todo_id = {todos}.id), \
+                            deleted_at, version, parent_id, completed_at, recurrence, assignee, \
+                            description, archived, user_id FROM {todos}";
+
+/// Keeps `completed_at` in line with an update that sets `completed` to the SQL expression
+/// `completed`: it becomes now when the todo gets completed, and is cleared when it no longer is
+fn set_completed_at(completed: &str) -> String {
+    format!(
+        "completed_at = CASE WHEN NOT {completed} THEN NULL WHEN completed THEN completed_at \
+         ELSE strftime('%Y-%m-%d %H:%M:%f+00:00', 'now') END"
+    )
 }
 
-pub(crate) async fn insert_todo(connection: &Connection, todo: Todo) {
-    connection
-        .call_unwrap(move |connection| {
-            connection
-                .execute(
-                    "INSERT INTO todos (id, name, completed) VALUES (?1, ?2, ?3)",
-                    (todo.id, todo.name, todo.completed),
-                )
-                .unwrap();
+/// The position after the last todo, so new todos are listed at the end
+const NEXT_POSITION: &str = "(SELECT coalesce(max(position), 0) + 1 FROM {todos})";
+
+/// The ASCII unit separator, which won't show up in a tag name
+const TAG_SEPARATOR: char = '\u{1f}';
+
+/// Fills in the `{name}` placeholders in `sql` with the names of the tables belonging to `table`.
+///
+/// `{todos}` is the table itself, the names of the other tables, indexes and triggers are
+/// prefixed with it, unless it is the default table.
+fn sql(table: &TableName, sql: &str) -> String {
+    let mut rendered = String::with_capacity(sql.len());
+    let mut rest = sql;
+
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .expect("unclosed placeholder in SQL");
+        let name = &rest[start + 1..end];
+
+        rendered.push_str(&rest[..start]);
+        if name == "todos" {
+            rendered.push_str(table.as_str());
+        } else {
+            if !table.is_default() {
+                rendered.push_str(table.as_str());
+                rendered.push('_');
+            }
+            rendered.push_str(name);
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+fn todo_from_row(row: &rusqlite::Row) -> Result<Todo, rusqlite::Error> {
+    Ok(Todo {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        completed: row.get(2)?,
+        due_date: row.get(3)?,
+        priority: row.get(4)?,
+        tags: {
+            let tags: Option<String> = row.get(5)?;
+            let mut tags: Vec<String> = tags
+                .map(|tags| tags.split(TAG_SEPARATOR).map(Into::into).collect())
+                .unwrap_or_default();
+            tags.sort();
+            tags
+        },
+        deleted_at: row.get(6)?,
+        version: row.get(7)?,
+        parent_id: row.get(8)?,
+        completed_at: row.get(9)?,
+        recurrence: row.get(10)?,
+        assignee: row.get(11)?,
+        description: row.get(12)?,
+        archived: row.get(13)?,
+        user_id: row.get(14)?,
+    })
+}
+
+/// The assignee to store, blank names mean the todo is unassigned
+fn normalize_assignee(assignee: Option<&str>) -> Option<&str> {
+    assignee
+        .map(str::trim)
+        .filter(|assignee| !assignee.is_empty())
+}
+
+/// The description to store, an empty description is the same as none. Unlike assignees,
+/// descriptions are kept as they are, whitespace and all
+fn normalize_description(description: Option<&str>) -> Option<&str> {
+    description.filter(|description| !description.is_empty())
+}
+
+/// Replaces the tags of a todo, creating any tags that don't exist yet
+fn set_tags(
+    connection: &rusqlite::Connection,
+    table: &TableName,
+    todo_id: u32,
+    tags: &[String],
+) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        &sql(table, "DELETE FROM {todo_tags} WHERE todo_id = ?1"),
+        [todo_id],
+    )?;
+
+    for tag in tags {
+        connection.execute(
+            &sql(table, "INSERT OR IGNORE INTO {tags} (name) VALUES (?1)"),
+            [tag],
+        )?;
+        connection.execute(
+            &sql(
+                table,
+                "INSERT OR IGNORE INTO {todo_tags} (todo_id, tag_id) \
+                 SELECT ?1, id FROM {tags} WHERE name = ?2",
+            ),
+            (todo_id, tag),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Priorities are stored as integers so that sorting by them sorts by importance
+impl ToSql for Priority {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let value: i64 = match self {
+            Priority::Low => 0,
+            Priority::Medium => 1,
+            Priority::High => 2,
+        };
+        Ok(value.into())
+    }
+}
+
+impl FromSql for Priority {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_i64()? {
+            0 => Ok(Priority::Low),
+            1 => Ok(Priority::Medium),
+            2 => Ok(Priority::High),
+            other => Err(FromSqlError::OutOfRange(other)),
+        }
+    }
+}
+
+impl ToSql for Recurrence {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let value = match self {
+            Recurrence::Daily => "daily",
+            Recurrence::Weekly => "weekly",
+            Recurrence::Monthly => "monthly",
+        };
+        Ok(value.into())
+    }
+}
+
+impl FromSql for Recurrence {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "daily" => Ok(Recurrence::Daily),
+            "weekly" => Ok(Recurrence::Weekly),
+            "monthly" => Ok(Recurrence::Monthly),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+// Todos without a user have a NULL user_id, so compare with `user_id IS ?` rather than `=`
+impl ToSql for UserId {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self.as_deref() {
+            Some(user_id) => ToSqlOutput::Borrowed(ValueRef::Text(user_id.as_bytes())),
+            None => ToSqlOutput::Owned(Value::Null),
         })
-        .await
+    }
 }
 
-pub(crate) async fn get_todo(connection: &Connection, id: u32) -> Option<Todo> {
-    connection
-        .call(move |conn| {
-            let mut stmt = conn
-                .prepare("SELECT id, name, completed FROM todos WHERE id = ?1;")
-                .unwrap();
-            let result = stmt.query_row([id], |row| {
-                Ok(Todo {
-                    id: row.get_unwrap(0),
-                    name: row.get_unwrap(1),
-                    completed: row.get_unwrap(2),
-                })
+// Actions are written as text by the triggers that record history
+impl FromSql for ChangeAction {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "created" => Ok(ChangeAction::Created),
+            "updated" => Ok(ChangeAction::Updated),
+            "toggled" => Ok(ChangeAction::Toggled),
+            "deleted" => Ok(ChangeAction::Deleted),
+            "restored" => Ok(ChangeAction::Restored),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// Sets up a freshly opened connection, before anything else runs on it.
+///
+/// WAL lets readers keep reading while a write is in progress. Foreign keys are off by default
+/// in SQLite, and have to be turned on for every connection.
+pub(crate) fn configure(connection: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    // In-memory databases ignore this and stay in the "memory" journal mode
+    connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+    connection.pragma_update(None, "foreign_keys", "ON")?;
+    Ok(())
+}
+
+/// Runs SQLite's integrity check, returning the problems it found or an empty list if there are
+/// none. Files that aren't databases at all fail with an error instead.
+pub(crate) async fn integrity_check(pool: &ConnectionPool) -> Result<Vec<String>, rusqlite::Error> {
+    pool.call_unwrap(|connection| {
+        let mut stmt = connection.prepare("PRAGMA integrity_check")?;
+        let problems = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .filter(|result| !matches!(result.as_deref(), Ok("ok")))
+            .collect();
+        problems
+    })
+    .await
+}
+
+/// Every change to the schema, in the order they are applied.
+///
+/// Never edit a migration that has been released, add a new one to the end instead. Table names
+/// are written as placeholders, see [`sql`], so every table gets its own copy of the schema.
+const MIGRATIONS: &[&str] = &[
+    CREATE_TODO_TABLE_SQL,
+    include_str!("./migrations/0002_create_tags.sql"),
+    include_str!("./migrations/0003_add_deleted_at.sql"),
+    include_str!("./migrations/0004_create_idempotency_keys.sql"),
+    include_str!("./migrations/0005_add_version.sql"),
+    include_str!("./migrations/0006_create_todo_history.sql"),
+    include_str!("./migrations/0007_add_parent_id.sql"),
+    include_str!("./migrations/0008_add_completed_at.sql"),
+    include_str!("./migrations/0009_add_recurrence.sql"),
+    include_str!("./migrations/0010_add_position.sql"),
+    include_str!("./migrations/0011_add_assignee.sql"),
+    include_str!("./migrations/0012_add_description.sql"),
+    include_str!("./migrations/0013_add_archived.sql"),
+    include_str!("./migrations/0014_add_user_id.sql"),
+    include_str!("./migrations/0015_limit_name_length.sql"),
+    include_str!("./migrations/0016_scope_idempotency_keys.sql"),
+];
+
+/// Brings the schema up to date by applying every migration that hasn't been applied yet.
+///
+/// Applied migrations are recorded in the `schema_version` table of each todo table, so running
+/// this again is a no-op. Returns the number of migrations that were applied.
+pub(crate) async fn migrate(
+    pool: &ConnectionPool,
+    table: &TableName,
+) -> Result<usize, rusqlite::Error> {
+    let table = table.clone();
+    pool.call_unwrap(move |connection| {
+        let transaction =
+            connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+        transaction.execute(
+            &sql(
+                &table,
+                "CREATE TABLE IF NOT EXISTS {schema_version} (
+                   version    INTEGER PRIMARY KEY NOT NULL,
+                   applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                 )",
+            ),
+            [],
+        )?;
+
+        let current: usize = transaction.query_row(
+            &sql(
+                &table,
+                "SELECT COALESCE(MAX(version), 0) FROM {schema_version}",
+            ),
+            [],
+            |row| row.get(0),
+        )?;
+
+        let pending = MIGRATIONS.iter().enumerate().skip(current);
+        let mut applied = 0;
+        for (index, migration) in pending {
+            transaction.execute_batch(&sql(&table, migration))?;
+            transaction.execute(
+                &sql(&table, "INSERT INTO {schema_version} (version) VALUES (?1)"),
+                [index + 1],
+            )?;
+            applied += 1;
+        }
+
+        transaction.commit()?;
+        Ok(applied)
+    })
+    .await
+}
+
+/// How many times a write is retried while another connection holds the lock
+const BUSY_RETRIES: u32 = 5;
+
+/// How long to wait before the first retry, the wait doubles with every retry after it
+const BUSY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Whether the error was caused by another connection holding a lock on the database
+fn is_busy(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+/// Runs a write, retrying it with exponential backoff while the database is busy.
+///
+/// The busy timeout already makes SQLite wait for locks, but a transaction that would deadlock
+/// fails right away, so the whole transaction has to be run again.
+async fn retry_busy<T, F, Fut>(mut write: F) -> Result<T, rusqlite::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, rusqlite::Error>>,
+{
+    let mut backoff = BUSY_BACKOFF;
+    for _ in 0..BUSY_RETRIES {
+        match write().await {
+            Err(error) if is_busy(&error) => {
+                tracing::debug!("database is busy, retrying in {backoff:?}");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    write().await
+}
+
+/// Inserts a todo, returning the id it was stored with.
+///
+/// If an idempotency key is given and the user already created a todo with it, nothing is
+/// inserted and the id of that todo is returned instead. Keys are forgotten after 24 hours.
+///
+/// With `unique_name` set, the todo isn't inserted if the user has a todo with the same name.
+/// Names are compared with `COLLATE NOCASE`, which only ignores the case of ASCII letters.
+pub(crate) async fn insert_todo(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    todo: NewTodo,
+    idempotency_key: Option<String>,
+    unique_name: bool,
+) -> Result<Inserted, RepositoryError> {
+    let result = retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let todo = todo.clone();
+        let idempotency_key = idempotency_key.clone();
+        pool.call_unwrap(move |connection| {
+            // Take the write lock up front, so two requests with the same key can't both insert
+            let transaction =
+                connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+            if let Some(key) = &idempotency_key {
+                transaction.execute(
+                    &sql(
+                        &table,
+                        "DELETE FROM {idempotency_keys} \
+                         WHERE created_at < datetime('now', '-24 hours')",
+                    ),
+                    [],
+                )?;
+
+                let existing = transaction
+                    .query_row(
+                        &sql(
+                            &table,
+                            "SELECT todo_id FROM {idempotency_keys} \
+                             WHERE user_id = coalesce(?1, '') AND key = ?2",
+                        ),
+                        (&user, key),
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+
+                if let Some(id) = existing {
+                    return Ok(Ok(Inserted { id, replayed: true }));
+                }
+            }
+
+            // Checked in the same transaction as the insert, so two requests can't both pass it
+            if unique_name {
+                let duplicate = transaction
+                    .query_row(
+                        &sql(
+                            &table,
+                            "SELECT 1 FROM {todos} WHERE name = ?1 COLLATE NOCASE \
+                             AND user_id IS ?2 AND deleted_at IS NULL",
+                        ),
+                        (&todo.name, &user),
+                        |_| Ok(()),
+                    )
+                    .optional()?
+                    .is_some();
+
+                if duplicate {
+                    return Ok(Err(RepositoryError::DuplicateName));
+                }
+            }
+
+            let id = insert(&transaction, &table, &user, todo)?;
+
+            if let Some(key) = &idempotency_key {
+                transaction.execute(
+                    &sql(
+                        &table,
+                        "INSERT INTO {idempotency_keys} (user_id, key, todo_id) \
+                         VALUES (coalesce(?1, ''), ?2, ?3)",
+                    ),
+                    (&user, key, id),
+                )?;
+            }
+
+            transaction.commit()?;
+            Ok(Ok(Inserted {
+                id,
+                replayed: false,
+            }))
+        })
+    })
+    .await;
+
+    result.unwrap_or_else(|error| Err(error.into()))
+}
+
+/// The id of the todo the user created with the idempotency key, unless the key is older than
+/// 24 hours and about to be forgotten
+pub(crate) async fn idempotent_todo_id(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    idempotency_key: &str,
+) -> Result<Option<u32>, rusqlite::Error> {
+    let table = table.clone();
+    let user = user.clone();
+    let idempotency_key = idempotency_key.to_owned();
+    pool.call_unwrap(move |connection| {
+        connection
+            .query_row(
+                &sql(
+                    &table,
+                    "SELECT todo_id FROM {idempotency_keys} \
+                     WHERE user_id = coalesce(?1, '') AND key = ?2 \
+                     AND created_at >= datetime('now', '-24 hours')",
+                ),
+                (&user, &idempotency_key),
+                |row| row.get(0),
+            )
+            .optional()
+    })
+    .await
+}
+
+/// Inserts all todos in a single transaction, so either all or none of them are stored
+pub(crate) async fn insert_todos(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    todos: Vec<NewTodo>,
+) -> Result<Vec<u32>, rusqlite::Error> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let todos = todos.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let ids = todos
+                .into_iter()
+                .map(|todo| insert(&transaction, &table, &user, todo))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            transaction.commit()?;
+            Ok(ids)
+        })
+    })
+    .await
+}
+
+/// Imports todos with their ids, e.g. from a backup, in a single transaction.
+///
+/// The todos are imported for `user`, todos with an id another user already has are skipped.
+pub(crate) async fn import_todos(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    todos: Vec<Todo>,
+    on_conflict: OnConflict,
+) -> Result<ImportSummary, rusqlite::Error> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let todos = todos.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+            let mut summary = ImportSummary::default();
+
+            for todo in todos {
+                // `None` if the id is free, otherwise whether the todo is the user's own
+                let owned: Option<bool> = transaction
+                    .query_row(
+                        &sql(&table, "SELECT user_id IS ?2 FROM {todos} WHERE id = ?1"),
+                        (todo.id, &user),
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+
+                match (owned, on_conflict) {
+                    (Some(false), _) | (Some(true), OnConflict::Skip) => {
+                        summary.skipped += 1;
+                        continue;
+                    }
+                    (Some(true), OnConflict::Replace) => {
+                        transaction.execute(
+                            &sql(
+                                &table,
+                                "UPDATE {todos} SET name = ?1, completed = ?2, due_date = ?3, \
+                                 priority = ?4, deleted_at = ?5, parent_id = ?6, \
+                                 completed_at = ?7, recurrence = ?8, assignee = ?9, \
+                                 description = ?10, archived = ?11, version = version + 1 \
+                                 WHERE id = ?12",
+                            ),
+                            (
+                                &todo.name,
+                                todo.completed,
+                                todo.due_date,
+                                todo.priority,
+                                todo.deleted_at,
+                                todo.parent_id,
+                                todo.completed_at,
+                                todo.recurrence,
+                                normalize_assignee(todo.assignee.as_deref()),
+                                normalize_description(todo.description.as_deref()),
+                                todo.archived,
+                                todo.id,
+                            ),
+                        )?;
+                        summary.replaced += 1;
+                    }
+                    (None, _) => {
+                        transaction.execute(
+                            &sql(
+                                &table,
+                                &format!(
+                                    "INSERT INTO {{todos}} \
+                                     (id, name, completed, due_date, priority, deleted_at, \
+                                     parent_id, completed_at, recurrence, assignee, description, \
+                                     archived, user_id, position) \
+                                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, \
+                                     ?13, {NEXT_POSITION})"
+                                ),
+                            ),
+                            (
+                                todo.id,
+                                &todo.name,
+                                todo.completed,
+                                todo.due_date,
+                                todo.priority,
+                                todo.deleted_at,
+                                todo.parent_id,
+                                todo.completed_at,
+                                todo.recurrence,
+                                normalize_assignee(todo.assignee.as_deref()),
+                                normalize_description(todo.description.as_deref()),
+                                todo.archived,
+                                &user,
+                            ),
+                        )?;
+                        summary.inserted += 1;
+                    }
+                }
+
+                set_tags(&transaction, &table, todo.id, &todo.tags)?;
+            }
+
+            transaction.commit()?;
+            Ok(summary)
+        })
+    })
+    .await
+}
+
+/// Inserts a todo and its tags for `user`, callers should run this in a transaction
+fn insert(
+    connection: &rusqlite::Connection,
+    table: &TableName,
+    user: &UserId,
+    todo: NewTodo,
+) -> Result<u32, rusqlite::Error> {
+    // The foreign key only checks that the parent exists, not that it is the user's own
+    if let Some(parent_id) = todo.parent_id {
+        let owns_parent = connection
+            .query_row(
+                &sql(
+                    table,
+                    "SELECT 1 FROM {todos} WHERE id = ?1 AND user_id IS ?2",
+                ),
+                (parent_id, user),
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+
+        if !owns_parent {
+            return Err(foreign_key_violation());
+        }
+    }
+
+    connection.execute(
+        &sql(
+            table,
+            &format!(
+                "INSERT INTO {{todos}} \
+                 (id, name, completed, due_date, priority, parent_id, completed_at, recurrence, \
+                 assignee, description, user_id, position) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, {NEXT_POSITION})"
+            ),
+        ),
+        (
+            todo.id,
+            todo.name,
+            todo.completed,
+            todo.due_date,
+            todo.priority,
+            todo.parent_id,
+            todo.completed.then(Utc::now),
+            todo.recurrence,
+            normalize_assignee(todo.assignee.as_deref()),
+            normalize_description(todo.description.as_deref()),
+            user,
+        ),
+    )?;
+
+    let id = connection.last_insert_rowid() as u32;
+    set_tags(connection, table, id, &todo.tags)?;
+    Ok(id)
+}
+
+/// The error SQLite gives for a parent that doesn't exist, for parents that belong to another user
+fn foreign_key_violation() -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error {
+            code: rusqlite::ErrorCode::ConstraintViolation,
+            extended_code: rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY,
+        },
+        Some("the parent todo belongs to another user".into()),
+    )
+}
+
+impl From<rusqlite::Error> for RepositoryError {
+    fn from(error: rusqlite::Error) -> Self {
+        if is_unique_violation(&error) {
+            RepositoryError::Conflict
+        } else if is_foreign_key_violation(&error) {
+            RepositoryError::InvalidParent
+        } else if is_name_length_violation(&error) {
+            RepositoryError::NameTooLong
+        } else {
+            RepositoryError::Other(error.into())
+        }
+    }
+}
+
+/// Whether the error was caused by inserting an id or unique value that already exists
+fn is_unique_violation(error: &rusqlite::Error) -> bool {
+    matches!(
+        error,
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY
+                || err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE
+    )
+}
+
+/// Whether the error was caused by referring to a parent todo that doesn't exist
+fn is_foreign_key_violation(error: &rusqlite::Error) -> bool {
+    matches!(
+        error,
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY
+    )
+}
+
+/// Whether the error was caused by a name longer than the schema allows
+fn is_name_length_violation(error: &rusqlite::Error) -> bool {
+    matches!(
+        error,
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_TRIGGER
+                || err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_CHECK
+    )
+}
+
+/// Fetches one of the user's todos, deleted todos are treated as if they don't exist
+pub(crate) async fn get_todo(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+) -> Result<Option<Todo>, rusqlite::Error> {
+    let table = table.clone();
+    let user = user.clone();
+    pool.call_unwrap(move |conn| {
+        let mut stmt = conn.prepare(&sql(
+            &table,
+            &format!("{SELECT_TODOS} WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL;"),
+        ))?;
+
+        stmt.query_row((id, &user), todo_from_row).optional()
+    })
+    .await
+}
+
+/// Whether the user has a todo, without fetching it. Deleted todos don't count
+pub(crate) async fn todo_exists(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+) -> Result<bool, rusqlite::Error> {
+    let table = table.clone();
+    let user = user.clone();
+    pool.call_unwrap(move |conn| {
+        let mut stmt = conn.prepare(&sql(
+            &table,
+            "SELECT 1 FROM {todos} WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL LIMIT 1;",
+        ))?;
+
+        stmt.exists((id, &user))
+    })
+    .await
+}
+
+/// Picks one of the incomplete todos at random, or `None` if there are none. Archived todos have
+/// been dealt with, so they aren't picked either
+pub(crate) async fn random_pending(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+) -> Result<Option<Todo>, rusqlite::Error> {
+    let table = table.clone();
+    let user = user.clone();
+    pool.call_unwrap(move |conn| {
+        let mut stmt = conn.prepare(&sql(
+            &table,
+            &format!(
+                "{SELECT_TODOS} WHERE user_id IS ?1 AND completed = 0 AND archived = 0 \
+                 AND deleted_at IS NULL ORDER BY RANDOM() LIMIT 1;"
+            ),
+        ))?;
+
+        stmt.query_row([&user], todo_from_row).optional()
+    })
+    .await
+}
+
+pub(crate) async fn get_todos(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+) -> Result<Vec<Todo>, rusqlite::Error> {
+    let filter = Filter {
+        user: user.clone(),
+        ..Filter::default()
+    };
+    get_todos_filtered(pool, table, filter, Sort::default(), None).await
+}
+
+impl Filter {
+    /// Appends the WHERE clause for this filter to `sql`, pushing its parameters to `params`
+    fn push_where_clause(&self, sql: &mut String, params: &mut Vec<Value>) {
+        // Declared before the conditions, which borrow it
+        let id_in;
+        let mut conditions = vec!["user_id IS ?"];
+        params.push(self.user.as_deref().map(str::to_owned).into());
+
+        if !self.include_deleted {
+            conditions.push("deleted_at IS NULL");
+        }
+
+        if let Some(completed) = self.completed {
+            conditions.push("completed = ?");
+            params.push(completed.into());
+        }
+
+        if let Some(search) = &self.name_contains {
+            // LIKE is case-insensitive in SQLite, but wildcards in the search need escaping
+            conditions.push("name LIKE '%' || ? || '%' ESCAPE '\\'");
+            params.push(escape_like(search).into());
+        }
+
+        if let Some(overdue) = self.overdue {
+            // Todos without a due date are never overdue
+            conditions.push(if overdue {
+                "(completed = 0 AND due_date IS NOT NULL AND julianday(due_date) < julianday(?))"
+            } else {
+                "NOT (completed = 0 AND due_date IS NOT NULL AND julianday(due_date) < julianday(?))"
+            });
+            params.push(timestamp(Utc::now()));
+        }
+
+        if let Some(tag) = &self.tag {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM {todo_tags} JOIN {tags} ON {tags}.id = {todo_tags}.tag_id \
+                 WHERE {todo_tags}.todo_id = {todos}.id AND {tags}.name = ?)",
+            );
+            params.push(tag.clone().into());
+        }
+
+        if let Some(top_level) = self.top_level {
+            conditions.push(if top_level {
+                "parent_id IS NULL"
+            } else {
+                "parent_id IS NOT NULL"
             });
+        }
+
+        if let Some(parent_id) = self.parent_id {
+            conditions.push("parent_id = ?");
+            params.push(parent_id.into());
+        }
+
+        if let Some(completed_after) = self.completed_after {
+            conditions.push("julianday(completed_at) > julianday(?)");
+            params.push(timestamp(completed_after));
+        }
+
+        if let Some(due_before) = self.due_before {
+            conditions.push("julianday(due_date) < julianday(?)");
+            params.push(timestamp(due_before));
+        }
+
+        if let Some(due_after) = self.due_after {
+            conditions.push("julianday(due_date) > julianday(?)");
+            params.push(timestamp(due_after));
+        }
+
+        if let Some(assignee) = &self.assignee {
+            conditions.push("assignee = ?");
+            params.push(assignee.clone().into());
+        }
+
+        if let Some(archived) = self.archived {
+            conditions.push("archived = ?");
+            params.push(archived.into());
+        }
+
+        if let Some(ids) = &self.ids {
+            id_in = format!("id IN ({})", vec!["?"; ids.len()].join(", "));
+            conditions.push(&id_in);
+            params.extend(ids.iter().map(|&id| Value::from(id)));
+        }
+
+        sql.push_str(" WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+    }
+}
+
+/// Formats a timestamp the same way rusqlite stores them
+fn timestamp(value: DateTime<Utc>) -> Value {
+    Value::Text(value.format("%F %T%.f%:z").to_string())
+}
+
+/// Escapes the LIKE wildcards in `value` so they match literally
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+impl SortField {
+    /// The column to sort by, only ever one of a fixed set so it is safe to put in SQL
+    fn column(self) -> &'static str {
+        match self {
+            SortField::Position => "position",
+            SortField::Id => "id",
+            SortField::Name => "name",
+            SortField::Completed => "completed",
+            SortField::CreatedAt => "created_at",
+            SortField::Priority => "priority",
+        }
+    }
+}
+
+impl SortOrder {
+    fn keyword(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+impl Sort {
+    fn push_order_by_clause(&self, sql: &mut String) {
+        let order = self.order.keyword();
+
+        sql.push_str(&format!(" ORDER BY {} {order}", self.field.column()));
+        if self.field != SortField::Id {
+            // Keep todos that compare equal in a stable order
+            sql.push_str(&format!(", id {order}"));
+        }
+    }
+}
+
+/// Fetches up to `limit` todos matching the filter with an id above `after`, or from the first
+/// todo without one, ordered by id.
+///
+/// Unlike an offset, continuing from the last id seen doesn't make SQLite skip over every earlier
+/// row again, so a whole table can be read one batch at a time.
+pub(crate) async fn get_todos_after(
+    pool: &ConnectionPool,
+    table: &TableName,
+    filter: Filter,
+    after: Option<u32>,
+    limit: u32,
+) -> Result<Vec<Todo>, rusqlite::Error> {
+    let table = table.clone();
+    pool.call_unwrap(move |conn| {
+        let mut query = SELECT_TODOS.to_owned();
+        let mut params: Vec<Value> = Vec::new();
+
+        filter.push_where_clause(&mut query, &mut params);
+        if let Some(after) = after {
+            query.push_str(" AND id > ?");
+            params.push(after.into());
+        }
+        query.push_str(" ORDER BY id LIMIT ?");
+        params.push(limit.into());
+
+        let mut stmt = conn.prepare(&sql(&table, &query))?;
+        let todos = stmt
+            .query(rusqlite::params_from_iter(params))?
+            .mapped(todo_from_row)
+            .collect::<Result<_, _>>()?;
+
+        Ok(todos)
+    })
+    .await
+}
+
+/// Returns todos in the given order, optionally only those matching the filter
+/// and only those within the given page
+pub(crate) async fn get_todos_filtered(
+    pool: &ConnectionPool,
+    table: &TableName,
+    filter: Filter,
+    sort: Sort,
+    page: Option<Page>,
+) -> Result<Vec<Todo>, rusqlite::Error> {
+    let table = table.clone();
+    pool.call_unwrap(move |connection| {
+        let mut query = SELECT_TODOS.to_owned();
+        let mut params: Vec<Value> = Vec::new();
+
+        filter.push_where_clause(&mut query, &mut params);
+
+        sort.push_order_by_clause(&mut query);
 
-            Ok(result?)
+        if let Some(page) = page {
+            query.push_str(" LIMIT ? OFFSET ?");
+            params.push(page.limit.into());
+            params.push(page.offset.into());
+        }
+
+        let mut stmt = connection.prepare(&sql(&table, &query))?;
+        let result: Result<Vec<_>, _> = stmt
+            .query(rusqlite::params_from_iter(params))?
+            .mapped(todo_from_row)
+            .collect();
+        result
+    })
+    .await
+}
+
+/// Returns the todos matching the criteria along with how many match in total, both read in
+/// one transaction so they agree with each other
+pub(crate) async fn search_todos(
+    pool: &ConnectionPool,
+    table: &TableName,
+    criteria: SearchCriteria,
+) -> Result<SearchResults, rusqlite::Error> {
+    let table = table.clone();
+    pool.call_unwrap(move |connection| {
+        let transaction = connection.transaction()?;
+
+        let mut conditions = String::new();
+        let mut params: Vec<Value> = Vec::new();
+        criteria
+            .filter
+            .push_where_clause(&mut conditions, &mut params);
+
+        let total = transaction.query_row(
+            &sql(
+                &table,
+                &format!("SELECT count(*) FROM {{todos}}{conditions}"),
+            ),
+            rusqlite::params_from_iter(&params),
+            |row| row.get(0),
+        )?;
+
+        let mut query = format!("{SELECT_TODOS}{conditions}");
+        criteria.sort.push_order_by_clause(&mut query);
+        if let Some(page) = criteria.page {
+            query.push_str(" LIMIT ? OFFSET ?");
+            params.push(page.limit.into());
+            params.push(page.offset.into());
+        }
+
+        let todos = transaction
+            .prepare(&sql(&table, &query))?
+            .query(rusqlite::params_from_iter(params))?
+            .mapped(todo_from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SearchResults { todos, total })
+    })
+    .await
+}
+
+/// Selects the id of a todo and the ids of all its subtasks, their subtasks and so on
+const SUBTREE: &str = "WITH RECURSIVE subtree(id) AS ( \
+    SELECT ?1 \
+    UNION SELECT {todos}.id FROM {todos} JOIN subtree ON {todos}.parent_id = subtree.id)";
+
+/// Replaces a todo, as long as it is still at the version the caller last saw.
+///
+/// The stored version is incremented, so anyone still holding the old version gets a conflict.
+pub(crate) async fn update_todo(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    updated: Todo,
+) -> Result<(), UpdateError> {
+    let result = retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let updated = updated.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let previous_parent_id: Option<u32> = transaction
+                .query_row(
+                    &sql(
+                        &table,
+                        "SELECT parent_id FROM {todos} WHERE id = ?1 AND user_id IS ?2",
+                    ),
+                    (updated.id, &user),
+                    |row| row.get(0),
+                )
+                .optional()?
+                .flatten();
+
+            let rows = transaction.execute(
+                &sql(
+                    &table,
+                    &format!(
+                        "UPDATE {{todos}} SET name = ?1, completed = ?2, due_date = ?3, \
+                         priority = ?4, parent_id = ?5, recurrence = ?6, assignee = ?7, \
+                         description = ?8, {}, version = version + 1 \
+                         WHERE id = ?9 AND user_id IS ?11 AND deleted_at IS NULL \
+                         AND version = ?10",
+                        set_completed_at("?2")
+                    ),
+                ),
+                (
+                    &updated.name,
+                    updated.completed,
+                    updated.due_date,
+                    updated.priority,
+                    updated.parent_id,
+                    updated.recurrence,
+                    normalize_assignee(updated.assignee.as_deref()),
+                    normalize_description(updated.description.as_deref()),
+                    updated.id,
+                    updated.version,
+                    &user,
+                ),
+            )?;
+
+            if rows == 0 {
+                // Nothing matched, either because the todo is gone or because its version moved on
+                let exists = transaction
+                    .query_row(
+                        &sql(
+                            &table,
+                            "SELECT 1 FROM {todos} \
+                             WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL",
+                        ),
+                        (updated.id, &user),
+                        |_| Ok(()),
+                    )
+                    .optional()?
+                    .is_some();
+
+                return Ok(Err(if exists {
+                    UpdateError::VersionConflict
+                } else {
+                    UpdateError::NotFound
+                }));
+            }
+
+            let parent_changed = updated.parent_id != previous_parent_id;
+            if let Some(parent_id) = updated.parent_id.filter(|_| parent_changed) {
+                // Dropping the transaction rolls the update back
+                if !is_valid_parent(&transaction, &table, &user, updated.id, parent_id)? {
+                    return Ok(Err(UpdateError::InvalidParent));
+                }
+            }
+
+            set_tags(&transaction, &table, updated.id, &updated.tags)?;
+
+            transaction.commit()?;
+            Ok(Ok(()))
         })
-        .await
-        .ok()
-}
-
-pub(crate) async fn get_todos(connection: &Connection) -> Vec<Todo> {
-    connection
-        .call_unwrap(|connection| {
-            let mut stmt = connection
-                .prepare("SELECT id, name, completed FROM todos;")
-                .unwrap();
-            let result: Result<Vec<_>, _> = stmt
-                .query([])
-                .unwrap()
-                .mapped(|row| {
-                    Ok(Todo {
-                        id: row.get_unwrap(0),
-                        name: row.get_unwrap(1),
-                        completed: row.get_unwrap(2),
-                    })
-                })
+    })
+    .await;
+
+    result.unwrap_or_else(|error| Err(UpdateError::Other(error.into())))
+}
+
+/// Whether `parent_id` is an existing todo of the user that `id` can be a subtask of. Moving a
+/// todo under itself or one of its own subtasks would create a cycle
+fn is_valid_parent(
+    connection: &rusqlite::Connection,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+    parent_id: u32,
+) -> Result<bool, rusqlite::Error> {
+    Ok(connection
+        .query_row(
+            &sql(
+                table,
+                &format!(
+                    "{SUBTREE} SELECT 1 FROM {{todos}} WHERE id = ?2 AND user_id IS ?3 \
+                     AND deleted_at IS NULL AND id NOT IN subtree"
+                ),
+            ),
+            (id, parent_id, user),
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some())
+}
+
+/// Creates the todo with the given id, or replaces it if it already exists, with a single
+/// `INSERT ... ON CONFLICT DO UPDATE`.
+///
+/// Replacing follows the same rules as [`update_todo`], so the version must match and deleted
+/// todos are not found rather than brought back. Another user's todo is not found either, so its
+/// id can't be taken over.
+pub(crate) async fn upsert_todo(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    todo: Todo,
+) -> Result<Upserted, UpdateError> {
+    let result = retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let todo = todo.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            // `None` if there is no todo to replace, otherwise the parent it has now
+            let previous_parent_id: Option<Option<u32>> = transaction
+                .query_row(
+                    &sql(
+                        &table,
+                        "SELECT parent_id FROM {todos} WHERE id = ?1 AND user_id IS ?2",
+                    ),
+                    (todo.id, &user),
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            let upserted = transaction.execute(
+                &sql(
+                    &table,
+                    &format!(
+                        "INSERT INTO {{todos}} \
+                         (id, name, completed, due_date, priority, parent_id, completed_at, \
+                         recurrence, assignee, description, user_id, position) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?12, {NEXT_POSITION}) \
+                         ON CONFLICT(id) DO UPDATE SET name = excluded.name, \
+                         completed = excluded.completed, due_date = excluded.due_date, \
+                         priority = excluded.priority, parent_id = excluded.parent_id, \
+                         recurrence = excluded.recurrence, assignee = excluded.assignee, \
+                         description = excluded.description, {}, version = version + 1 \
+                         WHERE user_id IS excluded.user_id AND deleted_at IS NULL \
+                         AND version = ?11",
+                        set_completed_at("excluded.completed")
+                    ),
+                ),
+                (
+                    todo.id,
+                    &todo.name,
+                    todo.completed,
+                    todo.due_date,
+                    todo.priority,
+                    todo.parent_id,
+                    todo.completed.then(Utc::now),
+                    todo.recurrence,
+                    normalize_assignee(todo.assignee.as_deref()),
+                    normalize_description(todo.description.as_deref()),
+                    todo.version,
+                    &user,
+                ),
+            );
+
+            match upserted {
+                Ok(0) => {
+                    // The todo exists, but is deleted, another user's or its version moved on
+                    let exists = transaction
+                        .query_row(
+                            &sql(
+                                &table,
+                                "SELECT 1 FROM {todos} \
+                                 WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL",
+                            ),
+                            (todo.id, &user),
+                            |_| Ok(()),
+                        )
+                        .optional()?
+                        .is_some();
+
+                    return Ok(Err(if exists {
+                        UpdateError::VersionConflict
+                    } else {
+                        UpdateError::NotFound
+                    }));
+                }
+                Ok(_) => {}
+                Err(error) if is_foreign_key_violation(&error) => {
+                    return Ok(Err(UpdateError::InvalidParent));
+                }
+                Err(error) => return Err(error),
+            }
+
+            let parent_changed = todo.parent_id != previous_parent_id.flatten();
+            if let Some(parent_id) = todo.parent_id.filter(|_| parent_changed) {
+                if !is_valid_parent(&transaction, &table, &user, todo.id, parent_id)? {
+                    return Ok(Err(UpdateError::InvalidParent));
+                }
+            }
+
+            set_tags(&transaction, &table, todo.id, &todo.tags)?;
+
+            transaction.commit()?;
+            Ok(Ok(if previous_parent_id.is_some() {
+                Upserted::Replaced
+            } else {
+                Upserted::Created
+            }))
+        })
+    })
+    .await;
+
+    result.unwrap_or_else(|error| Err(UpdateError::Other(error.into())))
+}
+
+/// Lists the todos with the given ids in the given order.
+///
+/// The todos swap places among the positions they already had, so todos that aren't listed stay
+/// where they are. This lets a client reorder a filtered list without moving the todos it hides.
+/// Only the user's own todos are renumbered.
+pub(crate) async fn reorder_todos(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    ids: Vec<u32>,
+) -> Result<(), ReorderError> {
+    let result = retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let ids = ids.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            for &id in &ids {
+                let exists = transaction
+                    .query_row(
+                        &sql(
+                            &table,
+                            "SELECT 1 FROM {todos} \
+                             WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL",
+                        ),
+                        (id, &user),
+                        |_| Ok(()),
+                    )
+                    .optional()?
+                    .is_some();
+
+                if !exists {
+                    return Ok(Err(ReorderError::NotFound(id)));
+                }
+            }
+
+            let mut order = transaction
+                .prepare(&sql(
+                    &table,
+                    "SELECT id FROM {todos} WHERE user_id IS ?1 ORDER BY position, id",
+                ))?
+                .query_map([&user], |row| row.get::<_, u32>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let slots: Vec<usize> = order
+                .iter()
+                .enumerate()
+                .filter(|(_, id)| ids.contains(id))
+                .map(|(slot, _)| slot)
                 .collect();
-            result
+            for (slot, &id) in slots.into_iter().zip(&ids) {
+                order[slot] = id;
+            }
+
+            // Number every todo again, which also evens out gaps and ties left by older versions
+            let mut update = transaction.prepare(&sql(
+                &table,
+                "UPDATE {todos} SET position = ?1 WHERE id = ?2",
+            ))?;
+            for (position, id) in order.into_iter().enumerate() {
+                update.execute((position + 1, id))?;
+            }
+            drop(update);
+
+            transaction.commit()?;
+            Ok(Ok(()))
+        })
+    })
+    .await;
+
+    result.unwrap_or_else(|error| Err(ReorderError::Repository(error.into())))
+}
+
+/// Moves one of the user's todos before or after all their other todos, by giving it a position
+/// just past theirs. Returns whether there was such a todo
+pub(crate) async fn move_todo(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+    to: MoveTo,
+) -> Result<bool, rusqlite::Error> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let exists = transaction
+                .query_row(
+                    &sql(
+                        &table,
+                        "SELECT 1 FROM {todos} \
+                         WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL",
+                    ),
+                    (id, &user),
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+
+            if !exists {
+                return Ok(false);
+            }
+
+            let edge = match to {
+                MoveTo::Top => "min(position) - 1",
+                MoveTo::Bottom => "max(position) + 1",
+            };
+            // Without other todos there is nowhere to move to, the todo is already at both ends
+            let position: Option<i64> = transaction.query_row(
+                &sql(
+                    &table,
+                    &format!("SELECT {edge} FROM {{todos}} WHERE user_id IS ?1 AND id != ?2"),
+                ),
+                (&user, id),
+                |row| row.get(0),
+            )?;
+
+            if let Some(position) = position {
+                transaction.execute(
+                    &sql(&table, "UPDATE {todos} SET position = ?1 WHERE id = ?2"),
+                    (position, id),
+                )?;
+            }
+
+            transaction.commit()?;
+            Ok(true)
+        })
+    })
+    .await
+}
+
+/// Soft-deletes a todo by setting its `deleted_at`, so it can be restored later.
+///
+/// If `cascade` is set all its subtasks are deleted too, otherwise a todo with subtasks is kept.
+/// Returns every todo that was deleted, starting with the todo itself. A dry run only looks up
+/// the todos that would be deleted.
+pub(crate) async fn delete_todo(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+    cascade: bool,
+    dry_run: bool,
+) -> Result<Vec<Todo>, DeleteError> {
+    let result = retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            // The subtree is just the todo itself, unless its subtasks are deleted too
+            let subtree = if cascade {
+                SUBTREE
+            } else {
+                "WITH subtree(id) AS (SELECT ?1)"
+            };
+            let deleted = transaction
+                .prepare(&sql(
+                    &table,
+                    &format!(
+                        "{subtree} {SELECT_TODOS} \
+                         WHERE user_id IS ?2 AND deleted_at IS NULL AND id IN subtree \
+                         ORDER BY id = ?1 DESC, id"
+                    ),
+                ))?
+                .query_map((id, &user), todo_from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // If nothing matched, the todo did not exist or was already deleted
+            if deleted.first().map(|todo| todo.id) != Some(id) {
+                return Ok(Err(DeleteError::NotFound));
+            }
+
+            let has_subtasks = transaction
+                .query_row(
+                    &sql(
+                        &table,
+                        "SELECT 1 FROM {todos} WHERE parent_id = ?1 AND deleted_at IS NULL",
+                    ),
+                    [id],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+
+            if !cascade && has_subtasks {
+                return Ok(Err(DeleteError::HasSubtasks));
+            }
+
+            if dry_run {
+                return Ok(Ok(deleted));
+            }
+
+            transaction.execute(
+                &sql(
+                    &table,
+                    &format!(
+                        "{subtree} UPDATE {{todos}} SET deleted_at = ?2 \
+                         WHERE user_id IS ?3 AND deleted_at IS NULL AND id IN subtree"
+                    ),
+                ),
+                (id, Utc::now(), &user),
+            )?;
+
+            transaction.commit()?;
+            Ok(Ok(deleted))
         })
+    })
+    .await;
+
+    result.unwrap_or_else(|error| Err(DeleteError::Other(error.into())))
+}
+
+/// Brings back one of the user's soft-deleted todos, returning whether there was one to restore
+pub(crate) async fn restore_todo(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+) -> Result<bool, rusqlite::Error> {
+    let restored = retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        pool.call_unwrap(move |connection| {
+            connection.execute(
+                &sql(
+                    &table,
+                    "UPDATE {todos} SET deleted_at = NULL \
+                     WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NOT NULL",
+                ),
+                (id, &user),
+            )
+        })
+    })
+    .await?;
+
+    // If the connection restored zero rows, it did not exist or was not deleted
+    Ok(restored > 0)
+}
+
+/// Applies a partial update to one of the user's todos, returning the updated todo.
+///
+/// If the patch has a version, the todo must still be at that version.
+pub(crate) async fn patch_todo(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+    patch: TodoPatch,
+) -> Result<Todo, UpdateError> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let patch = patch.clone();
+        pool.call_unwrap(move |conn| {
+            // Fetch, merge and write back within one transaction so no other connection can
+            // interleave. Immediate transactions take the write lock up front.
+            let transaction =
+                conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+            let existing = transaction
+                .query_row(
+                    &sql(
+                        &table,
+                        &format!(
+                            "{SELECT_TODOS} WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL;"
+                        ),
+                    ),
+                    (id, &user),
+                    todo_from_row,
+                )
+                .optional()?;
+
+            let Some(existing) = existing else {
+                return Ok(Err(UpdateError::NotFound));
+            };
+
+            if patch
+                .version
+                .is_some_and(|version| version != existing.version)
+            {
+                return Ok(Err(UpdateError::VersionConflict));
+            }
+
+            let mut patched = Todo {
+                name: patch.name.unwrap_or_else(|| existing.name.clone()),
+                completed: patch.completed.unwrap_or(existing.completed),
+                priority: patch.priority.unwrap_or(existing.priority),
+                assignee: match &patch.assignee {
+                    Some(assignee) => normalize_assignee(Some(assignee)).map(str::to_owned),
+                    None => existing.assignee.clone(),
+                },
+                description: match &patch.description {
+                    Some(description) => {
+                        normalize_description(Some(description)).map(str::to_owned)
+                    }
+                    None => existing.description.clone(),
+                },
+                ..existing.clone()
+            };
+
+            // A patch that changes nothing isn't an update, so it keeps the version
+            if patched == existing {
+                return Ok(Ok(patched));
+            }
+            patched.version += 1;
+            patched.completed_at = match (existing.completed, patched.completed) {
+                (false, true) => Some(Utc::now()),
+                (true, true) => existing.completed_at,
+                (_, false) => None,
+            };
+
+            transaction.execute(
+                &sql(
+                    &table,
+                    "UPDATE {todos} SET name = ?1, completed = ?2, priority = ?3, version = ?4, \
+                     completed_at = ?5, assignee = ?6, description = ?7 WHERE id = ?8",
+                ),
+                (
+                    &patched.name,
+                    patched.completed,
+                    patched.priority,
+                    patched.version,
+                    patched.completed_at,
+                    &patched.assignee,
+                    &patched.description,
+                    patched.id,
+                ),
+            )?;
+
+            transaction.commit()?;
+            Ok(Ok(patched))
+        })
+    })
+    .await
+    .unwrap_or_else(|error| Err(UpdateError::Other(error.into())))
+}
+
+/// Returns every recorded change to a todo, oldest first, or `None` if the user has no such todo.
+///
+/// Deleted todos still have their history.
+pub(crate) async fn get_todo_history(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+) -> Result<Option<Vec<TodoChange>>, rusqlite::Error> {
+    let table = table.clone();
+    let user = user.clone();
+    pool.call_unwrap(move |connection| {
+        let exists = connection
+            .query_row(
+                &sql(
+                    &table,
+                    "SELECT 1 FROM {todos} WHERE id = ?1 AND user_id IS ?2",
+                ),
+                (id, &user),
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+
+        if !exists {
+            return Ok(None);
+        }
+
+        let mut stmt = connection.prepare(&sql(
+            &table,
+            "SELECT action, changed_at, name, completed, due_date, priority \
+             FROM {todo_history} WHERE todo_id = ?1 ORDER BY id",
+        ))?;
+        let history = stmt
+            .query_map([id], |row| {
+                Ok(TodoChange {
+                    action: row.get(0)?,
+                    changed_at: row.get(1)?,
+                    name: row.get(2)?,
+                    completed: row.get(3)?,
+                    due_date: row.get(4)?,
+                    priority: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Some(history))
+    })
+    .await
+}
+
+/// Soft-deletes all of the user's completed todos, returning the todos that were deleted.
+///
+/// A dry run only looks up the todos that would be deleted.
+pub(crate) async fn delete_completed(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    dry_run: bool,
+) -> Result<Vec<Todo>, RepositoryError> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let deleted = transaction
+                .prepare(&sql(
+                    &table,
+                    &format!(
+                        "{SELECT_TODOS} WHERE user_id IS ?1 AND completed = 1 \
+                         AND deleted_at IS NULL ORDER BY id"
+                    ),
+                ))?
+                .query_map([&user], todo_from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if !dry_run {
+                transaction.execute(
+                    &sql(
+                        &table,
+                        "UPDATE {todos} SET deleted_at = ?1 \
+                         WHERE user_id IS ?2 AND completed = 1 AND deleted_at IS NULL",
+                    ),
+                    (Utc::now(), &user),
+                )?;
+                transaction.commit()?;
+            }
+
+            Ok(deleted)
+        })
+    })
+    .await
+    .map_err(RepositoryError::from)
+}
+
+/// Deletes every user's todos that were completed before `completed_before` for good, along with
+/// their history, returning how many were deleted.
+///
+/// Todos that still have subtasks are kept, as the subtasks refer to them. Once the subtasks
+/// are purged too, the todo goes in a later purge.
+pub(crate) async fn purge_completed(
+    pool: &ConnectionPool,
+    table: &TableName,
+    completed_before: DateTime<Utc>,
+) -> Result<usize, rusqlite::Error> {
+    const PURGED: &str = "completed = 1 AND julianday(completed_at) < julianday(?1) \
+                          AND NOT EXISTS (SELECT 1 FROM {todos} AS subtask \
+                          WHERE subtask.parent_id = {todos}.id)";
+
+    retry_busy(|| {
+        let table = table.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+            let completed_before = timestamp(completed_before);
+
+            transaction.execute(
+                &sql(
+                    &table,
+                    &format!(
+                        "DELETE FROM {{todo_history}} \
+                         WHERE todo_id IN (SELECT id FROM {{todos}} WHERE {PURGED})"
+                    ),
+                ),
+                [&completed_before],
+            )?;
+            // Tags go with the todos, through the delete_todo_tags trigger
+            let purged = transaction.execute(
+                &sql(&table, &format!("DELETE FROM {{todos}} WHERE {PURGED}")),
+                [&completed_before],
+            )?;
+
+            transaction.commit()?;
+            Ok(purged)
+        })
+    })
+    .await
+}
+
+/// Soft-deletes the user's todos with the given ids in a single transaction, returning the todos
+/// that were deleted. Ids that don't exist or are already deleted are left out.
+///
+/// A dry run only looks up the todos that would be deleted.
+pub(crate) async fn delete_many(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    ids: Vec<u32>,
+    dry_run: bool,
+) -> Result<Vec<Todo>, rusqlite::Error> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let ids = ids.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            // One statement per id rather than an IN clause, which SQLite caps at a number of
+            // parameters
+            let mut deleted: Vec<Todo> = Vec::new();
+            {
+                let mut select = transaction.prepare(&sql(
+                    &table,
+                    &format!(
+                        "{SELECT_TODOS} WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL"
+                    ),
+                ))?;
+                let mut delete = transaction.prepare(&sql(
+                    &table,
+                    "UPDATE {todos} SET deleted_at = ?1 WHERE id = ?2",
+                ))?;
+                let now = Utc::now();
+                for id in ids {
+                    // A dry run doesn't mark listed todos as deleted, so skip repeated ids here
+                    if deleted.iter().any(|todo| todo.id == id) {
+                        continue;
+                    }
+                    let Some(todo) = select.query_row((id, &user), todo_from_row).optional()?
+                    else {
+                        continue;
+                    };
+                    if !dry_run {
+                        delete.execute((now, id))?;
+                    }
+                    deleted.push(todo);
+                }
+            }
+
+            if !dry_run {
+                transaction.commit()?;
+            }
+            Ok(deleted)
+        })
+    })
+    .await
+}
+
+/// Checks that the database answers queries
+pub(crate) async fn ping(pool: &ConnectionPool) -> Result<(), rusqlite::Error> {
+    pool.call_unwrap(|connection| connection.query_row("SELECT 1", [], |_| Ok(())))
         .await
-        .expect("fetching todos failed")
 }
 
-pub(crate) async fn update_todo(connection: &Connection, updated: Todo) -> Result<(), ()> {
-    let result = connection
-        .call(move |connection| {
-            connection
-                .execute(
-                    "UPDATE todos SET name = ?1, completed = ?2 WHERE id = ?3",
-                    (updated.name, updated.completed, updated.id),
+/// Counts all of the user's todos, broken down by completion status
+pub(crate) async fn count_todos(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+) -> Result<TodoCounts, rusqlite::Error> {
+    let table = table.clone();
+    let user = user.clone();
+    pool.call_unwrap(move |connection| {
+        let mut stmt = connection.prepare(&sql(
+            &table,
+            "SELECT completed, COUNT(*) FROM {todos} WHERE user_id IS ?1 AND deleted_at IS NULL \
+             GROUP BY completed;",
+        ))?;
+        let mut rows = stmt.query([&user])?;
+
+        // An empty table has no groups at all, so every count starts at zero
+        let mut counts = TodoCounts::default();
+        while let Some(row) = rows.next()? {
+            let completed: bool = row.get(0)?;
+            let count: u64 = row.get(1)?;
+
+            if completed {
+                counts.completed = count;
+            } else {
+                counts.active = count;
+            }
+        }
+        counts.total = counts.completed + counts.active;
+
+        Ok(counts)
+    })
+    .await
+}
+
+/// Counts how many of the user's todos were created and completed on each day, keyed by the date
+/// in UTC. Days on which nothing happened are left out, so an empty table gives an empty map
+pub(crate) async fn daily_stats(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+) -> Result<BTreeMap<String, DayStats>, rusqlite::Error> {
+    let table = table.clone();
+    let user = user.clone();
+    pool.call_unwrap(move |connection| {
+        let mut stmt = connection.prepare(&sql(
+            &table,
+            "SELECT day, SUM(created), SUM(completed) FROM ( \
+               SELECT date(created_at) AS day, 1 AS created, 0 AS completed FROM {todos} \
+               WHERE user_id IS ?1 AND deleted_at IS NULL \
+               UNION ALL \
+               SELECT date(completed_at), 0, 1 FROM {todos} \
+               WHERE completed_at IS NOT NULL AND user_id IS ?1 AND deleted_at IS NULL \
+             ) GROUP BY day;",
+        ))?;
+        let stats = stmt
+            .query_map([&user], |row| {
+                Ok((
+                    row.get(0)?,
+                    DayStats {
+                        created: row.get(1)?,
+                        completed: row.get(2)?,
+                    },
+                ))
+            })?
+            .collect();
+        stats
+    })
+    .await
+}
+
+/// Counts the todos of every user, deleted todos don't count
+pub(crate) async fn count_stored_todos(
+    pool: &ConnectionPool,
+    table: &TableName,
+) -> Result<u64, rusqlite::Error> {
+    let table = table.clone();
+    pool.call_unwrap(move |connection| {
+        connection.query_row(
+            &sql(
+                &table,
+                "SELECT COUNT(*) FROM {todos} WHERE deleted_at IS NULL;",
+            ),
+            [],
+            |row| row.get(0),
+        )
+    })
+    .await
+}
+
+/// Flips whether a todo is completed with a single `UPDATE`, so two toggles can't both read the
+/// same state and have one of them lost.
+///
+/// Completing a recurring todo clears its recurrence, like [`set_completed`] does. Returns
+/// `None` if the todo doesn't exist.
+pub(crate) async fn toggle_todo(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+) -> Result<Option<Toggled>, rusqlite::Error> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        pool.call_unwrap(move |connection| {
+            // Take the write lock up front, so the recurrence read is the one being updated
+            let transaction =
+                connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+            let recurrence = transaction
+                .query_row(
+                    &sql(
+                        &table,
+                        "SELECT recurrence FROM {todos} \
+                         WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL",
+                    ),
+                    (id, &user),
+                    |row| row.get(0),
+                )
+                .optional()?
+                .flatten();
+
+            let completed = transaction
+                .query_row(
+                    &sql(
+                        &table,
+                        &format!(
+                            "UPDATE {{todos}} SET completed = NOT completed, {}, \
+                             recurrence = CASE WHEN completed THEN recurrence END, \
+                             version = version + 1 \
+                             WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL \
+                             RETURNING completed",
+                            set_completed_at("NOT completed")
+                        ),
+                    ),
+                    (id, &user),
+                    |row| row.get(0),
                 )
-                .map_err(Into::into)
+                .optional()?;
+
+            transaction.commit()?;
+            Ok(completed.map(|completed| Toggled {
+                completed,
+                recurrence,
+            }))
         })
-        .await;
+    })
+    .await
+}
 
-    match result {
-        // If the connection updated zero rows, it did not exist
-        Ok(0) => Err(()),
-        Ok(_) => Ok(()),
-        _ => Err(()),
-    }
+/// Flips whether each of the todos is completed, in a single transaction.
+///
+/// Every todo is toggled with its own atomic `UPDATE`, like [`toggle_todo`]. Todos that don't
+/// exist are skipped and listed as missing.
+pub(crate) async fn toggle_many(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    ids: Vec<u32>,
+) -> Result<ToggledMany, rusqlite::Error> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let ids = ids.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction =
+                connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+            let mut toggled = ToggledMany::default();
+            for id in ids {
+                let existing = transaction
+                    .query_row(
+                        &sql(
+                            &table,
+                            &format!(
+                                "{SELECT_TODOS} WHERE id = ?1 AND user_id IS ?2 \
+                                 AND deleted_at IS NULL"
+                            ),
+                        ),
+                        (id, &user),
+                        todo_from_row,
+                    )
+                    .optional()?;
+
+                let Some(existing) = existing else {
+                    toggled.missing.push(id);
+                    continue;
+                };
+
+                transaction.execute(
+                    &sql(
+                        &table,
+                        &format!(
+                            "UPDATE {{todos}} SET completed = NOT completed, {}, \
+                             recurrence = CASE WHEN completed THEN recurrence END, \
+                             version = version + 1 WHERE id = ?1",
+                            set_completed_at("NOT completed")
+                        ),
+                    ),
+                    [id],
+                )?;
+
+                let todo = transaction.query_row(
+                    &sql(&table, &format!("{SELECT_TODOS} WHERE id = ?1")),
+                    [id],
+                    todo_from_row,
+                )?;
+                toggled.todos.push((todo, existing.recurrence));
+            }
+
+            transaction.commit()?;
+            Ok(toggled)
+        })
+    })
+    .await
+}
+
+/// Sets whether a todo is completed, leaving it untouched if it already is as asked.
+///
+/// Completing a recurring todo clears its recurrence, since the next occurrence takes it over.
+/// Returns the todo as it was before, or `None` if the user has no such todo.
+pub(crate) async fn set_completed(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+    completed: bool,
+) -> Result<Option<Todo>, rusqlite::Error> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let previous = transaction
+                .query_row(
+                    &sql(
+                        &table,
+                        &format!(
+                            "{SELECT_TODOS} WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL"
+                        ),
+                    ),
+                    (id, &user),
+                    todo_from_row,
+                )
+                .optional()?;
+
+            if previous
+                .as_ref()
+                .is_some_and(|todo| todo.completed != completed)
+            {
+                transaction.execute(
+                    &sql(
+                        &table,
+                        &format!(
+                            "UPDATE {{todos}} SET completed = ?2, {}, \
+                             recurrence = CASE WHEN ?2 THEN NULL ELSE recurrence END, \
+                             version = version + 1 WHERE id = ?1",
+                            set_completed_at("?2")
+                        ),
+                    ),
+                    (id, completed),
+                )?;
+            }
+
+            transaction.commit()?;
+            Ok(previous)
+        })
+    })
+    .await
+}
+
+/// Sets whether a todo is archived, leaving it untouched if it already is as asked.
+///
+/// Returns the todo as it was before, or `None` if the user has no such todo.
+pub(crate) async fn set_archived(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    id: u32,
+    archived: bool,
+) -> Result<Option<Todo>, rusqlite::Error> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let previous = transaction
+                .query_row(
+                    &sql(
+                        &table,
+                        &format!(
+                            "{SELECT_TODOS} WHERE id = ?1 AND user_id IS ?2 AND deleted_at IS NULL"
+                        ),
+                    ),
+                    (id, &user),
+                    todo_from_row,
+                )
+                .optional()?;
+
+            if previous
+                .as_ref()
+                .is_some_and(|todo| todo.archived != archived)
+            {
+                transaction.execute(
+                    &sql(
+                        &table,
+                        "UPDATE {todos} SET archived = ?2, version = version + 1 WHERE id = ?1",
+                    ),
+                    (id, archived),
+                )?;
+            }
+
+            transaction.commit()?;
+            Ok(previous)
+        })
+    })
+    .await
+}
+
+/// Reads the todos with the given ids, ordered by id
+fn select_by_ids(
+    connection: &rusqlite::Connection,
+    table: &TableName,
+    mut ids: Vec<u32>,
+) -> Result<Vec<Todo>, rusqlite::Error> {
+    ids.sort_unstable();
+    let mut stmt = connection.prepare(&sql(table, &format!("{SELECT_TODOS} WHERE id = ?1")))?;
+    ids.into_iter()
+        .map(|id| stmt.query_row([id], todo_from_row))
+        .collect()
+}
+
+/// Sets the completion status of every todo of the user, returning the updated todos
+pub(crate) async fn set_all_completed(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    completed: bool,
+) -> Result<Vec<Todo>, RepositoryError> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let ids = transaction
+                .prepare(&sql(
+                    &table,
+                    &format!(
+                        "UPDATE {{todos}} SET completed = ?1, {}, version = version + 1 \
+                         WHERE user_id IS ?2 AND deleted_at IS NULL RETURNING id",
+                        set_completed_at("?1")
+                    ),
+                ))?
+                .query_map((completed, &user), |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            let updated = select_by_ids(&transaction, &table, ids)?;
+
+            transaction.commit()?;
+            Ok(updated)
+        })
+    })
+    .await
+    .map_err(RepositoryError::from)
+}
+
+/// Replaces every occurrence of `find` in the names of the user's todos, returning the renamed
+/// todos. Todos whose name doesn't contain `find`, or would be left blank, are left alone
+pub(crate) async fn replace_in_names(
+    pool: &ConnectionPool,
+    table: &TableName,
+    user: &UserId,
+    find: String,
+    replace: String,
+) -> Result<Vec<Todo>, rusqlite::Error> {
+    retry_busy(|| {
+        let table = table.clone();
+        let user = user.clone();
+        let find = find.clone();
+        let replace = replace.clone();
+        pool.call_unwrap(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let ids = transaction
+                .prepare(&sql(
+                    &table,
+                    "UPDATE {todos} SET name = replace(name, ?1, ?2), version = version + 1 \
+                     WHERE instr(name, ?1) > 0 AND user_id IS ?3 AND deleted_at IS NULL \
+                     AND trim(replace(name, ?1, ?2), char(32, 9, 10, 13)) != '' RETURNING id",
+                ))?
+                .query_map((&find, &replace, &user), |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            let renamed = select_by_ids(&transaction, &table, ids)?;
+
+            transaction.commit()?;
+            Ok(renamed)
+        })
+    })
+    .await
 }
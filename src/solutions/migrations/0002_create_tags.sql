@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS {tags} (
+  id   INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS {todo_tags} (
+  todo_id INTEGER NOT NULL REFERENCES {todos} (id) ON DELETE CASCADE,
+  tag_id  INTEGER NOT NULL REFERENCES {tags} (id) ON DELETE CASCADE,
+  PRIMARY KEY (todo_id, tag_id)
+);
+
+-- Foreign keys are only enforced when enabled per connection, so clean up explicitly too
+CREATE TRIGGER IF NOT EXISTS {delete_todo_tags} AFTER DELETE ON {todos}
+BEGIN
+  DELETE FROM {todo_tags} WHERE todo_id = OLD.id;
+END;
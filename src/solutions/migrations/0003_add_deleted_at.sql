@@ -0,0 +1,2 @@
+-- Deleting a todo only marks it as deleted, so it can be restored later
+ALTER TABLE {todos} ADD COLUMN deleted_at TEXT;
@@ -0,0 +1,6 @@
+-- Remembers which todo was created for an Idempotency-Key, so retried requests don't create duplicates
+CREATE TABLE IF NOT EXISTS {idempotency_keys} (
+  key        TEXT PRIMARY KEY NOT NULL,
+  todo_id    INTEGER NOT NULL,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+-- Counts how often a todo has been updated, so clients can detect concurrent edits
+ALTER TABLE {todos} ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,40 @@
+-- Every change to a todo, recorded by triggers so it always happens in the same transaction
+CREATE TABLE IF NOT EXISTS {todo_history} (
+  id         INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  todo_id    INTEGER NOT NULL,
+  action     TEXT NOT NULL,
+  changed_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+  name       TEXT NOT NULL,
+  completed  BOOL NOT NULL,
+  due_date   TEXT,
+  priority   INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS {todo_history_todo_id} ON {todo_history} (todo_id);
+
+CREATE TRIGGER IF NOT EXISTS {record_todo_created} AFTER INSERT ON {todos}
+BEGIN
+  INSERT INTO {todo_history} (todo_id, action, name, completed, due_date, priority)
+  VALUES (NEW.id, 'created', NEW.name, NEW.completed, NEW.due_date, NEW.priority);
+END;
+
+CREATE TRIGGER IF NOT EXISTS {record_todo_updated} AFTER UPDATE ON {todos}
+BEGIN
+  INSERT INTO {todo_history} (todo_id, action, name, completed, due_date, priority)
+  VALUES (
+    NEW.id,
+    CASE
+      WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'deleted'
+      WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restored'
+      WHEN OLD.completed IS NOT NEW.completed
+        AND OLD.name IS NEW.name
+        AND OLD.due_date IS NEW.due_date
+        AND OLD.priority IS NEW.priority THEN 'toggled'
+      ELSE 'updated'
+    END,
+    NEW.name,
+    NEW.completed,
+    NEW.due_date,
+    NEW.priority
+  );
+END;
@@ -0,0 +1,2 @@
+-- Lets a todo be a subtask of another todo. Checked at commit, so imports can list children first
+ALTER TABLE {todos} ADD COLUMN parent_id INTEGER REFERENCES {todos} (id) DEFERRABLE INITIALLY DEFERRED;
@@ -0,0 +1,2 @@
+-- When the todo was last completed, cleared again when it is marked as not completed
+ALTER TABLE {todos} ADD COLUMN completed_at TEXT;
@@ -0,0 +1,2 @@
+-- How often a todo comes back once completed, NULL for todos that don't recur
+ALTER TABLE {todos} ADD COLUMN recurrence TEXT;
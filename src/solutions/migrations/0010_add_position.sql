@@ -0,0 +1,31 @@
+-- Only record changes to what the history keeps, so reordering todos doesn't show up in it
+DROP TRIGGER IF EXISTS {record_todo_updated};
+
+CREATE TRIGGER IF NOT EXISTS {record_todo_updated}
+AFTER UPDATE OF name, completed, due_date, priority, deleted_at ON {todos}
+BEGIN
+  INSERT INTO {todo_history} (todo_id, action, name, completed, due_date, priority)
+  VALUES (
+    NEW.id,
+    CASE
+      WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'deleted'
+      WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restored'
+      WHEN OLD.completed IS NOT NEW.completed
+        AND OLD.name IS NEW.name
+        AND OLD.due_date IS NEW.due_date
+        AND OLD.priority IS NEW.priority THEN 'toggled'
+      ELSE 'updated'
+    END,
+    NEW.name,
+    NEW.completed,
+    NEW.due_date,
+    NEW.priority
+  );
+END;
+
+-- Where the todo is listed, set by reordering. Existing todos keep the order of their ids
+ALTER TABLE {todos} ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+
+UPDATE {todos} SET position = id;
+
+CREATE INDEX IF NOT EXISTS {todos_position} ON {todos} (position);
@@ -0,0 +1,4 @@
+-- Who the todo is assigned to, NULL for unassigned todos
+ALTER TABLE {todos} ADD COLUMN assignee TEXT;
+
+CREATE INDEX IF NOT EXISTS {todos_assignee} ON {todos} (assignee);
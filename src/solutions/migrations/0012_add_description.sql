@@ -0,0 +1,2 @@
+-- Free text about the todo, next to its short name. NULL for todos without one
+ALTER TABLE {todos} ADD COLUMN description TEXT;
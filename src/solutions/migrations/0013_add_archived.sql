@@ -0,0 +1,2 @@
+-- Archived todos are kept, but left out of the list unless asked for
+ALTER TABLE {todos} ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
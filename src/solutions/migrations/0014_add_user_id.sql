@@ -0,0 +1,4 @@
+-- The user a todo belongs to, NULL for todos created without a user
+ALTER TABLE {todos} ADD COLUMN user_id TEXT;
+
+CREATE INDEX IF NOT EXISTS {todos_user_id} ON {todos} (user_id);
@@ -0,0 +1,13 @@
+-- SQLite can't add a CHECK constraint to an existing table without rebuilding it, so the
+-- limit on name length is enforced by triggers instead, which also covers direct writes
+CREATE TRIGGER IF NOT EXISTS {check_name_length_on_insert} BEFORE INSERT ON {todos}
+WHEN length(NEW.name) > 255
+BEGIN
+  SELECT RAISE(ABORT, 'name must be at most 255 characters');
+END;
+
+CREATE TRIGGER IF NOT EXISTS {check_name_length_on_update} BEFORE UPDATE OF name ON {todos}
+WHEN length(NEW.name) > 255
+BEGIN
+  SELECT RAISE(ABORT, 'name must be at most 255 characters');
+END;
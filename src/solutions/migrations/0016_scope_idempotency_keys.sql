@@ -0,0 +1,19 @@
+-- Keys are only unique per user, so one user can't replay a todo another user created. The
+-- primary key can't be changed in place, so the table is rebuilt. Todos created without a user
+-- have their keys stored with an empty user id, as NULLs would never be equal to each other
+CREATE TABLE IF NOT EXISTS {idempotency_keys_by_user} (
+  user_id    TEXT NOT NULL,
+  key        TEXT NOT NULL,
+  todo_id    INTEGER NOT NULL,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (user_id, key)
+);
+
+INSERT INTO {idempotency_keys_by_user} (user_id, key, todo_id, created_at)
+SELECT coalesce({todos}.user_id, ''), keys.key, keys.todo_id, keys.created_at
+FROM {idempotency_keys} AS keys
+LEFT JOIN {todos} ON {todos}.id = keys.todo_id;
+
+DROP TABLE {idempotency_keys};
+
+ALTER TABLE {idempotency_keys_by_user} RENAME TO {idempotency_keys};
@@ -1 +1,4 @@
 pub(crate) mod db;
+mod sqlite_repository;
+
+pub use sqlite_repository::SqliteRepository;
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::db;
+use crate::{
+    ConnectionPool, DayStats, DeleteError, Filter, ImportSummary, Inserted, MoveTo, NewTodo,
+    OnConflict, Page, ReorderError, RepositoryError, SearchCriteria, SearchResults, Sort,
+    TableName, Todo, TodoChange, TodoCounts, TodoPatch, TodoRepository, Toggled, ToggledMany,
+    UpdateError, Upserted, UserId,
+};
+
+/// Keeps todos in a table of a SQLite database, whose schema is already migrated
+#[derive(Clone)]
+pub struct SqliteRepository {
+    pool: ConnectionPool,
+    table: TableName,
+}
+
+impl SqliteRepository {
+    /// Keeps todos in `table`, which [`app_with_pool`](crate::app_with_pool) must have migrated
+    pub fn new(pool: ConnectionPool, table: TableName) -> Self {
+        Self { pool, table }
+    }
+}
+
+#[async_trait]
+impl TodoRepository for SqliteRepository {
+    async fn ping(&self) -> Result<(), RepositoryError> {
+        Ok(db::ping(&self.pool).await?)
+    }
+
+    async fn get_todo(&self, user: &UserId, id: u32) -> Result<Option<Todo>, RepositoryError> {
+        Ok(db::get_todo(&self.pool, &self.table, user, id).await?)
+    }
+
+    async fn todo_exists(&self, user: &UserId, id: u32) -> Result<bool, RepositoryError> {
+        Ok(db::todo_exists(&self.pool, &self.table, user, id).await?)
+    }
+
+    async fn random_pending(&self, user: &UserId) -> Result<Option<Todo>, RepositoryError> {
+        Ok(db::random_pending(&self.pool, &self.table, user).await?)
+    }
+
+    async fn get_todos(&self, user: &UserId) -> Result<Vec<Todo>, RepositoryError> {
+        Ok(db::get_todos(&self.pool, &self.table, user).await?)
+    }
+
+    async fn get_todos_after(
+        &self,
+        filter: Filter,
+        after: Option<u32>,
+        limit: u32,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        Ok(db::get_todos_after(&self.pool, &self.table, filter, after, limit).await?)
+    }
+
+    async fn get_todos_filtered(
+        &self,
+        filter: Filter,
+        sort: Sort,
+        page: Option<Page>,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        Ok(db::get_todos_filtered(&self.pool, &self.table, filter, sort, page).await?)
+    }
+
+    async fn search_todos(
+        &self,
+        criteria: SearchCriteria,
+    ) -> Result<SearchResults, RepositoryError> {
+        Ok(db::search_todos(&self.pool, &self.table, criteria).await?)
+    }
+
+    async fn count_todos(&self, user: &UserId) -> Result<TodoCounts, RepositoryError> {
+        Ok(db::count_todos(&self.pool, &self.table, user).await?)
+    }
+
+    async fn count_stored_todos(&self) -> Result<u64, RepositoryError> {
+        Ok(db::count_stored_todos(&self.pool, &self.table).await?)
+    }
+
+    async fn daily_stats(
+        &self,
+        user: &UserId,
+    ) -> Result<BTreeMap<String, DayStats>, RepositoryError> {
+        Ok(db::daily_stats(&self.pool, &self.table, user).await?)
+    }
+
+    async fn get_todo_history(
+        &self,
+        user: &UserId,
+        id: u32,
+    ) -> Result<Option<Vec<TodoChange>>, RepositoryError> {
+        Ok(db::get_todo_history(&self.pool, &self.table, user, id).await?)
+    }
+
+    async fn insert_todo(
+        &self,
+        user: &UserId,
+        todo: NewTodo,
+        idempotency_key: Option<String>,
+        unique_name: bool,
+    ) -> Result<Inserted, RepositoryError> {
+        db::insert_todo(
+            &self.pool,
+            &self.table,
+            user,
+            todo,
+            idempotency_key,
+            unique_name,
+        )
+        .await
+    }
+
+    async fn idempotent_todo_id(
+        &self,
+        user: &UserId,
+        idempotency_key: &str,
+    ) -> Result<Option<u32>, RepositoryError> {
+        Ok(db::idempotent_todo_id(&self.pool, &self.table, user, idempotency_key).await?)
+    }
+
+    async fn insert_todos(
+        &self,
+        user: &UserId,
+        todos: Vec<NewTodo>,
+    ) -> Result<Vec<u32>, RepositoryError> {
+        Ok(db::insert_todos(&self.pool, &self.table, user, todos).await?)
+    }
+
+    async fn import_todos(
+        &self,
+        user: &UserId,
+        todos: Vec<Todo>,
+        on_conflict: OnConflict,
+    ) -> Result<ImportSummary, RepositoryError> {
+        Ok(db::import_todos(&self.pool, &self.table, user, todos, on_conflict).await?)
+    }
+
+    async fn update_todo(&self, user: &UserId, todo: Todo) -> Result<(), UpdateError> {
+        db::update_todo(&self.pool, &self.table, user, todo).await
+    }
+
+    async fn upsert_todo(&self, user: &UserId, todo: Todo) -> Result<Upserted, UpdateError> {
+        db::upsert_todo(&self.pool, &self.table, user, todo).await
+    }
+
+    async fn patch_todo(
+        &self,
+        user: &UserId,
+        id: u32,
+        patch: TodoPatch,
+    ) -> Result<Todo, UpdateError> {
+        db::patch_todo(&self.pool, &self.table, user, id, patch).await
+    }
+
+    async fn reorder_todos(&self, user: &UserId, ids: Vec<u32>) -> Result<(), ReorderError> {
+        db::reorder_todos(&self.pool, &self.table, user, ids).await
+    }
+
+    async fn move_todo(&self, user: &UserId, id: u32, to: MoveTo) -> Result<bool, RepositoryError> {
+        Ok(db::move_todo(&self.pool, &self.table, user, id, to).await?)
+    }
+
+    async fn toggle_todo(
+        &self,
+        user: &UserId,
+        id: u32,
+    ) -> Result<Option<Toggled>, RepositoryError> {
+        Ok(db::toggle_todo(&self.pool, &self.table, user, id).await?)
+    }
+
+    async fn toggle_many(
+        &self,
+        user: &UserId,
+        ids: Vec<u32>,
+    ) -> Result<ToggledMany, RepositoryError> {
+        Ok(db::toggle_many(&self.pool, &self.table, user, ids).await?)
+    }
+
+    async fn set_completed(
+        &self,
+        user: &UserId,
+        id: u32,
+        completed: bool,
+    ) -> Result<Option<Todo>, RepositoryError> {
+        Ok(db::set_completed(&self.pool, &self.table, user, id, completed).await?)
+    }
+
+    async fn set_archived(
+        &self,
+        user: &UserId,
+        id: u32,
+        archived: bool,
+    ) -> Result<Option<Todo>, RepositoryError> {
+        Ok(db::set_archived(&self.pool, &self.table, user, id, archived).await?)
+    }
+
+    async fn set_all_completed(
+        &self,
+        user: &UserId,
+        completed: bool,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        db::set_all_completed(&self.pool, &self.table, user, completed).await
+    }
+
+    async fn replace_in_names(
+        &self,
+        user: &UserId,
+        find: String,
+        replace: String,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        Ok(db::replace_in_names(&self.pool, &self.table, user, find, replace).await?)
+    }
+
+    async fn delete_todo(
+        &self,
+        user: &UserId,
+        id: u32,
+        cascade: bool,
+        dry_run: bool,
+    ) -> Result<Vec<Todo>, DeleteError> {
+        db::delete_todo(&self.pool, &self.table, user, id, cascade, dry_run).await
+    }
+
+    async fn restore_todo(&self, user: &UserId, id: u32) -> Result<bool, RepositoryError> {
+        Ok(db::restore_todo(&self.pool, &self.table, user, id).await?)
+    }
+
+    async fn delete_completed(
+        &self,
+        user: &UserId,
+        dry_run: bool,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        db::delete_completed(&self.pool, &self.table, user, dry_run).await
+    }
+
+    async fn purge_completed(
+        &self,
+        completed_before: DateTime<Utc>,
+    ) -> Result<usize, RepositoryError> {
+        Ok(db::purge_completed(&self.pool, &self.table, completed_before).await?)
+    }
+
+    async fn delete_many(
+        &self,
+        user: &UserId,
+        ids: Vec<u32>,
+        dry_run: bool,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        Ok(db::delete_many(&self.pool, &self.table, user, ids, dry_run).await?)
+    }
+}
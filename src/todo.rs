@@ -1,6 +1,258 @@
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+use chrono::{DateTime, Days, Months, Utc};
+
+#[derive(
+    Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema,
+)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "strict-json", serde(deny_unknown_fields))]
 pub struct Todo {
     pub id: u32,
     pub name: String,
     pub completed: bool,
+    /// When the todo should be completed by, serialized as RFC 3339
+    #[serde(default)]
+    pub due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub priority: Priority,
+    /// Labels used to categorize the todo, sorted alphabetically
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// When the todo was deleted, deleted todos are only listed when asked for
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// How many times the todo has been updated.
+    ///
+    /// Updates must send the version they are based on, and fail if it has changed since.
+    #[serde(default)]
+    pub version: u32,
+    /// The todo this is a subtask of
+    #[serde(default)]
+    pub parent_id: Option<u32>,
+    /// When the todo was completed, or `None` if it isn't
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// How often the todo comes back. Completing it creates the next occurrence, which takes
+    /// over the recurrence
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// Who the todo is assigned to, or `None` if it is unassigned. An empty name unassigns it
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// Longer free text about the todo, next to its short name
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Whether the todo has been put away, archived todos are only listed when asked for.
+    ///
+    /// Only changed through archiving and unarchiving, updates leave it as it is.
+    #[serde(default)]
+    pub archived: bool,
+    /// The user the todo belongs to, set from the `X-User-Id` header it was created with.
+    ///
+    /// Only used to keep users apart, so it is never read from or written to JSON.
+    #[serde(skip)]
+    pub user_id: Option<String>,
+}
+
+/// How important a todo is, ordered from least to most important
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    serde::Serialize,
+    serde::Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+/// How often a recurring todo comes back
+#[derive(
+    Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, Hash, utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    /// On the same day of the next month, or its last day if the month is shorter
+    Monthly,
+}
+
+impl Recurrence {
+    /// The due date of the occurrence after one due at `due_date`
+    pub fn next_due_date(self, due_date: DateTime<Utc>) -> DateTime<Utc> {
+        let next = match self {
+            Recurrence::Daily => due_date.checked_add_days(Days::new(1)),
+            Recurrence::Weekly => due_date.checked_add_days(Days::new(7)),
+            Recurrence::Monthly => due_date.checked_add_months(Months::new(1)),
+        };
+        next.unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
+
+/// A todo as submitted by a client, before it has been stored.
+///
+/// The id is optional, if omitted the database assigns one.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "strict-json", serde(deny_unknown_fields))]
+pub struct NewTodo {
+    #[serde(default)]
+    pub id: Option<u32>,
+    pub name: String,
+    /// Whether the todo is already done when it is created, defaults to `false`
+    #[serde(default)]
+    pub completed: bool,
+    #[serde(default)]
+    pub due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The todo this is a subtask of
+    #[serde(default)]
+    pub parent_id: Option<u32>,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A partial update to a todo, only the fields that are set are changed.
+#[derive(
+    Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema,
+)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "strict-json", serde(deny_unknown_fields))]
+pub struct TodoPatch {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub completed: Option<bool>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// Who to assign the todo to, an empty name unassigns it
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// The new description, an empty one removes it
+    #[serde(default)]
+    pub description: Option<String>,
+    /// If set, the patch is only applied if the todo is still at this version
+    #[serde(default)]
+    pub version: Option<u32>,
+}
+
+/// The longest name a todo can have, counted in characters
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// Describes which field of a todo failed validation, and why
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
+pub struct ValidationError {
+    pub field: String,
+    pub error: String,
+}
+
+impl ValidationError {
+    fn new(field: &str, error: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            error: error.into(),
+        }
+    }
+}
+
+/// Checks that a name is not blank and at most [`MAX_NAME_LENGTH`] characters long
+pub fn validate_name(name: &str) -> Result<(), ValidationError> {
+    if name.trim().is_empty() {
+        return Err(ValidationError::new("name", "name must not be empty"));
+    }
+
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ValidationError::new(
+            "name",
+            format!("name must be at most {MAX_NAME_LENGTH} characters"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that a todo is valid to store
+pub fn validate_todo(todo: &Todo) -> Result<(), ValidationError> {
+    validate_name(&todo.name)
+}
+
+/// A recorded change to a todo, with the values the todo had after the change
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct TodoChange {
+    pub action: ChangeAction,
+    pub changed_at: DateTime<Utc>,
+    pub name: String,
+    pub completed: bool,
+    pub due_date: Option<DateTime<Utc>>,
+    pub priority: Priority,
+}
+
+/// What kind of change was made to a todo
+#[derive(
+    Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAction {
+    Created,
+    Updated,
+    /// Only the completion status changed
+    Toggled,
+    Deleted,
+    Restored,
+}
+
+/// The number of todos, broken down by completion status
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    serde::Serialize,
+    serde::Deserialize,
+    PartialEq,
+    Eq,
+    utoipa::ToSchema,
+)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct TodoCounts {
+    pub total: u64,
+    pub completed: u64,
+    pub active: u64,
+}
+
+/// How many todos were created and completed on a single day, in UTC
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    serde::Serialize,
+    serde::Deserialize,
+    PartialEq,
+    Eq,
+    utoipa::ToSchema,
+)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct DayStats {
+    pub created: u64,
+    pub completed: u64,
 }
@@ -0,0 +1,97 @@
+use std::{future::Future, io, os::unix::fs::FileTypeExt, path::Path, time::Duration};
+
+use axum::Router;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use tokio::{
+    net::{UnixListener, UnixStream},
+    sync::watch,
+    task::JoinSet,
+};
+
+/// Serves the app on a Unix domain socket at `path` until `shutdown` completes, then waits for
+/// in-flight requests to finish and removes the socket.
+///
+/// `axum::serve` only listens on TCP. A socket left behind by a server that didn't shut down
+/// cleanly is removed before binding, one another server still listens on is an error.
+pub async fn serve_unix(
+    path: impl AsRef<Path>,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    remove_stale_socket(path).await?;
+    let listener = UnixListener::bind(path)?;
+
+    // Set once shutting down, telling open connections to finish their requests and close
+    let (stopping, _) = watch::channel(false);
+    let mut connections = JoinSet::new();
+
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    connections.spawn(serve_connection(stream, app.clone(), stopping.subscribe()));
+                }
+                Err(error) => {
+                    // Usually out of file descriptors, which retrying right away won't fix
+                    tracing::warn!(%error, "accepting a connection failed");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            },
+            // Reaps finished connections, so they don't pile up in the set
+            Some(_) = connections.join_next() => {}
+            () = &mut shutdown => break,
+        }
+    }
+
+    drop(listener);
+    stopping.send_replace(true);
+    while connections.join_next().await.is_some() {}
+
+    std::fs::remove_file(path)
+}
+
+async fn serve_connection(stream: UnixStream, app: Router, mut stopping: watch::Receiver<bool>) {
+    let builder = Builder::new(TokioExecutor::new());
+    // Upgrades are needed by the `/ws` route
+    let connection =
+        builder.serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(app));
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = stopping.changed() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+
+    if let Err(error) = result {
+        tracing::debug!(%error, "serving a connection failed");
+    }
+}
+
+/// Removes a socket file nothing listens on anymore, leaving other files to fail binding
+async fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.file_type().is_socket() {
+        return Ok(());
+    }
+
+    if UnixStream::connect(path).await.is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another server is listening on {}", path.display()),
+        ));
+    }
+
+    tracing::info!("removing the stale socket {}", path.display());
+    std::fs::remove_file(path)
+}
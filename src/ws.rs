@@ -0,0 +1,124 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    auth::UserId,
+    events::{TodoAction, TodoEvent},
+    todo, AppState, NewTodo, RepositoryError,
+};
+
+/// A command sent by a client over the WebSocket
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Create { todo: NewTodo },
+    Toggle { id: u32 },
+}
+
+/// A message sent by the server over the WebSocket
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    /// A todo changed, either through this socket or any other client
+    Event(TodoEvent),
+    /// A command from this client could not be carried out
+    Error { error: String },
+}
+
+/// Upgrades to a WebSocket that pushes every change to the user's todos and accepts commands.
+///
+/// Commands don't get a direct reply, their result arrives as an event like any other change.
+pub(crate) async fn websocket(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    user: UserId,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, state, user))
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState, user: UserId) {
+    let mut events = state.events.subscribe();
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if !user.owns(&event.todo) => {}
+                Ok(event) => {
+                    if send(&mut socket, &ServerMessage::Event(event)).await.is_err() {
+                        break;
+                    }
+                }
+                // Skip the events we were too slow to receive
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if let Err(error) = handle_command(&state, &user, &text).await {
+                        if send(&mut socket, &ServerMessage::Error { error }).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pings are answered by axum, and binary messages aren't part of the protocol
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Carries out a command, the resulting change is published to every subscriber
+async fn handle_command(state: &AppState, user: &UserId, text: &str) -> Result<(), String> {
+    let command = serde_json::from_str::<ClientMessage>(text)
+        .map_err(|error| format!("invalid message: {error}"))?;
+
+    match command {
+        ClientMessage::Create { todo } => {
+            todo::validate_name(&todo.name).map_err(|error| error.error)?;
+            crate::ensure_room(state, 1)
+                .await
+                .map_err(|error| error.message().to_owned())?;
+
+            let inserted = state
+                .repository
+                .insert_todo(user, todo, None, false)
+                .await
+                .map_err(|error| match error {
+                    RepositoryError::Conflict
+                    | RepositoryError::InvalidParent
+                    | RepositoryError::NameTooLong
+                    | RepositoryError::DuplicateName => error.to_string(),
+                    RepositoryError::Other(_) => "creating the todo failed".to_owned(),
+                })?;
+            let created = state
+                .repository
+                .get_todo(user, inserted.id)
+                .await
+                .ok()
+                .flatten()
+                .ok_or("creating the todo failed")?;
+
+            state.publish(TodoAction::Created, created);
+        }
+        ClientMessage::Toggle { id } => {
+            crate::toggle_todo(state, user, id)
+                .await
+                .map_err(|_| "toggling the todo failed".to_owned())?
+                .ok_or(format!("todo {id} does not exist"))?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn send(socket: &mut WebSocket, message: &ServerMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).expect("messages always serialize");
+    socket.send(Message::Text(text)).await
+}
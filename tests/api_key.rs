@@ -0,0 +1,83 @@
+//! Tests for apps configured with an API key, which is required to change todos.
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use todo_server_workshop::AppConfig;
+use tower::ServiceExt;
+
+const API_KEY: &str = "let-me-in";
+
+async fn app() -> axum::Router {
+    let config = AppConfig::builder()
+        .db_path(":memory:")
+        .api_key(API_KEY)
+        .build();
+    todo_server_workshop::app(config).await.unwrap()
+}
+
+fn post_todo_request(api_key: Option<&str>) -> Request<Body> {
+    let mut request = Request::builder()
+        .uri("/todos")
+        .method(axum::http::Method::POST)
+        .header(
+            axum::http::header::CONTENT_TYPE,
+            "application/json; charset=utf-8",
+        );
+
+    if let Some(api_key) = api_key {
+        request = request.header("X-API-Key", api_key);
+    }
+
+    request
+        .body(Body::from(
+            r#"{"name": "Walk the dog", "completed": false}"#,
+        ))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn rejects_changes_without_api_key() {
+    let response = app().await.oneshot(post_todo_request(None)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn rejects_changes_with_wrong_api_key() {
+    let response = app()
+        .await
+        .oneshot(post_todo_request(Some("let-me-in-please")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn allows_changes_with_api_key() {
+    let response = app()
+        .await
+        .oneshot(post_todo_request(Some(API_KEY)))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn reading_stays_public() {
+    let response = app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/todos")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
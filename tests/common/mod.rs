@@ -0,0 +1,222 @@
+//! Helpers shared by the integration tests, so each test only spells out what it checks.
+
+// Every test binary gets its own copy of this module, and not all of them use every helper
+#![allow(dead_code)]
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Method, Request, Response, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde::{de::DeserializeOwned, Serialize};
+use todo_server_workshop::Todo;
+use tower::{Service, ServiceExt};
+
+/// Sends a request to the app, which stays usable for further requests
+pub async fn send(app: &mut Router, request: Request<Body>) -> Response<Body> {
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+/// Sends a request to the app, returning the status and the whole body of the response
+pub async fn fetch(app: &mut Router, request: Request<Body>) -> (StatusCode, Bytes) {
+    let response = send(app, request).await;
+    (response.status(), read_body(response).await)
+}
+
+pub async fn read_body(response: Response<Body>) -> Bytes {
+    response.into_body().collect().await.unwrap().to_bytes()
+}
+
+pub async fn read_json<T: DeserializeOwned>(response: Response<Body>) -> T {
+    serde_json::from_slice(&read_body(response).await).unwrap()
+}
+
+/// The todo a `POST /todos` with a single todo created, which is answered with a list of one
+pub async fn read_created(response: Response<Body>) -> Todo {
+    let [created] = read_json::<[Todo; 1]>(response).await;
+    created
+}
+
+pub fn get_request(uri: &str) -> Request<Body> {
+    Request::builder().uri(uri).body(Body::empty()).unwrap()
+}
+
+/// A `POST` without a body, like toggling a todo
+pub fn post_request(uri: &str) -> Request<Body> {
+    Request::builder()
+        .uri(uri)
+        .method(Method::POST)
+        .body(Body::empty())
+        .unwrap()
+}
+
+pub fn json_request(method: Method, uri: &str, body: &impl Serialize) -> Request<Body> {
+    Request::builder()
+        .uri(uri)
+        .method(method)
+        .header(header::CONTENT_TYPE, "application/json; charset=utf-8")
+        .body(Body::from(serde_json::to_string(body).unwrap()))
+        .unwrap()
+}
+
+pub fn get_todos_request() -> Request<Body> {
+    get_request("/todos")
+}
+
+pub fn post_todo_request(todo: Todo) -> Request<Body> {
+    json_request(Method::POST, "/todos", &new_todo_body(&todo))
+}
+
+/// The body creating the todo, leaving out the fields that are only set by the server, which
+/// the `strict-json` feature rejects
+pub fn new_todo_body(todo: &Todo) -> serde_json::Value {
+    let mut body = serde_json::to_value(todo).unwrap();
+    let fields = body.as_object_mut().unwrap();
+    for field in [
+        "deleted_at",
+        "version",
+        "completed_at",
+        "archived",
+        "user_id",
+    ] {
+        fields.remove(&key(field));
+    }
+    body
+}
+
+/// The JSON key of a todo field, which is camelCase with the `camel-case` feature
+pub fn key(field: &str) -> String {
+    if !cfg!(feature = "camel-case") {
+        return field.to_owned();
+    }
+
+    let mut words = field.split('_');
+    let mut key = words.next().unwrap_or_default().to_owned();
+    for word in words {
+        let mut chars = word.chars();
+        key.extend(chars.next().map(|first| first.to_ascii_uppercase()));
+        key.push_str(chars.as_str());
+    }
+    key
+}
+
+pub fn default_todo() -> Todo {
+    Todo {
+        id: 1,
+        name: "Remember to store the todo".into(),
+        completed: false,
+        ..Default::default()
+    }
+}
+
+/// An app along with typed shortcuts for the requests most tests make.
+///
+/// The shortcuts assert that the request succeeded, tests checking errors use [`TestApp::send`].
+pub struct TestApp {
+    pub router: Router,
+}
+
+impl TestApp {
+    /// An app backed by a fresh in-memory database
+    #[cfg(feature = "sqlite")]
+    pub async fn new() -> Self {
+        Self::from(todo_server_workshop::app_in_memory().await)
+    }
+
+    pub async fn send(&mut self, request: Request<Body>) -> Response<Body> {
+        send(&mut self.router, request).await
+    }
+
+    /// Creates the todo, returning it as it was stored
+    pub async fn create_todo(&mut self, todo: Todo) -> Todo {
+        let response = self.send(post_todo_request(todo)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        read_created(response).await
+    }
+
+    /// The first page of todos
+    pub async fn get_todos(&mut self) -> Vec<Todo> {
+        let response = self.send(get_todos_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        read_json(response).await
+    }
+
+    /// The todo, or `None` if it doesn't exist
+    pub async fn get_todo(&mut self, id: u32) -> Option<Todo> {
+        let response = self.send(get_request(&format!("/todos/{id}"))).await;
+        match response.status() {
+            StatusCode::NOT_FOUND => None,
+            status => {
+                assert_eq!(status, StatusCode::OK);
+                Some(read_json(response).await)
+            }
+        }
+    }
+
+    /// Toggles the todo, returning whether it is completed now or `None` if it doesn't exist
+    pub async fn toggle(&mut self, id: u32) -> Option<bool> {
+        let response = self.send(post_request(&format!("/toggle/{id}"))).await;
+        match response.status() {
+            StatusCode::NOT_FOUND => None,
+            status => {
+                assert_eq!(status, StatusCode::OK);
+                let body: serde_json::Value = read_json(response).await;
+                body["completed"].as_bool()
+            }
+        }
+    }
+
+    /// Subscribes to the events about the todos, only events after this call arrive
+    pub async fn subscribe(&mut self) -> Events {
+        let response = self.send(get_request("/todos/events")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        Events(response.into_body())
+    }
+}
+
+/// The event stream of an app, see [`TestApp::subscribe`]
+pub struct Events(Body);
+
+impl Events {
+    /// The action and todo of the next event, failing if none arrives within five seconds
+    pub async fn next(&mut self) -> (String, Todo) {
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(5), self.0.frame())
+            .await
+            .expect("no event arrived")
+            .unwrap()
+            .unwrap();
+        let event = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+
+        let data = event
+            .lines()
+            .find_map(|line| line.strip_prefix("data: "))
+            .unwrap();
+        let data: serde_json::Value = serde_json::from_str(data).unwrap();
+        (
+            data["action"].as_str().unwrap().to_owned(),
+            serde_json::from_value(data["todo"].clone()).unwrap(),
+        )
+    }
+
+    /// The actions and todo ids of the next `count` events
+    pub async fn take(&mut self, count: usize) -> Vec<(String, u32)> {
+        let mut events = Vec::new();
+        for _ in 0..count {
+            let (action, todo) = self.next().await;
+            events.push((action, todo.id));
+        }
+        events
+    }
+}
+
+impl From<Router> for TestApp {
+    fn from(router: Router) -> Self {
+        Self { router }
+    }
+}
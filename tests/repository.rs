@@ -0,0 +1,752 @@
+//! Tests for apps backed by a repository other than SQLite, here a fake one keeping todos in a
+//! `Vec`, to check that the handlers work with any repository.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    async_trait,
+    body::Body,
+    http::{HeaderValue, Method, Request, StatusCode},
+};
+use chrono::{DateTime, Utc};
+use todo_server_workshop::{
+    app_with_repository, AppConfig, DayStats, DeleteError, Filter, ImportSummary, Inserted, MoveTo,
+    NewTodo, OnConflict, Page, ReorderError, RepositoryError, SearchCriteria, SearchResults, Sort,
+    Todo, TodoChange, TodoCounts, TodoPatch, TodoRepository, Toggled, ToggledMany, UpdateError,
+    Upserted, UserId,
+};
+
+use common::*;
+
+mod common;
+
+/// Keeps todos in memory, in the order they are listed in.
+///
+/// Only the user and completion status are filtered on, and subtasks, recurrences and history
+/// aren't supported.
+#[derive(Clone, Default)]
+struct MemoryRepository {
+    todos: Arc<Mutex<Vec<Todo>>>,
+    unreachable: bool,
+}
+
+impl MemoryRepository {
+    fn todos(&self) -> Vec<Todo> {
+        self.todos.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl TodoRepository for MemoryRepository {
+    async fn ping(&self) -> Result<(), RepositoryError> {
+        if self.unreachable {
+            return Err(RepositoryError::Other(
+                "the repository is unreachable".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn get_todo(&self, user: &UserId, id: u32) -> Result<Option<Todo>, RepositoryError> {
+        Ok(self
+            .todos()
+            .into_iter()
+            .find(|todo| todo.id == id && user.owns(todo) && todo.deleted_at.is_none()))
+    }
+
+    async fn todo_exists(&self, user: &UserId, id: u32) -> Result<bool, RepositoryError> {
+        Ok(self.get_todo(user, id).await?.is_some())
+    }
+
+    /// Picks the first incomplete todo, which is random enough for these tests
+    async fn random_pending(&self, user: &UserId) -> Result<Option<Todo>, RepositoryError> {
+        Ok(self
+            .todos()
+            .into_iter()
+            .find(|todo| is_live(user, todo) && !todo.completed && !todo.archived))
+    }
+
+    async fn get_todos(&self, user: &UserId) -> Result<Vec<Todo>, RepositoryError> {
+        let filter = Filter {
+            user: user.clone(),
+            ..Filter::default()
+        };
+        self.get_todos_filtered(filter, Sort::default(), None).await
+    }
+
+    async fn get_todos_after(
+        &self,
+        filter: Filter,
+        after: Option<u32>,
+        limit: u32,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        let mut todos: Vec<Todo> = self
+            .get_todos_filtered(filter, Sort::default(), None)
+            .await?
+            .into_iter()
+            .filter(|todo| after.is_none_or(|after| todo.id > after))
+            .collect();
+        todos.sort_by_key(|todo| todo.id);
+        todos.truncate(limit as usize);
+        Ok(todos)
+    }
+
+    /// Only the user and completion status are filtered on
+    async fn get_todos_filtered(
+        &self,
+        filter: Filter,
+        _sort: Sort,
+        page: Option<Page>,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        let page = page.unwrap_or(Page {
+            limit: u32::MAX,
+            offset: 0,
+        });
+
+        Ok(self
+            .todos()
+            .into_iter()
+            .filter(|todo| filter.user.owns(todo) && todo.deleted_at.is_none())
+            .filter(|todo| filter.completed.is_none_or(|done| todo.completed == done))
+            .skip(page.offset as usize)
+            .take(page.limit as usize)
+            .collect())
+    }
+
+    async fn search_todos(
+        &self,
+        criteria: SearchCriteria,
+    ) -> Result<SearchResults, RepositoryError> {
+        let matching = self
+            .get_todos_filtered(criteria.filter.clone(), criteria.sort, None)
+            .await?;
+        let todos = self
+            .get_todos_filtered(criteria.filter, criteria.sort, criteria.page)
+            .await?;
+
+        Ok(SearchResults {
+            todos,
+            total: matching.len() as u64,
+        })
+    }
+
+    async fn count_todos(&self, user: &UserId) -> Result<TodoCounts, RepositoryError> {
+        let todos = self.get_todos(user).await?;
+        let completed = todos.iter().filter(|todo| todo.completed).count() as u64;
+
+        Ok(TodoCounts {
+            total: todos.len() as u64,
+            active: todos.len() as u64 - completed,
+            completed,
+        })
+    }
+
+    async fn count_stored_todos(&self) -> Result<u64, RepositoryError> {
+        Ok(self.todos().len() as u64)
+    }
+
+    /// Only completions are counted, as creation times aren't kept
+    async fn daily_stats(
+        &self,
+        user: &UserId,
+    ) -> Result<BTreeMap<String, DayStats>, RepositoryError> {
+        let mut stats = BTreeMap::<String, DayStats>::new();
+        for todo in self.get_todos(user).await? {
+            if let Some(completed_at) = todo.completed_at {
+                let day = completed_at.date_naive().to_string();
+                stats.entry(day).or_default().completed += 1;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Changes aren't kept, so every todo has an empty history
+    async fn get_todo_history(
+        &self,
+        user: &UserId,
+        id: u32,
+    ) -> Result<Option<Vec<TodoChange>>, RepositoryError> {
+        let exists = self
+            .todos()
+            .iter()
+            .any(|todo| todo.id == id && user.owns(todo));
+        Ok(exists.then(Vec::new))
+    }
+
+    async fn insert_todo(
+        &self,
+        user: &UserId,
+        todo: NewTodo,
+        _idempotency_key: Option<String>,
+        unique_name: bool,
+    ) -> Result<Inserted, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        if unique_name
+            && todos
+                .iter()
+                .any(|stored| user.owns(stored) && stored.name.eq_ignore_ascii_case(&todo.name))
+        {
+            return Err(RepositoryError::DuplicateName);
+        }
+
+        let id = insert(&mut todos, user, todo)?;
+        Ok(Inserted {
+            id,
+            replayed: false,
+        })
+    }
+
+    /// Idempotency keys aren't kept, so every request is a new one
+    async fn idempotent_todo_id(
+        &self,
+        _user: &UserId,
+        _idempotency_key: &str,
+    ) -> Result<Option<u32>, RepositoryError> {
+        Ok(None)
+    }
+
+    async fn insert_todos(
+        &self,
+        user: &UserId,
+        todos: Vec<NewTodo>,
+    ) -> Result<Vec<u32>, RepositoryError> {
+        let mut stored = self.todos.lock().unwrap();
+        // Inserting into a copy leaves the stored todos alone if one of them fails
+        let mut inserted = stored.clone();
+        let ids = todos
+            .into_iter()
+            .map(|todo| insert(&mut inserted, user, todo))
+            .collect::<Result<_, _>>()?;
+
+        *stored = inserted;
+        Ok(ids)
+    }
+
+    async fn import_todos(
+        &self,
+        user: &UserId,
+        todos: Vec<Todo>,
+        on_conflict: OnConflict,
+    ) -> Result<ImportSummary, RepositoryError> {
+        let mut stored = self.todos.lock().unwrap();
+        let mut summary = ImportSummary::default();
+        for todo in todos {
+            let todo = Todo {
+                user_id: user.as_deref().map(Into::into),
+                ..todo
+            };
+            match stored.iter_mut().find(|stored| stored.id == todo.id) {
+                None => {
+                    stored.push(todo);
+                    summary.inserted += 1;
+                }
+                Some(existing) if user.owns(existing) && on_conflict == OnConflict::Replace => {
+                    *existing = todo;
+                    summary.replaced += 1;
+                }
+                Some(_) => summary.skipped += 1,
+            }
+        }
+        Ok(summary)
+    }
+
+    async fn update_todo(&self, user: &UserId, todo: Todo) -> Result<(), UpdateError> {
+        let mut todos = self.todos.lock().unwrap();
+        let stored = todos
+            .iter_mut()
+            .find(|stored| stored.id == todo.id && is_live(user, stored))
+            .ok_or(UpdateError::NotFound)?;
+
+        replace(stored, todo)
+    }
+
+    async fn upsert_todo(&self, user: &UserId, todo: Todo) -> Result<Upserted, UpdateError> {
+        let mut todos = self.todos.lock().unwrap();
+        let Some(stored) = todos.iter_mut().find(|stored| stored.id == todo.id) else {
+            todos.push(Todo {
+                user_id: user.as_deref().map(Into::into),
+                version: 0,
+                ..todo
+            });
+            return Ok(Upserted::Created);
+        };
+
+        if !is_live(user, stored) {
+            return Err(UpdateError::NotFound);
+        }
+        replace(stored, todo)?;
+        Ok(Upserted::Replaced)
+    }
+
+    async fn patch_todo(
+        &self,
+        user: &UserId,
+        id: u32,
+        patch: TodoPatch,
+    ) -> Result<Todo, UpdateError> {
+        let mut todos = self.todos.lock().unwrap();
+        let stored = todos
+            .iter_mut()
+            .find(|stored| stored.id == id && is_live(user, stored))
+            .ok_or(UpdateError::NotFound)?;
+
+        if patch
+            .version
+            .is_some_and(|version| version != stored.version)
+        {
+            return Err(UpdateError::VersionConflict);
+        }
+        if let Some(name) = patch.name {
+            stored.name = name;
+        }
+        if let Some(completed) = patch.completed {
+            set_completed(stored, completed);
+        }
+        if let Some(priority) = patch.priority {
+            stored.priority = priority;
+        }
+        if let Some(assignee) = patch.assignee {
+            stored.assignee = Some(assignee).filter(|assignee| !assignee.is_empty());
+        }
+        if let Some(description) = patch.description {
+            stored.description = Some(description).filter(|description| !description.is_empty());
+        }
+        stored.version += 1;
+        Ok(stored.clone())
+    }
+
+    async fn reorder_todos(&self, user: &UserId, ids: Vec<u32>) -> Result<(), ReorderError> {
+        let mut todos = self.todos.lock().unwrap();
+        let mut positions = ids
+            .iter()
+            .map(|&id| {
+                todos
+                    .iter()
+                    .position(|todo| todo.id == id && is_live(user, todo))
+                    .ok_or(ReorderError::NotFound(id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The listed todos take the positions they had between them, in the given order
+        let reordered: Vec<Todo> = positions
+            .iter()
+            .map(|&index| todos[index].clone())
+            .collect();
+        positions.sort_unstable();
+        for (index, todo) in positions.into_iter().zip(reordered) {
+            todos[index] = todo;
+        }
+        Ok(())
+    }
+
+    async fn move_todo(&self, user: &UserId, id: u32, to: MoveTo) -> Result<bool, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        let Some(index) = todos
+            .iter()
+            .position(|todo| todo.id == id && is_live(user, todo))
+        else {
+            return Ok(false);
+        };
+
+        let todo = todos.remove(index);
+        match to {
+            MoveTo::Top => todos.insert(0, todo),
+            MoveTo::Bottom => todos.push(todo),
+        }
+        Ok(true)
+    }
+
+    async fn toggle_todo(
+        &self,
+        user: &UserId,
+        id: u32,
+    ) -> Result<Option<Toggled>, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        let Some(todo) = todos
+            .iter_mut()
+            .find(|todo| todo.id == id && is_live(user, todo))
+        else {
+            return Ok(None);
+        };
+
+        set_completed(todo, !todo.completed);
+        Ok(Some(Toggled {
+            completed: todo.completed,
+            recurrence: None,
+        }))
+    }
+
+    async fn toggle_many(
+        &self,
+        user: &UserId,
+        ids: Vec<u32>,
+    ) -> Result<ToggledMany, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        let mut toggled = ToggledMany::default();
+        for id in ids {
+            match todos
+                .iter_mut()
+                .find(|todo| todo.id == id && is_live(user, todo))
+            {
+                Some(todo) => {
+                    set_completed(todo, !todo.completed);
+                    toggled.todos.push((todo.clone(), None));
+                }
+                None => toggled.missing.push(id),
+            }
+        }
+        Ok(toggled)
+    }
+
+    async fn set_completed(
+        &self,
+        user: &UserId,
+        id: u32,
+        completed: bool,
+    ) -> Result<Option<Todo>, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        Ok(todos
+            .iter_mut()
+            .find(|todo| todo.id == id && is_live(user, todo))
+            .map(|todo| {
+                let previous = todo.clone();
+                set_completed(todo, completed);
+                previous
+            }))
+    }
+
+    async fn set_archived(
+        &self,
+        user: &UserId,
+        id: u32,
+        archived: bool,
+    ) -> Result<Option<Todo>, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        Ok(todos
+            .iter_mut()
+            .find(|todo| todo.id == id && is_live(user, todo))
+            .map(|todo| {
+                let previous = todo.clone();
+                todo.archived = archived;
+                previous
+            }))
+    }
+
+    async fn set_all_completed(
+        &self,
+        user: &UserId,
+        completed: bool,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        let mut updated = Vec::new();
+        for todo in todos.iter_mut().filter(|todo| is_live(user, todo)) {
+            set_completed(todo, completed);
+            updated.push(todo.clone());
+        }
+        Ok(updated)
+    }
+
+    async fn replace_in_names(
+        &self,
+        user: &UserId,
+        find: String,
+        replace: String,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        let mut renamed = Vec::new();
+        for todo in todos
+            .iter_mut()
+            .filter(|todo| is_live(user, todo) && todo.name.contains(&find))
+        {
+            let name = todo.name.replace(&find, &replace);
+            if !name.trim().is_empty() {
+                todo.name = name;
+                renamed.push(todo.clone());
+            }
+        }
+        Ok(renamed)
+    }
+
+    /// Removes the todo for good, subtasks aren't supported
+    async fn delete_todo(
+        &self,
+        user: &UserId,
+        id: u32,
+        _cascade: bool,
+        dry_run: bool,
+    ) -> Result<Vec<Todo>, DeleteError> {
+        let mut todos = self.todos.lock().unwrap();
+        let index = todos
+            .iter()
+            .position(|todo| todo.id == id && user.owns(todo))
+            .ok_or(DeleteError::NotFound)?;
+
+        if dry_run {
+            return Ok(vec![todos[index].clone()]);
+        }
+        Ok(vec![todos.remove(index)])
+    }
+
+    async fn restore_todo(&self, user: &UserId, id: u32) -> Result<bool, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        let deleted = todos
+            .iter_mut()
+            .find(|todo| todo.id == id && user.owns(todo) && todo.deleted_at.is_some());
+
+        Ok(deleted.map(|todo| todo.deleted_at = None).is_some())
+    }
+
+    async fn delete_completed(
+        &self,
+        user: &UserId,
+        dry_run: bool,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        let mut deleted = Vec::new();
+        for todo in todos
+            .iter_mut()
+            .filter(|todo| is_live(user, todo) && todo.completed)
+        {
+            deleted.push(todo.clone());
+            if !dry_run {
+                todo.deleted_at = Some(Utc::now());
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Subtasks aren't supported, so no todo is kept for having them
+    async fn purge_completed(
+        &self,
+        completed_before: DateTime<Utc>,
+    ) -> Result<usize, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        let before = todos.len();
+        todos.retain(|todo| {
+            !todo.completed
+                || todo
+                    .completed_at
+                    .is_none_or(|completed_at| completed_at >= completed_before)
+        });
+        Ok(before - todos.len())
+    }
+
+    async fn delete_many(
+        &self,
+        user: &UserId,
+        ids: Vec<u32>,
+        dry_run: bool,
+    ) -> Result<Vec<Todo>, RepositoryError> {
+        let mut todos = self.todos.lock().unwrap();
+        let mut deleted = Vec::new();
+        for todo in todos
+            .iter_mut()
+            .filter(|todo| is_live(user, todo) && ids.contains(&todo.id))
+        {
+            deleted.push(todo.clone());
+            if !dry_run {
+                todo.deleted_at = Some(Utc::now());
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+/// Whether the todo belongs to the user and isn't deleted
+fn is_live(user: &UserId, todo: &Todo) -> bool {
+    user.owns(todo) && todo.deleted_at.is_none()
+}
+
+/// Adds the todo at the end, with the next free id unless it has one
+fn insert(todos: &mut Vec<Todo>, user: &UserId, todo: NewTodo) -> Result<u32, RepositoryError> {
+    let id = match todo.id {
+        Some(id) if todos.iter().any(|todo| todo.id == id) => {
+            return Err(RepositoryError::Conflict)
+        }
+        Some(id) => id,
+        None => todos.iter().map(|todo| todo.id).max().unwrap_or(0) + 1,
+    };
+
+    todos.push(Todo {
+        id,
+        name: todo.name,
+        completed: todo.completed,
+        user_id: user.as_deref().map(Into::into),
+        ..Todo::default()
+    });
+    Ok(id)
+}
+
+/// Replaces the stored todo as long as it is still at the version of `todo`
+fn replace(stored: &mut Todo, todo: Todo) -> Result<(), UpdateError> {
+    if stored.version != todo.version {
+        return Err(UpdateError::VersionConflict);
+    }
+    *stored = Todo {
+        version: stored.version + 1,
+        user_id: stored.user_id.take(),
+        deleted_at: None,
+        ..todo
+    };
+    Ok(())
+}
+
+fn set_completed(todo: &mut Todo, completed: bool) {
+    if todo.completed != completed {
+        todo.completed_at = completed.then(Utc::now);
+    }
+    todo.completed = completed;
+}
+
+async fn test_app(repository: MemoryRepository) -> TestApp {
+    TestApp::from(app_with_repository(repository, AppConfig::default()).await)
+}
+
+#[tokio::test]
+async fn created_todos_are_kept_in_the_repository() {
+    let repository = MemoryRepository::default();
+    let mut app = test_app(repository.clone()).await;
+
+    let created = app.create_todo(default_todo()).await;
+
+    assert_eq!(created, default_todo());
+    assert_eq!(repository.todos(), vec![default_todo()]);
+    assert_eq!(app.get_todos().await, vec![default_todo()]);
+}
+
+#[tokio::test]
+async fn toggling_changes_the_todo_in_the_repository() {
+    let repository = MemoryRepository::default();
+    let mut app = test_app(repository.clone()).await;
+    app.create_todo(default_todo()).await;
+
+    assert_eq!(app.toggle(1).await, Some(true));
+
+    assert!(repository.todos()[0].completed);
+    assert_eq!(app.toggle(2).await, None);
+}
+
+#[tokio::test]
+async fn conflicts_reported_by_the_repository_are_answered_with_409() {
+    let mut app = test_app(MemoryRepository::default()).await;
+    app.create_todo(default_todo()).await;
+
+    let response = app.send(post_todo_request(default_todo())).await;
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn health_check_fails_when_the_repository_is_unreachable() {
+    let repository = MemoryRepository {
+        unreachable: true,
+        ..MemoryRepository::default()
+    };
+    let mut app = test_app(repository).await;
+
+    let response = app.send(get_request("/health")).await;
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn todos_of_other_users_are_not_found_in_the_repository() {
+    let repository = MemoryRepository::default();
+    let mut app = test_app(repository.clone()).await;
+    let mut request = json_request(Method::POST, "/todos", &new_todo_body(&default_todo()));
+    request
+        .headers_mut()
+        .insert("x-user-id", HeaderValue::from_static("alice"));
+
+    let response = app.send(request).await;
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(repository.todos()[0].user_id.as_deref(), Some("alice"));
+
+    assert_eq!(app.get_todo(1).await, None);
+    assert_eq!(app.get_todos().await, vec![]);
+}
+
+#[tokio::test]
+async fn updates_are_written_to_the_repository() {
+    let repository = MemoryRepository::default();
+    let mut app = test_app(repository.clone()).await;
+    app.create_todo(default_todo()).await;
+    let updated = Todo {
+        name: "Walk the dog".into(),
+        ..default_todo()
+    };
+
+    let response = app
+        .send(json_request(Method::PUT, "/todos", &updated))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(repository.todos()[0].name, "Walk the dog");
+    assert_eq!(repository.todos()[0].version, 1);
+}
+
+#[tokio::test]
+async fn version_conflicts_reported_by_the_repository_are_answered_with_409() {
+    let mut app = test_app(MemoryRepository::default()).await;
+    app.create_todo(default_todo()).await;
+    let outdated = Todo {
+        version: 3,
+        ..default_todo()
+    };
+
+    let response = app
+        .send(json_request(Method::PUT, "/todos", &outdated))
+        .await;
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn deleting_removes_the_todo_from_the_repository() {
+    let repository = MemoryRepository::default();
+    let mut app = test_app(repository.clone()).await;
+    app.create_todo(default_todo()).await;
+
+    let request = Request::builder()
+        .uri("/todos/1")
+        .method(Method::DELETE)
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = fetch(&mut app.router, request).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        serde_json::from_slice::<Todo>(&body).unwrap(),
+        default_todo()
+    );
+    assert_eq!(repository.todos(), vec![]);
+}
+
+#[tokio::test]
+async fn counts_come_from_the_repository() {
+    let mut app = test_app(MemoryRepository::default()).await;
+    app.create_todo(default_todo()).await;
+    app.create_todo(Todo {
+        id: 2,
+        completed: true,
+        ..default_todo()
+    })
+    .await;
+
+    let response = app.send(get_request("/todos/count")).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let counts: TodoCounts = read_json(response).await;
+    assert_eq!(
+        counts,
+        TodoCounts {
+            total: 2,
+            active: 1,
+            completed: 1,
+        }
+    );
+}
@@ -0,0 +1,59 @@
+//! Tests for apps configured with a short request timeout.
+
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use todo_server_workshop::{app_with_pool, AppConfig, ConnectionPool};
+use tokio_rusqlite::Connection;
+use tower::ServiceExt;
+
+async fn app(connection: Connection) -> axum::Router {
+    let config = AppConfig::builder()
+        .request_timeout(Duration::from_secs(1))
+        .build();
+    app_with_pool(ConnectionPool::single(connection), config)
+        .await
+        .unwrap()
+}
+
+fn get_todos_request() -> Request<Body> {
+    Request::builder()
+        .uri("/todos")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn answers_requests_stuck_on_the_database_with_408() {
+    let connection = Connection::open_in_memory().await.unwrap();
+    let app = app(connection.clone()).await;
+
+    // Wedge the only connection, so the request has to wait for it
+    let blocker = connection.clone();
+    tokio::spawn(async move {
+        blocker
+            .call(|_| {
+                std::thread::sleep(Duration::from_secs(3));
+                Ok(())
+            })
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let response = app.oneshot(get_todos_request()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+}
+
+#[tokio::test]
+async fn lets_quick_requests_through() {
+    let connection = Connection::open_in_memory().await.unwrap();
+    let app = app(connection).await;
+
+    let response = app.oneshot(get_todos_request()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -2,16 +2,19 @@ use axum::{
     body::Body,
     http::{Request, StatusCode},
 };
+use common::*;
 use http_body_util::BodyExt;
-use todo_server_workshop::{app, Todo};
-use tower::{Service, ServiceExt};
+use todo_server_workshop::{app, app_in_memory, AppConfig, AppError, Todo};
+use tower::ServiceExt;
+
+mod common;
 
 mod part1 {
     use super::*;
 
     #[tokio::test]
     async fn returns_empty_200_at_index() {
-        let app = app(":memory:".into()).await;
+        let app = app_in_memory().await;
 
         let response = app
             .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
@@ -20,32 +23,22 @@ mod part1 {
 
         assert_eq!(response.status(), StatusCode::OK);
     }
-}
 
-fn get_todos_request() -> Request<Body> {
-    Request::builder()
-        .uri("/todos")
-        .body(Body::empty())
-        .unwrap()
-}
+    #[tokio::test]
+    async fn health_check_pings_database() {
+        let app = app_in_memory().await;
 
-fn post_todo_request(todo: Todo) -> Request<Body> {
-    Request::builder()
-        .uri("/todos")
-        .method(axum::http::Method::POST)
-        .header(
-            axum::http::header::CONTENT_TYPE,
-            "application/json; charset=utf-8",
-        )
-        .body(Body::from(serde_json::to_string(&todo).unwrap()))
-        .unwrap()
-}
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/health")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
-fn default_todo() -> Todo {
-    Todo {
-        id: 1,
-        name: "Remember to store the todo".into(),
-        completed: false,
+        assert_eq!(response.status(), StatusCode::OK);
     }
 }
 
@@ -54,13 +47,13 @@ mod part2 {
 
     #[tokio::test]
     async fn returns_empty_list_of_todos() {
-        let app = app(":memory:".into()).await;
+        let app = app_in_memory().await;
 
         let response = app.oneshot(get_todos_request()).await.unwrap();
 
         assert!(response.status().is_success());
 
-        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = read_body(response).await;
         assert_eq!(&body[..], b"[]");
     }
 }
@@ -70,45 +63,22 @@ mod part3 {
 
     #[tokio::test]
     async fn returns_201_created_on_new_todo() {
-        let app = app(":memory:".into()).await;
-
-        let todo = default_todo();
+        let mut app = TestApp::new().await;
 
-        let response = app.oneshot(post_todo_request(todo)).await.unwrap();
+        let response = app.send(post_todo_request(default_todo())).await;
 
         assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(read_created(response).await, default_todo());
     }
 
     #[tokio::test]
     async fn persists_a_todo() {
-        let mut app = app(":memory:".into()).await;
-
-        let todo = default_todo();
-
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(post_todo_request(todo.clone()))
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
-
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(get_todos_request())
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
+        let mut app = TestApp::new().await;
 
-        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let created = app.create_todo(default_todo()).await;
 
-        assert_eq!(
-            serde_json::from_slice::<Vec<Todo>>(&body).unwrap(),
-            vec![todo]
-        );
+        assert_eq!(created, default_todo());
+        assert_eq!(app.get_todos().await, vec![default_todo()]);
     }
 }
 
@@ -117,55 +87,20 @@ mod part4 {
 
     #[tokio::test]
     async fn can_get_specific_todo() {
-        let mut app = app(":memory:".into()).await;
-
-        let todo = default_todo();
-
-        // Create todo
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(post_todo_request(todo.clone()))
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
-
-        // Fetch specific todo
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(
-                Request::builder()
-                    .uri("/todos/1")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
+        let mut app = TestApp::new().await;
 
-        let body = response.into_body().collect().await.unwrap().to_bytes();
+        app.create_todo(default_todo()).await;
 
-        assert_eq!(serde_json::from_slice::<Todo>(&body).unwrap(), todo);
+        assert_eq!(app.get_todo(1).await, Some(default_todo()));
     }
 
     #[tokio::test]
-    async fn fetching_nonexisting_todo_returns_400() {
-        let app = app(":memory:".into()).await;
+    async fn fetching_nonexisting_todo_returns_404() {
+        let mut app = TestApp::new().await;
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/todos/123")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        let response = app.send(get_request("/todos/123")).await;
 
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 }
 
@@ -174,78 +109,31 @@ mod part5 {
 
     #[tokio::test]
     async fn can_toggle_todo() {
-        let mut app = app(":memory:".into()).await;
-
-        let todo = default_todo();
-
-        // Create todo
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(post_todo_request(todo.clone()))
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
+        let mut app = TestApp::new().await;
+        let todo = app.create_todo(default_todo()).await;
 
-        // Toggle the todo
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(
-                Request::builder()
-                    .uri("/toggle/1")
-                    .method(axum::http::Method::POST)
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
+        assert_eq!(app.toggle(1).await, Some(true));
 
         // Fetch the todo and assert its completion status
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(
-                Request::builder()
-                    .uri("/todos/1")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
-
-        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let toggled = app.get_todo(1).await.unwrap();
 
+        assert!(toggled.completed_at.is_some());
         assert_eq!(
-            serde_json::from_slice::<Todo>(&body).unwrap(),
+            toggled,
             Todo {
                 completed: true,
+                version: 1,
+                completed_at: toggled.completed_at,
                 ..todo
             }
         );
     }
 
     #[tokio::test]
-    async fn toggling_nonexisting_todo_returns_400() {
-        let app = app(":memory:".into()).await;
-
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/toggle/1")
-                    .method(axum::http::Method::POST)
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+    async fn toggling_nonexisting_todo_returns_404() {
+        let mut app = TestApp::new().await;
 
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(app.toggle(1).await, None);
     }
 }
 
@@ -254,19 +142,8 @@ mod part6 {
 
     #[tokio::test]
     async fn updating_a_todo_works_correctly() {
-        let mut app = app(":memory:".into()).await;
-
-        let todo = default_todo();
-
-        // Create todo
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(post_todo_request(todo.clone()))
-            .await
-            .unwrap();
-
-        assert!(response.status().is_success());
+        let mut app = TestApp::new().await;
+        let todo = app.create_todo(default_todo()).await;
 
         let updated = Todo {
             name: "Wow, it works!".into(),
@@ -274,66 +151,40 @@ mod part6 {
             ..todo
         };
 
-        // Toggle the todo
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(
-                Request::builder()
-                    .uri("/todos")
-                    .method(axum::http::Method::PUT)
-                    .header(
-                        axum::http::header::CONTENT_TYPE,
-                        "application/json; charset=utf-8",
-                    )
-                    .body(Body::from(serde_json::to_string(&updated).unwrap()))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        let response = app
+            .send(json_request(axum::http::Method::PUT, "/todos", &updated))
+            .await;
 
-        assert!(response.status().is_success());
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(read_body(response).await.is_empty());
 
         // Fetch the todo and assert it worked
-        let response = ServiceExt::<Request<Body>>::ready(&mut app)
-            .await
-            .unwrap()
-            .call(
-                Request::builder()
-                    .uri("/todos/1")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        let fetched = app.get_todo(1).await.unwrap();
 
-        assert!(response.status().is_success());
-
-        let body = response.into_body().collect().await.unwrap().to_bytes();
-
-        assert_eq!(serde_json::from_slice::<Todo>(&body).unwrap(), updated);
+        assert_eq!(fetched.completed_at.is_some(), updated.completed);
+        assert_eq!(
+            fetched,
+            Todo {
+                version: 1,
+                completed_at: fetched.completed_at,
+                ..updated
+            }
+        );
     }
 
     #[tokio::test]
-    async fn updating_nonexisting_todo_returns_400() {
-        let app = app(":memory:".into()).await;
+    async fn updating_nonexisting_todo_returns_404() {
+        let mut app = TestApp::new().await;
 
         let response = app
-            .oneshot(
-                Request::builder()
-                    .uri("/todos")
-                    .method(axum::http::Method::PUT)
-                    .header(
-                        axum::http::header::CONTENT_TYPE,
-                        "application/json; charset=utf-8",
-                    )
-                    .body(Body::from(serde_json::to_string(&default_todo()).unwrap()))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+            .send(json_request(
+                axum::http::Method::PUT,
+                "/todos",
+                &default_todo(),
+            ))
+            .await;
 
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 }
 
@@ -347,7 +198,9 @@ mod part7 {
 
         // Create a todo
         {
-            let app = app(db_path.clone()).await;
+            let app = app(AppConfig::builder().db_path(db_path.clone()).build())
+                .await
+                .unwrap();
 
             let todo = default_todo();
 
@@ -358,13 +211,15 @@ mod part7 {
 
         // Running the server anew to check if the data still exists
         {
-            let app = app(db_path).await;
+            let app = app(AppConfig::builder().db_path(db_path).build())
+                .await
+                .unwrap();
 
             let response = app.oneshot(get_todos_request()).await.unwrap();
 
             assert!(response.status().is_success());
 
-            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let body = read_body(response).await;
 
             assert_eq!(
                 serde_json::from_slice::<Vec<Todo>>(&body).unwrap(),
@@ -373,3 +228,6026 @@ mod part7 {
         }
     }
 }
+
+mod delete_todo {
+    use super::*;
+
+    #[tokio::test]
+    async fn deleting_a_todo_removes_it() {
+        let mut app = app_in_memory().await;
+
+        let todo = default_todo();
+
+        // Create todo
+        let response = send(&mut app, post_todo_request(todo.clone())).await;
+
+        assert!(response.status().is_success());
+
+        // Delete the todo
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .method(axum::http::Method::DELETE)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Fetching the deleted todo should fail
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_todo_returns_it() {
+        let mut app = app_in_memory().await;
+
+        let todo = Todo {
+            priority: todo_server_workshop::Priority::High,
+            ..default_todo()
+        };
+        let response = send(&mut app, post_todo_request(todo)).await;
+        let created = read_created(response).await;
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .method(axum::http::Method::DELETE)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = read_body(response).await;
+        assert_eq!(serde_json::from_slice::<Todo>(&body).unwrap(), created);
+    }
+
+    #[tokio::test]
+    async fn deleting_nonexisting_todo_returns_404() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/todos/1")
+                    .method(axum::http::Method::DELETE)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+mod generated_ids {
+    use super::*;
+
+    fn post_new_todo_request(name: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/todos")
+            .method(axum::http::Method::POST)
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                "application/json; charset=utf-8",
+            )
+            .body(Body::from(format!(
+                r#"{{"name": "{name}", "completed": false}}"#
+            )))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn assigns_ids_to_todos_without_one() {
+        let mut app = app_in_memory().await;
+
+        for expected_id in 1..=2 {
+            let response = send(&mut app, post_new_todo_request("Generate my id")).await;
+
+            assert_eq!(response.status(), StatusCode::CREATED);
+
+            assert_eq!(
+                read_created(response).await,
+                Todo {
+                    id: expected_id,
+                    name: "Generate my id".into(),
+                    completed: false,
+                    ..Default::default()
+                }
+            );
+        }
+    }
+}
+
+mod duplicate_ids {
+    use super::*;
+
+    #[tokio::test]
+    async fn posting_duplicate_id_returns_409() {
+        let mut app = app_in_memory().await;
+
+        let todo = default_todo();
+
+        let response = send(&mut app, post_todo_request(todo.clone())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = send(&mut app, post_todo_request(todo.clone())).await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        // The server should still be responsive
+        let response = send(&mut app, get_todos_request()).await;
+
+        assert!(response.status().is_success());
+
+        let body = read_body(response).await;
+
+        assert_eq!(
+            serde_json::from_slice::<Vec<Todo>>(&body).unwrap(),
+            vec![todo]
+        );
+    }
+}
+
+mod patch_todo {
+    use super::*;
+
+    fn patch_todo_request(id: u32, patch: &str) -> Request<Body> {
+        Request::builder()
+            .uri(format!("/todos/{id}"))
+            .method(axum::http::Method::PATCH)
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                "application/json; charset=utf-8",
+            )
+            .body(Body::from(patch.to_owned()))
+            .unwrap()
+    }
+
+    async fn patch_default_todo(patch: &str) -> Todo {
+        let mut app = app_in_memory().await;
+
+        // Create todo
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert!(response.status().is_success());
+
+        // Patch the todo
+        let response = send(&mut app, patch_todo_request(1, patch)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Fetch the todo to make sure the patch was stored
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert!(response.status().is_success());
+
+        let body = read_body(response).await;
+        serde_json::from_slice::<Todo>(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn patching_name_keeps_completion() {
+        let patched = patch_default_todo(r#"{"name": "Only the name changed"}"#).await;
+
+        assert_eq!(
+            patched,
+            Todo {
+                name: "Only the name changed".into(),
+                version: 1,
+                ..default_todo()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn patching_completion_keeps_name() {
+        let patched = patch_default_todo(r#"{"completed": true}"#).await;
+
+        assert!(patched.completed_at.is_some());
+        assert_eq!(
+            patched,
+            Todo {
+                completed: true,
+                version: 1,
+                completed_at: patched.completed_at,
+                ..default_todo()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_patch_changes_nothing() {
+        let patched = patch_default_todo("{}").await;
+
+        assert_eq!(patched, default_todo());
+    }
+
+    #[tokio::test]
+    async fn patching_nonexisting_todo_returns_404() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(patch_todo_request(1, r#"{"completed": true}"#))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+mod filter_completed {
+    use super::*;
+
+    async fn get_todos_with_query(query: &str) -> Vec<Todo> {
+        let mut app = app_in_memory().await;
+
+        let todos = [
+            Todo {
+                id: 1,
+                name: "Done".into(),
+                completed: true,
+                ..Default::default()
+            },
+            Todo {
+                id: 2,
+                name: "Not done".into(),
+                completed: false,
+                ..Default::default()
+            },
+            Todo {
+                id: 3,
+                name: "Also done".into(),
+                completed: true,
+                ..Default::default()
+            },
+        ];
+
+        for todo in todos {
+            let response = send(&mut app, post_todo_request(todo)).await;
+
+            assert!(response.status().is_success());
+        }
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri(format!("/todos{query}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert!(response.status().is_success());
+
+        let body = read_body(response).await;
+        serde_json::from_slice::<Vec<Todo>>(&body).unwrap()
+    }
+
+    fn ids(todos: &[Todo]) -> Vec<u32> {
+        todos.iter().map(|todo| todo.id).collect()
+    }
+
+    #[tokio::test]
+    async fn returns_only_completed_todos() {
+        let todos = get_todos_with_query("?completed=true").await;
+        assert_eq!(ids(&todos), vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn returns_only_active_todos() {
+        let todos = get_todos_with_query("?completed=false").await;
+        assert_eq!(ids(&todos), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn returns_all_todos_without_filter() {
+        let todos = get_todos_with_query("").await;
+        assert_eq!(ids(&todos), vec![1, 2, 3]);
+    }
+}
+
+mod pagination {
+    use super::*;
+
+    async fn seeded_app() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=100 {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    name: format!("Todo number {id}"),
+                    completed: false,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+            assert!(response.status().is_success());
+        }
+
+        app
+    }
+
+    async fn get_ids(app: &mut axum::Router, query: &str) -> Result<Vec<u32>, StatusCode> {
+        let response = send(
+            app,
+            Request::builder()
+                .uri(format!("/todos{query}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        if !response.status().is_success() {
+            return Err(response.status());
+        }
+
+        let body = read_body(response).await;
+        let todos = serde_json::from_slice::<Vec<Todo>>(&body).unwrap();
+        Ok(todos.into_iter().map(|todo| todo.id).collect())
+    }
+
+    #[tokio::test]
+    async fn defaults_to_first_50_todos() {
+        let mut app = seeded_app().await;
+
+        let ids = get_ids(&mut app, "").await.unwrap();
+
+        assert_eq!(ids, (1..=50).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn returns_the_requested_page() {
+        let mut app = seeded_app().await;
+
+        let ids = get_ids(&mut app, "?limit=20&offset=40").await.unwrap();
+
+        assert_eq!(ids, (41..=60).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn last_page_is_partial() {
+        let mut app = seeded_app().await;
+
+        let ids = get_ids(&mut app, "?limit=30&offset=90").await.unwrap();
+        assert_eq!(ids, (91..=100).collect::<Vec<_>>());
+
+        let ids = get_ids(&mut app, "?offset=100").await.unwrap();
+        assert!(ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_limits() {
+        let mut app = seeded_app().await;
+
+        assert_eq!(
+            get_ids(&mut app, "?limit=1000").await,
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            get_ids(&mut app, "?limit=-1").await,
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            get_ids(&mut app, "?offset=-20").await,
+            Err(StatusCode::BAD_REQUEST)
+        );
+    }
+}
+
+mod clear_completed {
+    use super::*;
+
+    fn clear_completed_request(query: &str) -> Request<Body> {
+        Request::builder()
+            .uri(format!("/todos{query}"))
+            .method(axum::http::Method::DELETE)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn deletes_only_completed_todos() {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=5 {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    name: format!("Todo number {id}"),
+                    // Three completed, two active
+                    completed: id % 2 == 1,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+            assert!(response.status().is_success());
+        }
+
+        let response = send(&mut app, clear_completed_request("?completed=true")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "deleted": 3 })
+        );
+
+        let response = send(&mut app, get_todos_request()).await;
+
+        let body = read_body(response).await;
+        let remaining = serde_json::from_slice::<Vec<Todo>>(&body).unwrap();
+
+        assert_eq!(
+            remaining.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert!(remaining.iter().all(|todo| !todo.completed));
+    }
+
+    #[tokio::test]
+    async fn refuses_to_delete_without_filter() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert!(response.status().is_success());
+
+        for query in ["", "?completed=false"] {
+            let response = send(&mut app, clear_completed_request(query)).await;
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let response = send(&mut app, get_todos_request()).await;
+
+        let body = read_body(response).await;
+
+        assert_eq!(
+            serde_json::from_slice::<Vec<Todo>>(&body).unwrap(),
+            vec![default_todo()]
+        );
+    }
+}
+
+mod database_errors {
+    use super::*;
+
+    /// An app whose todos table was dropped behind its back, so every query fails
+    async fn broken_app(name: &str) -> axum::Router {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/{name}_{uuid}.db");
+
+        let app = app(AppConfig::builder().db_path(db_path.clone()).build())
+            .await
+            .unwrap();
+
+        rusqlite::Connection::open(&db_path)
+            .unwrap()
+            .execute("DROP TABLE todos", [])
+            .unwrap();
+
+        app
+    }
+
+    #[tokio::test]
+    async fn failing_database_returns_500() {
+        let app = broken_app("failing_database_returns_500").await;
+
+        let response = app.oneshot(get_todos_request()).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = read_body(response).await;
+        let error = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+
+        assert!(error["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn failing_to_clear_completed_todos_returns_500() {
+        let mut app = broken_app("failing_to_clear_completed_todos_returns_500").await;
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos?completed=true")
+                .method(axum::http::Method::DELETE)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn failing_to_toggle_all_returns_500() {
+        let mut app = broken_app("failing_to_toggle_all_returns_500").await;
+
+        let response = send(
+            &mut app,
+            json_request(
+                axum::http::Method::POST,
+                "/todos/toggle-all",
+                &serde_json::json!({ "completed": true }),
+            ),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn failing_to_reset_returns_500() {
+        let mut app = broken_app("failing_to_reset_returns_500").await;
+
+        let response = send(&mut app, post_request("/todos/reset")).await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: serde_json::Value = read_json(response).await;
+        assert_eq!(body["error"], "resetting the todos failed");
+    }
+
+    #[tokio::test]
+    async fn failing_lookups_are_not_mistaken_for_missing_todos() {
+        let mut app = broken_app("failing_lookups_are_not_mistaken_for_missing_todos").await;
+
+        for request in [
+            get_request("/todos/1"),
+            post_request("/toggle/1"),
+            post_request("/todos/1/restore"),
+            json_request(
+                axum::http::Method::PATCH,
+                "/todos/1",
+                &serde_json::json!({ "name": "Walk the dog" }),
+            ),
+            json_request(axum::http::Method::PUT, "/todos", &default_todo()),
+            Request::builder()
+                .uri("/todos/1")
+                .method(axum::http::Method::DELETE)
+                .body(Body::empty())
+                .unwrap(),
+        ] {
+            let uri = request.uri().clone();
+            let response = send(&mut app, request).await;
+
+            assert_eq!(
+                response.status(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "{uri}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn corrupt_database_file_is_an_error() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/corrupt_database_file_is_an_error_{uuid}.db");
+        std::fs::write(
+            &db_path,
+            "this is not a SQLite database, just some text".repeat(100),
+        )
+        .unwrap();
+
+        let error = app(AppConfig::builder().db_path(db_path.clone()).build())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, AppError::CorruptDatabase { .. }));
+        let message = error.to_string();
+        assert!(message.contains(&db_path), "{message}");
+        assert!(message.contains("not a database"), "{message}");
+    }
+}
+
+mod in_memory {
+    use super::*;
+
+    #[tokio::test]
+    async fn in_memory_apps_are_isolated() {
+        let mut first = app_in_memory().await;
+        let mut second = app_in_memory().await;
+
+        let response = send(&mut first, post_todo_request(default_todo())).await;
+
+        assert!(response.status().is_success());
+
+        // The first app keeps its todo between requests
+        let response = send(&mut first, get_todos_request()).await;
+
+        let body = read_body(response).await;
+
+        assert_eq!(
+            serde_json::from_slice::<Vec<Todo>>(&body).unwrap(),
+            vec![default_todo()]
+        );
+
+        // While the second never sees it
+        let response = send(&mut second, get_todos_request()).await;
+
+        let body = read_body(response).await;
+
+        assert_eq!(&body[..], b"[]");
+    }
+}
+
+mod validation {
+    use super::*;
+
+    fn todo_named(name: String) -> Todo {
+        Todo {
+            name,
+            ..default_todo()
+        }
+    }
+
+    async fn create_status(name: String) -> (StatusCode, serde_json::Value) {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(post_todo_request(todo_named(name)))
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_and_blank_names() {
+        for name in ["", "   ", "\t\n"] {
+            let (status, body) = create_status(name.into()).await;
+
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(body["field"], "name");
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_names_up_to_the_max_length() {
+        let (status, _) = create_status("a".repeat(255)).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn rejects_names_over_the_max_length() {
+        let (status, body) = create_status("a".repeat(256)).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field"], "name");
+    }
+
+    #[tokio::test]
+    async fn counts_characters_rather_than_bytes() {
+        // Each of these takes four bytes in UTF-8, and the skin tone makes two characters
+        let name = "🎉🚀👍🏽✅".repeat(51);
+        assert_eq!(name.chars().count(), 255);
+
+        let mut app = TestApp::new().await;
+        let created = app
+            .create_todo(Todo {
+                name: name.clone(),
+                ..default_todo()
+            })
+            .await;
+
+        assert_eq!(created.name, name);
+        assert_eq!(app.get_todo(1).await.unwrap().name, name);
+
+        let (status, body) = create_status(format!("{name}🎉")).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field"], "name");
+    }
+
+    #[tokio::test]
+    async fn database_rejects_names_over_the_max_length() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/database_rejects_names_over_the_max_length_{uuid}.db");
+
+        let app = app(AppConfig::builder().db_path(db_path.clone()).build())
+            .await
+            .unwrap();
+
+        let response = app
+            .oneshot(post_todo_request(default_todo()))
+            .await
+            .unwrap();
+
+        assert!(response.status().is_success());
+
+        // Writing to the database directly skips the handlers' validation
+        let connection = rusqlite::Connection::open(&db_path).unwrap();
+
+        let error = connection
+            .execute(
+                "INSERT INTO todos (name, completed) VALUES (?1, 0)",
+                ["a".repeat(256)],
+            )
+            .unwrap_err();
+
+        assert!(error.to_string().contains("at most 255 characters"));
+
+        let error = connection
+            .execute("UPDATE todos SET name = ?1", ["a".repeat(256)])
+            .unwrap_err();
+
+        assert!(error.to_string().contains("at most 255 characters"));
+
+        connection
+            .execute(
+                "INSERT INTO todos (name, completed) VALUES (?1, 0)",
+                ["a".repeat(255)],
+            )
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_names_on_update() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert!(response.status().is_success());
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos")
+                .method(axum::http::Method::PUT)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(Body::from(
+                    serde_json::to_string(&todo_named(" ".into())).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .method(axum::http::Method::PATCH)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(Body::from(format!(r#"{{"name": "{}"}}"#, "a".repeat(256))))
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+mod count {
+    use super::*;
+    use todo_server_workshop::TodoCounts;
+
+    async fn get_counts(app: &mut axum::Router) -> TodoCounts {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/todos/count")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn counts_are_zero_without_todos() {
+        let mut app = app_in_memory().await;
+
+        assert_eq!(get_counts(&mut app).await, TodoCounts::default());
+    }
+
+    #[tokio::test]
+    async fn counts_completed_and_active_todos() {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=10 {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    name: format!("Todo number {id}"),
+                    completed: id <= 4,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+            assert!(response.status().is_success());
+        }
+
+        assert_eq!(
+            get_counts(&mut app).await,
+            TodoCounts {
+                total: 10,
+                completed: 4,
+                active: 6,
+            }
+        );
+    }
+}
+
+mod search {
+    use super::*;
+
+    async fn search(query: &str) -> Vec<u32> {
+        let mut app = app_in_memory().await;
+
+        let todos = [
+            (1, "Buy milk", false),
+            (2, "Drink a MILKSHAKE", true),
+            (3, "Walk the dog", false),
+            (4, "100% done", true),
+        ];
+
+        for (id, name, completed) in todos {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    name: name.into(),
+                    completed,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+            assert!(response.status().is_success());
+        }
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri(format!("/todos?{query}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert!(response.status().is_success());
+
+        let body = read_body(response).await;
+        let todos = serde_json::from_slice::<Vec<Todo>>(&body).unwrap();
+        todos.into_iter().map(|todo| todo.id).collect()
+    }
+
+    #[tokio::test]
+    async fn matches_substrings_ignoring_case() {
+        assert_eq!(search("q=milk").await, vec![1, 2]);
+        assert_eq!(search("q=MiLk").await, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn returns_nothing_without_matches() {
+        assert!(search("q=groceries").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn combines_with_completed_filter() {
+        assert_eq!(search("q=milk&completed=true").await, vec![2]);
+        assert_eq!(search("q=milk&completed=false").await, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn treats_wildcards_literally() {
+        assert_eq!(search("q=%25").await, vec![4]);
+        assert!(search("q=_").await.is_empty());
+    }
+}
+
+mod batch {
+    use super::*;
+
+    fn post_batch_request(body: serde_json::Value) -> Request<Body> {
+        Request::builder()
+            .uri("/todos/batch")
+            .method(axum::http::Method::POST)
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                "application/json; charset=utf-8",
+            )
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn get_todos(app: &mut axum::Router) -> Vec<Todo> {
+        let response = send(app, get_todos_request()).await;
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn creates_all_todos() {
+        let mut app = app_in_memory().await;
+
+        let names: Vec<String> = (1..=5).map(|n| format!("Imported todo {n}")).collect();
+        let body = serde_json::Value::Array(
+            names
+                .iter()
+                .map(|name| serde_json::json!({ "name": name, "completed": false }))
+                .collect(),
+        );
+
+        let response = send(&mut app, post_batch_request(body)).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = read_body(response).await;
+        let ids = serde_json::from_slice::<Vec<u32>>(&body).unwrap();
+
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+
+        let todos = get_todos(&mut app).await;
+
+        assert_eq!(
+            todos.into_iter().map(|todo| todo.name).collect::<Vec<_>>(),
+            names
+        );
+    }
+
+    #[tokio::test]
+    async fn stores_nothing_when_one_todo_fails() {
+        let mut app = app_in_memory().await;
+
+        let body = serde_json::json!([
+            { "id": 1, "name": "First", "completed": false },
+            { "id": 2, "name": "Second", "completed": false },
+            { "id": 1, "name": "Duplicate of the first", "completed": false },
+        ]);
+
+        let response = send(&mut app, post_batch_request(body)).await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        assert!(get_todos(&mut app).await.is_empty());
+    }
+}
+
+mod toggle_all {
+    use super::*;
+
+    #[tokio::test]
+    async fn sets_every_todo_completed() {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=4 {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    name: format!("Todo number {id}"),
+                    completed: id % 2 == 0,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+            assert!(response.status().is_success());
+        }
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/toggle-all")
+                .method(axum::http::Method::POST)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(Body::from(r#"{"completed": true}"#))
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "updated": 4 })
+        );
+
+        let response = send(&mut app, get_todos_request()).await;
+
+        let body = read_body(response).await;
+        let todos = serde_json::from_slice::<Vec<Todo>>(&body).unwrap();
+
+        assert_eq!(todos.len(), 4);
+        assert!(todos.iter().all(|todo| todo.completed));
+    }
+}
+
+mod cors {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_preflight_requests() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/todos")
+                    .method(axum::http::Method::OPTIONS)
+                    .header(axum::http::header::ORIGIN, "http://localhost:3000")
+                    .header(axum::http::header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                    .header(
+                        axum::http::header::ACCESS_CONTROL_REQUEST_HEADERS,
+                        "content-type",
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert!(response.status().is_success());
+
+        let headers = response.headers();
+
+        assert_eq!(
+            headers[axum::http::header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "*"
+        );
+
+        let allowed_methods = headers[axum::http::header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap();
+        for method in ["GET", "POST", "PUT", "PATCH", "DELETE"] {
+            assert!(allowed_methods.contains(method), "{method} is not allowed");
+        }
+
+        assert_eq!(
+            headers[axum::http::header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type,idempotency-key,x-api-key,x-user-id,x-request-id"
+        );
+    }
+}
+
+mod sorting {
+    use super::*;
+
+    async fn sorted(query: &str) -> Result<Vec<String>, StatusCode> {
+        let mut app = app_in_memory().await;
+
+        for (id, name) in [(1, "banana"), (2, "cherry"), (3, "apple")] {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    name: name.into(),
+                    completed: false,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+            assert!(response.status().is_success());
+        }
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri(format!("/todos?{query}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        if !response.status().is_success() {
+            return Err(response.status());
+        }
+
+        let body = read_body(response).await;
+        let todos = serde_json::from_slice::<Vec<Todo>>(&body).unwrap();
+        Ok(todos.into_iter().map(|todo| todo.name).collect())
+    }
+
+    #[tokio::test]
+    async fn sorts_by_name_ascending() {
+        assert_eq!(
+            sorted("sort=name&order=asc").await.unwrap(),
+            vec!["apple", "banana", "cherry"]
+        );
+        // Ascending is the default order
+        assert_eq!(
+            sorted("sort=name").await.unwrap(),
+            vec!["apple", "banana", "cherry"]
+        );
+    }
+
+    #[tokio::test]
+    async fn sorts_by_id_descending() {
+        assert_eq!(
+            sorted("sort=id&order=desc").await.unwrap(),
+            vec!["apple", "cherry", "banana"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_sort_fields() {
+        assert_eq!(
+            sorted("sort=name%3BDROP%20TABLE%20todos").await,
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(sorted("order=sideways").await, Err(StatusCode::BAD_REQUEST));
+    }
+}
+
+mod due_dates {
+    use super::*;
+
+    fn due_date(rfc3339: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+        Some(rfc3339.parse().unwrap())
+    }
+
+    async fn get_todos_with_query(app: &mut axum::Router, query: &str) -> Vec<Todo> {
+        let response = send(
+            app,
+            Request::builder()
+                .uri(format!("/todos{query}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert!(response.status().is_success());
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn due_dates_round_trip_as_rfc3339() {
+        let mut app = app_in_memory().await;
+
+        let todo = Todo {
+            due_date: due_date("2024-01-31T12:30:00Z"),
+            ..default_todo()
+        };
+
+        let response = send(&mut app, post_todo_request(todo.clone())).await;
+
+        assert!(response.status().is_success());
+
+        let body = read_body(response).await;
+        let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+
+        assert_eq!(json[0][key("due_date")], "2024-01-31T12:30:00Z");
+        assert_eq!(get_todos_with_query(&mut app, "").await, vec![todo]);
+    }
+
+    #[tokio::test]
+    async fn filters_overdue_todos() {
+        let mut app = app_in_memory().await;
+
+        let todos = [
+            // Overdue
+            (1, false, due_date("2000-01-01T00:00:00Z")),
+            // Due in the future
+            (2, false, due_date("2999-01-01T00:00:00Z")),
+            // No due date, never overdue
+            (3, false, None),
+            // Completed after all, so not overdue
+            (4, true, due_date("2000-01-01T00:00:00Z")),
+        ];
+
+        for (id, completed, due_date) in todos {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    name: format!("Todo number {id}"),
+                    completed,
+                    due_date,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+            assert!(response.status().is_success());
+        }
+
+        let ids = |todos: Vec<Todo>| todos.into_iter().map(|todo| todo.id).collect::<Vec<_>>();
+
+        assert_eq!(
+            ids(get_todos_with_query(&mut app, "?overdue=true").await),
+            vec![1]
+        );
+        assert_eq!(
+            ids(get_todos_with_query(&mut app, "?overdue=false").await),
+            vec![2, 3, 4]
+        );
+    }
+}
+
+mod priority {
+    use super::*;
+    use todo_server_workshop::Priority;
+
+    #[test]
+    fn priorities_serialize_as_strings() {
+        let json = serde_json::to_value(Todo {
+            priority: Priority::High,
+            ..default_todo()
+        })
+        .unwrap();
+
+        assert_eq!(json["priority"], "high");
+
+        for (name, priority) in [
+            ("low", Priority::Low),
+            ("medium", Priority::Medium),
+            ("high", Priority::High),
+        ] {
+            assert_eq!(
+                serde_json::from_value::<Priority>(serde_json::json!(name)).unwrap(),
+                priority
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn new_todos_default_to_medium() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/todos")
+                    .method(axum::http::Method::POST)
+                    .header(
+                        axum::http::header::CONTENT_TYPE,
+                        "application/json; charset=utf-8",
+                    )
+                    .body(Body::from(r#"{"name": "No priority", "completed": false}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        assert_eq!(read_created(response).await.priority, Priority::Medium);
+    }
+
+    #[tokio::test]
+    async fn sorts_by_priority() {
+        let mut app = app_in_memory().await;
+
+        let todos = [
+            (1, Priority::Medium),
+            (2, Priority::High),
+            (3, Priority::Low),
+            (4, Priority::High),
+        ];
+
+        for (id, priority) in todos {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    name: format!("Todo number {id}"),
+                    priority,
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+            assert!(response.status().is_success());
+        }
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos?sort=priority&order=desc")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert!(response.status().is_success());
+
+        let body = read_body(response).await;
+        let todos = serde_json::from_slice::<Vec<Todo>>(&body).unwrap();
+
+        assert_eq!(
+            todos
+                .iter()
+                .map(|todo| (todo.id, todo.priority))
+                .collect::<Vec<_>>(),
+            vec![
+                (4, Priority::High),
+                (2, Priority::High),
+                (1, Priority::Medium),
+                (3, Priority::Low),
+            ]
+        );
+    }
+}
+
+mod graceful_shutdown {
+    use super::*;
+
+    #[tokio::test]
+    async fn stops_accepting_connections_after_shutdown() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (shutdown, shutdown_signal) = tokio::sync::oneshot::channel::<()>();
+
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app_in_memory().await)
+                .with_graceful_shutdown(async {
+                    shutdown_signal.await.ok();
+                })
+                .await
+                .unwrap();
+        });
+
+        // The server accepts connections while running
+        tokio::net::TcpStream::connect(addr).await.unwrap();
+
+        shutdown.send(()).unwrap();
+        server.await.unwrap();
+
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+}
+
+mod connection_pool {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_many_concurrent_reads() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/serves_many_concurrent_reads_{uuid}.db");
+
+        let app = app(AppConfig::builder().db_path(db_path).build())
+            .await
+            .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(post_todo_request(default_todo()))
+            .await
+            .unwrap();
+
+        assert!(response.status().is_success());
+
+        let requests: Vec<_> = (0..200)
+            .map(|_| {
+                let app = app.clone();
+                tokio::spawn(async move { app.oneshot(get_todos_request()).await.unwrap() })
+            })
+            .collect();
+
+        for request in requests {
+            let response = request.await.unwrap();
+            assert!(response.status().is_success());
+
+            let body = read_body(response).await;
+            assert_eq!(
+                serde_json::from_slice::<Vec<Todo>>(&body).unwrap(),
+                vec![default_todo()]
+            );
+        }
+    }
+}
+
+mod metrics {
+    use super::*;
+
+    #[tokio::test]
+    async fn counts_requests_per_endpoint() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, get_todos_request()).await;
+
+        assert!(response.status().is_success());
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/metrics")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        let metrics = String::from_utf8(body.to_vec()).unwrap();
+
+        // Other tests share the global recorder, so only check that the counter went up at all
+        let count = metrics
+            .lines()
+            .find(|line| {
+                line.starts_with("http_requests_total{")
+                    && line.contains(r#"method="GET""#)
+                    && line.contains(r#"path="/todos""#)
+                    && line.contains(r#"status="200""#)
+            })
+            .and_then(|line| line.rsplit(' ').next())
+            .and_then(|count| count.parse::<u64>().ok())
+            .expect("no request counter for GET /todos");
+
+        assert!(count >= 1);
+        assert!(metrics.contains("http_request_duration_seconds"));
+    }
+}
+
+mod migrations {
+    use super::*;
+
+    fn applied_versions(db_path: &str) -> Vec<u32> {
+        let connection = rusqlite::Connection::open(db_path).unwrap();
+        let mut stmt = connection
+            .prepare("SELECT version FROM schema_version ORDER BY version")
+            .unwrap();
+        let versions = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        versions
+    }
+
+    #[tokio::test]
+    async fn migrating_twice_is_a_noop() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/migrating_twice_is_a_noop_{uuid}.db");
+
+        {
+            let app = app(AppConfig::builder().db_path(db_path.clone()).build())
+                .await
+                .unwrap();
+
+            let response = app
+                .oneshot(post_todo_request(default_todo()))
+                .await
+                .unwrap();
+
+            assert!(response.status().is_success());
+        }
+
+        let versions = applied_versions(&db_path);
+        assert!(!versions.is_empty());
+        assert_eq!(versions, (1..=versions.len() as u32).collect::<Vec<_>>());
+
+        // Starting the server again runs the migrations again
+        let app = app(AppConfig::builder().db_path(db_path.clone()).build())
+            .await
+            .unwrap();
+
+        assert_eq!(applied_versions(&db_path), versions);
+
+        let response = app.oneshot(get_todos_request()).await.unwrap();
+        let body = read_body(response).await;
+
+        assert_eq!(
+            serde_json::from_slice::<Vec<Todo>>(&body).unwrap(),
+            vec![default_todo()]
+        );
+    }
+}
+
+mod tags {
+    use super::*;
+    use todo_server_workshop::Priority;
+
+    async fn get_todos(app: &mut axum::Router, uri: &str) -> Vec<Todo> {
+        let response = send(
+            app,
+            Request::builder().uri(uri).body(Body::empty()).unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn creates_todo_with_tags() {
+        let mut app = app_in_memory().await;
+
+        let response = send(
+            &mut app,
+            post_todo_request(Todo {
+                tags: vec!["work".into(), "urgent".into()],
+                ..default_todo()
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        assert_eq!(
+            read_created(response).await.tags,
+            vec!["urgent".to_string(), "work".to_string()]
+        );
+
+        let todos = get_todos(&mut app, "/todos").await;
+
+        assert_eq!(
+            todos[0].tags,
+            vec!["urgent".to_string(), "work".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn update_replaces_tags() {
+        let mut app = app_in_memory().await;
+
+        let response = send(
+            &mut app,
+            post_todo_request(Todo {
+                tags: vec!["work".into()],
+                ..default_todo()
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos")
+                .method(axum::http::Method::PUT)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(Body::from(
+                    serde_json::to_string(&Todo {
+                        priority: Priority::High,
+                        tags: vec!["home".into()],
+                        ..default_todo()
+                    })
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let todos = get_todos(&mut app, "/todos").await;
+
+        assert_eq!(todos[0].tags, vec!["home".to_string()]);
+        assert_eq!(todos[0].priority, Priority::High);
+    }
+
+    #[tokio::test]
+    async fn filters_by_tag() {
+        let mut app = app_in_memory().await;
+
+        let todos = [
+            (1, vec!["work"]),
+            (2, vec!["home"]),
+            (3, vec!["home", "work"]),
+            (4, vec![]),
+        ];
+
+        for (id, tags) in todos {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    name: format!("Todo number {id}"),
+                    tags: tags.into_iter().map(Into::into).collect(),
+                    ..Default::default()
+                }),
+            )
+            .await;
+
+            assert!(response.status().is_success());
+        }
+
+        let todos = get_todos(&mut app, "/todos?tag=work").await;
+
+        assert_eq!(
+            todos.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+    }
+}
+
+mod openapi {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_openapi_spec() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api-docs/openapi.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        let spec = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+
+        let todos = &spec["paths"]["/todos"];
+        for method in ["get", "post", "put", "delete"] {
+            assert!(
+                todos[method].is_object(),
+                "{method} /todos is not documented"
+            );
+        }
+        assert!(spec["paths"]["/todos/{id}"]["patch"].is_object());
+        assert!(spec["components"]["schemas"]["Todo"].is_object());
+    }
+}
+
+mod soft_delete {
+    use super::*;
+
+    fn request(method: axum::http::Method, uri: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .method(method)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn list(app: &mut axum::Router, uri: &str) -> Vec<Todo> {
+        let response = send(app, request(axum::http::Method::GET, uri)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn deleted_todos_are_hidden_and_can_be_restored() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = send(&mut app, request(axum::http::Method::DELETE, "/todos/1")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert!(list(&mut app, "/todos").await.is_empty());
+
+        // The deleted todo is still there when asked for
+        let todos = list(&mut app, "/todos?include_deleted=true").await;
+        assert_eq!(todos.len(), 1);
+        assert!(todos[0].deleted_at.is_some());
+
+        let response = send(
+            &mut app,
+            request(axum::http::Method::POST, "/todos/1/restore"),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        assert_eq!(list(&mut app, "/todos").await, vec![default_todo()]);
+    }
+
+    #[tokio::test]
+    async fn restoring_a_todo_that_is_not_deleted_returns_404() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        for uri in ["/todos/1/restore", "/todos/2/restore"] {
+            let response = send(&mut app, request(axum::http::Method::POST, uri)).await;
+
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
+
+mod location {
+    use super::*;
+
+    #[tokio::test]
+    async fn created_todo_has_location_header() {
+        let mut app = app_in_memory().await;
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos")
+                .method(axum::http::Method::POST)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(Body::from(
+                    r#"{"name": "Walk the dog", "completed": false}"#,
+                ))
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let location = response.headers()[axum::http::header::LOCATION]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let created = read_created(response).await;
+
+        assert_eq!(location, format!("/todos/{}", created.id));
+
+        // The location points at the created todo
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri(location)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+mod body_limit {
+    use super::*;
+    use todo_server_workshop::DEFAULT_MAX_BODY_BYTES;
+
+    #[tokio::test]
+    async fn rejects_oversized_bodies() {
+        let app = app_in_memory().await;
+
+        // A valid JSON string, just far too long
+        let body = format!(
+            r#"{{"name": "{}", "completed": false}}"#,
+            "a".repeat(DEFAULT_MAX_BODY_BYTES)
+        );
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/todos")
+                    .method(axum::http::Method::POST)
+                    .header(
+                        axum::http::header::CONTENT_TYPE,
+                        "application/json; charset=utf-8",
+                    )
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
+
+mod idempotency {
+    use super::*;
+
+    fn post_with_key(name: &str, key: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/todos")
+            .method(axum::http::Method::POST)
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                "application/json; charset=utf-8",
+            )
+            .header("Idempotency-Key", key)
+            .body(Body::from(format!(
+                r#"{{"name": "{name}", "completed": false}}"#
+            )))
+            .unwrap()
+    }
+
+    async fn list(app: &mut axum::Router) -> Vec<Todo> {
+        let response = send(app, get_todos_request()).await;
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn retrying_with_the_same_key_creates_one_todo() {
+        let mut app = app_in_memory().await;
+
+        let mut created = Vec::new();
+        for _ in 0..2 {
+            let response = send(&mut app, post_with_key("Walk the dog", "retry-me")).await;
+
+            assert_eq!(response.status(), StatusCode::CREATED);
+
+            created.push(read_created(response).await);
+        }
+
+        assert_eq!(created[0], created[1]);
+        assert_eq!(list(&mut app).await, vec![created[0].clone()]);
+    }
+
+    #[tokio::test]
+    async fn different_keys_create_different_todos() {
+        let mut app = app_in_memory().await;
+
+        for key in ["first", "second"] {
+            let response = send(&mut app, post_with_key("Walk the dog", key)).await;
+
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        assert_eq!(list(&mut app).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn keys_are_only_replayed_for_the_user_that_sent_them() {
+        let mut app = app_in_memory().await;
+
+        let mut created = Vec::new();
+        for (user, name) in [("alice", "Walk the dog"), ("bob", "Feed the cat")] {
+            let mut request = post_with_key(name, "shared");
+            request
+                .headers_mut()
+                .insert("x-user-id", user.parse().unwrap());
+            let response = send(&mut app, request).await;
+
+            assert_eq!(response.status(), StatusCode::CREATED);
+
+            created.push(read_created(response).await);
+        }
+
+        assert_eq!(created[1].name, "Feed the cat");
+        assert_ne!(created[0].id, created[1].id);
+    }
+}
+
+mod events {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn subscribers_are_told_about_created_todos() {
+        let mut app = app_in_memory().await;
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/events")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[axum::http::header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+
+        let mut events = response.into_body();
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let frame = tokio::time::timeout(Duration::from_secs(5), events.frame())
+            .await
+            .expect("no event arrived")
+            .unwrap()
+            .unwrap();
+        let event = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+
+        let mut lines = event.lines();
+        assert_eq!(lines.next(), Some("event: created"));
+
+        let data = lines.next().unwrap().strip_prefix("data: ").unwrap();
+        let data = serde_json::from_str::<serde_json::Value>(data).unwrap();
+
+        assert_eq!(data["action"], "created");
+        assert_eq!(
+            serde_json::from_value::<Todo>(data["todo"].clone()).unwrap(),
+            default_todo()
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_told_about_each_todo_created_in_a_batch() {
+        let mut app = TestApp::new().await;
+        let mut events = app.subscribe().await;
+
+        let response = app
+            .send(json_request(
+                axum::http::Method::POST,
+                "/todos/batch",
+                &serde_json::json!([{ "name": "First" }, { "name": "Second" }]),
+            ))
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        assert_eq!(
+            events.take(2).await,
+            vec![("created".into(), 1), ("created".into(), 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_told_about_each_todo_toggled_at_once() {
+        let mut app = TestApp::new().await;
+        for id in 1..=2 {
+            app.create_todo(Todo {
+                id,
+                ..default_todo()
+            })
+            .await;
+        }
+        let mut events = app.subscribe().await;
+
+        app.send(json_request(
+            axum::http::Method::POST,
+            "/todos/toggle-all",
+            &serde_json::json!({ "completed": true }),
+        ))
+        .await;
+
+        assert_eq!(
+            events.take(2).await,
+            vec![("toggled".into(), 1), ("toggled".into(), 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_told_about_each_cleared_todo() {
+        let mut app = TestApp::new().await;
+        for id in 1..=3 {
+            app.create_todo(Todo {
+                id,
+                completed: id != 2,
+                ..default_todo()
+            })
+            .await;
+        }
+        let mut events = app.subscribe().await;
+
+        app.send(
+            Request::builder()
+                .uri("/todos?completed=true")
+                .method(axum::http::Method::DELETE)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(
+            events.take(2).await,
+            vec![("deleted".into(), 1), ("deleted".into(), 3)]
+        );
+    }
+}
+
+mod websocket {
+    use std::time::Duration;
+
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+    use super::*;
+
+    type Socket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+    async fn connect() -> Socket {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::serve(listener, app_in_memory().await).await.unwrap();
+        });
+
+        let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+            .await
+            .unwrap();
+        socket
+    }
+
+    async fn send(socket: &mut Socket, message: serde_json::Value) {
+        socket
+            .send(Message::Text(message.to_string()))
+            .await
+            .unwrap();
+    }
+
+    async fn receive(socket: &mut Socket) -> serde_json::Value {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("no message arrived")
+            .unwrap()
+            .unwrap();
+
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn creating_and_toggling_a_todo_echoes_events() {
+        let mut socket = connect().await;
+
+        send(
+            &mut socket,
+            serde_json::json!({
+                "type": "create",
+                "todo": { "name": "Walk the dog", "completed": false },
+            }),
+        )
+        .await;
+
+        let event = receive(&mut socket).await;
+        assert_eq!(event["type"], "event");
+        assert_eq!(event["action"], "created");
+        assert_eq!(event["todo"]["name"], "Walk the dog");
+
+        let id = event["todo"]["id"].clone();
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "toggle", "id": id }),
+        )
+        .await;
+
+        let event = receive(&mut socket).await;
+        assert_eq!(event["action"], "toggled");
+        assert_eq!(event["todo"]["completed"], true);
+    }
+
+    #[tokio::test]
+    async fn invalid_commands_get_an_error() {
+        let mut socket = connect().await;
+
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "toggle", "id": 42 }),
+        )
+        .await;
+
+        let message = receive(&mut socket).await;
+        assert_eq!(message["type"], "error");
+        assert_eq!(message["error"], "todo 42 does not exist");
+    }
+}
+
+mod rate_limit {
+    use std::net::SocketAddr;
+
+    use axum::extract::ConnectInfo;
+    use todo_server_workshop::DEFAULT_RATE_LIMIT;
+
+    use super::*;
+
+    fn request_from(addr: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/todos")
+            .extension(ConnectInfo(addr.parse::<SocketAddr>().unwrap()))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_past_the_limit() {
+        let mut app = app_in_memory().await;
+
+        for _ in 0..DEFAULT_RATE_LIMIT {
+            let response = send(&mut app, request_from("10.0.0.1:1234")).await;
+
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = send(&mut app, request_from("10.0.0.1:1234")).await;
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let retry_after: u64 = response.headers()[axum::http::header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after));
+
+        // Other clients have their own limit
+        let response = send(&mut app, request_from("10.0.0.2:1234")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+mod csv_export {
+    use super::*;
+
+    #[tokio::test]
+    async fn exports_todos_as_csv() {
+        let mut app = app_in_memory().await;
+
+        for todo in [
+            default_todo(),
+            Todo {
+                id: 2,
+                name: r#"Buy "fresh" milk, eggs"#.into(),
+                completed: true,
+                ..Default::default()
+            },
+        ] {
+            let response = send(&mut app, post_todo_request(todo)).await;
+
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos.csv")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[axum::http::header::CONTENT_TYPE],
+            "text/csv; charset=utf-8"
+        );
+
+        let body = read_body(response).await;
+
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            "id,name,completed\n\
+             1,Remember to store the todo,false\n\
+             2,\"Buy \"\"fresh\"\" milk, eggs\",true\n"
+        );
+    }
+}
+
+mod ndjson_export {
+    use super::*;
+
+    #[tokio::test]
+    async fn streams_every_todo_on_its_own_line() {
+        let mut app = app_in_memory().await;
+
+        // More than one batch, so the export has to continue where the previous batch ended
+        let todos: Vec<serde_json::Value> = (1..=250)
+            .map(|id| serde_json::json!({ "name": format!("Todo {id}"), "completed": id % 3 == 0 }))
+            .collect();
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos")
+                .method(axum::http::Method::POST)
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_string(&todos).unwrap()))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos.ndjson")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[axum::http::header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+
+        let body = read_body(response).await;
+        let exported: Vec<Todo> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(exported.len(), 250);
+        for (todo, id) in exported.iter().zip(1..) {
+            assert_eq!(todo.id, id);
+            assert_eq!(todo.name, format!("Todo {id}"));
+            assert_eq!(todo.completed, id % 3 == 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn exports_nothing_without_todos() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/todos.ndjson")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = read_body(response).await;
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn includes_the_todo_with_id_zero() {
+        let mut app = TestApp::new().await;
+        for id in 0..=1 {
+            app.create_todo(Todo {
+                id,
+                ..default_todo()
+            })
+            .await;
+        }
+
+        let response = app.send(get_request("/todos.ndjson")).await;
+
+        let body = read_body(response).await;
+        let ids: Vec<u32> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Todo>(line).unwrap().id)
+            .collect();
+        assert_eq!(ids, vec![0, 1]);
+    }
+}
+
+mod import {
+    use super::*;
+
+    fn import_request(on_conflict: &str, todos: &[Todo]) -> Request<Body> {
+        Request::builder()
+            .uri(format!("/todos/import?on_conflict={on_conflict}"))
+            .method(axum::http::Method::POST)
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                "application/json; charset=utf-8",
+            )
+            .body(Body::from(serde_json::to_string(todos).unwrap()))
+            .unwrap()
+    }
+
+    /// Creates the default todo, then imports a changed version of it along with a new todo
+    async fn import(on_conflict: &str) -> (serde_json::Value, Vec<Todo>) {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let imported = [
+            Todo {
+                name: "Imported over the existing todo".into(),
+                ..default_todo()
+            },
+            Todo {
+                id: 2,
+                name: "Imported todo".into(),
+                tags: vec!["backup".into()],
+                ..Default::default()
+            },
+        ];
+
+        let response = send(&mut app, import_request(on_conflict, &imported)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        let summary = serde_json::from_slice(&body).unwrap();
+
+        let response = send(&mut app, get_todos_request()).await;
+
+        let body = read_body(response).await;
+        let todos = serde_json::from_slice(&body).unwrap();
+
+        (summary, todos)
+    }
+
+    #[tokio::test]
+    async fn skips_existing_todos() {
+        let (summary, todos) = import("skip").await;
+
+        assert_eq!(
+            summary,
+            serde_json::json!({ "inserted": 1, "replaced": 0, "skipped": 1 })
+        );
+        assert_eq!(todos[0], default_todo());
+        assert_eq!(todos[1].name, "Imported todo");
+        assert_eq!(todos[1].tags, vec!["backup".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn replaces_existing_todos() {
+        let (summary, todos) = import("replace").await;
+
+        assert_eq!(
+            summary,
+            serde_json::json!({ "inserted": 1, "replaced": 1, "skipped": 0 })
+        );
+        assert_eq!(todos[0].name, "Imported over the existing todo");
+        assert_eq!(todos[1].name, "Imported todo");
+    }
+}
+
+mod etag {
+    use super::*;
+
+    fn get_todo_request(if_none_match: Option<&str>) -> Request<Body> {
+        let mut request = Request::builder().uri("/todos/1");
+
+        if let Some(etag) = if_none_match {
+            request = request.header(axum::http::header::IF_NONE_MATCH, etag);
+        }
+
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn returns_304_for_matching_etag() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = send(&mut app, get_todo_request(None)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let etag = response.headers()[axum::http::header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        let response = send(&mut app, get_todo_request(Some(&etag))).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[axum::http::header::ETAG], etag.as_str());
+
+        let body = read_body(response).await;
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn etag_changes_when_the_todo_changes() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = send(&mut app, get_todo_request(None)).await;
+
+        let etag = response.headers()[axum::http::header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/toggle/1")
+                .method(axum::http::Method::POST)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send(&mut app, get_todo_request(Some(&etag))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[axum::http::header::ETAG], etag.as_str());
+    }
+}
+
+mod versions {
+    use super::*;
+
+    fn put_todo_request(todo: &Todo) -> Request<Body> {
+        Request::builder()
+            .uri("/todos")
+            .method(axum::http::Method::PUT)
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                "application/json; charset=utf-8",
+            )
+            .body(Body::from(serde_json::to_string(todo).unwrap()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn stale_update_is_rejected() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // Two clients both read version 0, the first one to write wins
+        let first = Todo {
+            name: "First edit".into(),
+            ..default_todo()
+        };
+        let second = Todo {
+            name: "Second edit".into(),
+            ..default_todo()
+        };
+
+        let response = send(&mut app, put_todo_request(&first)).await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = send(&mut app, put_todo_request(&second)).await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        // A stale patch is rejected the same way
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .method(axum::http::Method::PATCH)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(Body::from(r#"{"completed": true, "version": 0}"#))
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = send(&mut app, get_todos_request()).await;
+
+        let body = read_body(response).await;
+
+        assert_eq!(
+            serde_json::from_slice::<Vec<Todo>>(&body).unwrap(),
+            vec![Todo {
+                version: 1,
+                ..first
+            }]
+        );
+    }
+}
+
+mod history {
+    use todo_server_workshop::{ChangeAction, TodoChange};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn records_creating_and_toggling() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/toggle/1")
+                .method(axum::http::Method::POST)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1/history")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        let history = serde_json::from_slice::<Vec<TodoChange>>(&body).unwrap();
+
+        assert_eq!(
+            history
+                .iter()
+                .map(|change| (change.action, change.completed))
+                .collect::<Vec<_>>(),
+            vec![
+                (ChangeAction::Created, false),
+                (ChangeAction::Toggled, true)
+            ]
+        );
+        assert!(history[0].changed_at <= history[1].changed_at);
+    }
+
+    #[tokio::test]
+    async fn history_of_nonexisting_todo_returns_404() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/todos/1/history")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+mod error_bodies {
+    use super::*;
+
+    #[tokio::test]
+    async fn missing_todo_has_error_body() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/todos/42")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = read_body(response).await;
+
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({
+                "error": "todo 42 does not exist",
+                "code": "todo_not_found",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn validation_errors_name_the_field() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(post_todo_request(Todo {
+                name: " ".into(),
+                ..default_todo()
+            }))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = read_body(response).await;
+        let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+
+        assert_eq!(body["code"], "validation_failed");
+        assert_eq!(body["field"], "name");
+        assert!(body["error"].is_string());
+    }
+    #[tokio::test]
+    async fn non_numeric_ids_have_error_body() {
+        let mut app = TestApp::new().await;
+
+        let response = app.send(get_request("/todos/abc")).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body: serde_json::Value = read_json(response).await;
+
+        assert_eq!(body["code"], "invalid_path");
+        assert!(body["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn invalid_query_parameters_have_error_body() {
+        let mut app = TestApp::new().await;
+
+        for query in ["limit=abc", "sort=bogus"] {
+            let response = app.send(get_request(&format!("/todos?{query}"))).await;
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{query}");
+
+            let body: serde_json::Value = read_json(response).await;
+
+            assert_eq!(body["code"], "invalid_query", "{query}");
+        }
+    }
+}
+
+mod create_one_or_many {
+    use super::*;
+
+    fn post_json(body: &'static str) -> Request<Body> {
+        Request::builder()
+            .uri("/todos")
+            .method(axum::http::Method::POST)
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                "application/json; charset=utf-8",
+            )
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn single_object_returns_a_list_of_one() {
+        let app = app_in_memory().await;
+
+        let response = app
+            .oneshot(post_json(r#"{"name": "Walk the dog", "completed": false}"#))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(response
+            .headers()
+            .contains_key(axum::http::header::LOCATION));
+
+        let todo = read_created(response).await;
+
+        assert_eq!(todo.name, "Walk the dog");
+    }
+
+    #[tokio::test]
+    async fn array_returns_every_created_todo() {
+        let mut app = app_in_memory().await;
+
+        let response = send(
+            &mut app,
+            post_json(
+                r#"[{"name": "Buy milk", "completed": false},
+                    {"id": 7, "name": "Buy bread", "completed": true}]"#,
+            ),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = read_body(response).await;
+        let created = serde_json::from_slice::<Vec<Todo>>(&body).unwrap();
+
+        assert_eq!(created.len(), 2);
+        assert_eq!(created[0].name, "Buy milk");
+        assert_eq!(created[1].id, 7);
+        assert!(created[1].completed);
+
+        let response = app.oneshot(get_todos_request()).await.unwrap();
+        let body = read_body(response).await;
+
+        assert_eq!(serde_json::from_slice::<Vec<Todo>>(&body).unwrap(), created);
+    }
+
+    #[tokio::test]
+    async fn invalid_todo_in_array_creates_nothing() {
+        let mut app = app_in_memory().await;
+
+        let response = send(
+            &mut app,
+            post_json(
+                r#"[{"name": "Buy milk", "completed": false},
+                    {"name": " ", "completed": false}]"#,
+            ),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = app.oneshot(get_todos_request()).await.unwrap();
+        let body = read_body(response).await;
+
+        assert!(serde_json::from_slice::<Vec<Todo>>(&body)
+            .unwrap()
+            .is_empty());
+    }
+}
+
+mod table_name {
+    use super::*;
+    use todo_server_workshop::TableName;
+
+    async fn list(app: &mut axum::Router) -> Vec<Todo> {
+        let response = send(app, get_todos_request()).await;
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn apps_with_different_tables_are_isolated() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/apps_with_different_tables_are_isolated_{uuid}.db");
+
+        let config = |table: &str| {
+            AppConfig::builder()
+                .db_path(db_path.clone())
+                .table_name(TableName::new(table).unwrap())
+                .build()
+        };
+        let mut home = app(config("home")).await.unwrap();
+        let mut work = app(config("work")).await.unwrap();
+
+        let response = send(&mut home, post_todo_request(default_todo())).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        assert_eq!(list(&mut home).await, vec![default_todo()]);
+        assert_eq!(list(&mut work).await, vec![]);
+
+        // The same id is free in the other table
+        let response = send(
+            &mut work,
+            post_todo_request(Todo {
+                name: "File the report".into(),
+                ..default_todo()
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(list(&mut home).await, vec![default_todo()]);
+        assert_eq!(list(&mut work).await[0].name, "File the report");
+
+        // The default table is untouched by both
+        let mut default = app(AppConfig::builder().db_path(db_path).build())
+            .await
+            .unwrap();
+        assert_eq!(list(&mut default).await, vec![]);
+    }
+
+    #[test]
+    fn rejects_names_that_are_not_identifiers() {
+        for name in [
+            "",
+            "1todos",
+            "todos; DROP TABLE todos",
+            "to-dos",
+            "\"todos\"",
+            "tödos",
+            "tags",
+            "sqlite_master",
+            &"t".repeat(65),
+        ] {
+            assert!(TableName::new(name).is_err(), "{name:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn accepts_identifiers() {
+        for name in ["todos", "work", "_private", "team_42_todos"] {
+            assert_eq!(TableName::new(name).unwrap().as_str(), name);
+        }
+    }
+}
+
+mod concurrent_writes {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_toggles_all_succeed() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/concurrent_toggles_all_succeed_{uuid}.db");
+
+        // A database file gets a pool of connections, so the toggles race each other
+        let app = app(AppConfig::builder().db_path(db_path).build())
+            .await
+            .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(post_todo_request(default_todo()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let toggles = (0..20).map(|_| {
+            let app = app.clone();
+            tokio::spawn(async move {
+                app.oneshot(
+                    Request::builder()
+                        .uri("/toggle/1")
+                        .method(axum::http::Method::POST)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .status()
+            })
+        });
+
+        for toggle in toggles.collect::<Vec<_>>() {
+            assert_eq!(toggle.await.unwrap(), StatusCode::OK);
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/todos/1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = read_body(response).await;
+        let todo = serde_json::from_slice::<Todo>(&body).unwrap();
+
+        // Every toggle was applied exactly once
+        assert!(!todo.completed);
+        assert_eq!(todo.version, 20);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn two_concurrent_toggles_cancel_out() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/two_concurrent_toggles_cancel_out_{uuid}.db");
+        let app = app(AppConfig::builder().db_path(db_path).build())
+            .await
+            .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(post_todo_request(default_todo()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let toggles = (0..2).map(|_| {
+            let app = app.clone();
+            tokio::spawn(async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/toggle/1")
+                            .method(axum::http::Method::POST)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+
+                let body = read_body(response).await;
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()["completed"]
+                    .as_bool()
+                    .unwrap()
+            })
+        });
+
+        let mut states = Vec::new();
+        for toggle in toggles.collect::<Vec<_>>() {
+            states.push(toggle.await.unwrap());
+        }
+
+        // One toggle saw the todo incomplete and the other saw it completed, whichever came first
+        states.sort();
+        assert_eq!(states, vec![false, true]);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/todos/1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = read_body(response).await;
+        let todo = serde_json::from_slice::<Todo>(&body).unwrap();
+
+        assert!(!todo.completed);
+        assert_eq!(todo.completed_at, None);
+    }
+}
+
+mod connection_settings {
+    use super::*;
+
+    #[tokio::test]
+    async fn database_files_use_wal() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/database_files_use_wal_{uuid}.db");
+
+        let _app = app(AppConfig::builder().db_path(db_path.clone()).build())
+            .await
+            .unwrap();
+
+        // The journal mode is stored in the file, so every connection sees it
+        let connection = rusqlite::Connection::open(&db_path).unwrap();
+        let journal_mode: String = connection
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+
+        assert_eq!(journal_mode, "wal");
+    }
+}
+
+mod subtasks {
+    use super::*;
+
+    async fn send_json(
+        app: &mut axum::Router,
+        method: &str,
+        uri: &str,
+        body: &str,
+    ) -> axum::response::Response {
+        common::send(
+            app,
+            Request::builder()
+                .uri(uri)
+                .method(method)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(Body::from(body.to_owned()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    async fn list(app: &mut axum::Router, uri: &str) -> Vec<u32> {
+        let response = send_json(app, "GET", uri, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice::<Vec<Todo>>(&body)
+            .unwrap()
+            .iter()
+            .map(|todo| todo.id)
+            .collect()
+    }
+
+    /// Creates a parent with id 1, and a subtask of it with id 2
+    async fn app_with_subtask() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        let response = send_json(
+            &mut app,
+            "POST",
+            "/todos",
+            r#"{"id": 1, "name": "Clean the house", "completed": false}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = send_json(
+            &mut app,
+            "POST",
+            "/todos",
+            &format!(
+                r#"{{"id": 2, "name": "Vacuum", "completed": false, "{}": 1}}"#,
+                key("parent_id")
+            ),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        assert_eq!(read_created(response).await.parent_id, Some(1));
+
+        app
+    }
+
+    #[tokio::test]
+    async fn subtasks_are_listed_under_their_parent() {
+        let mut app = app_with_subtask().await;
+
+        assert_eq!(list(&mut app, "/todos/1/subtasks").await, vec![2]);
+        assert_eq!(list(&mut app, "/todos/2/subtasks").await, Vec::<u32>::new());
+        assert_eq!(list(&mut app, "/todos?top_level=true").await, vec![1]);
+        assert_eq!(list(&mut app, "/todos").await, vec![1, 2]);
+
+        let response = send_json(&mut app, "GET", "/todos/3/subtasks", "").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_parents() {
+        let mut app = app_in_memory().await;
+
+        let response = send_json(
+            &mut app,
+            "POST",
+            "/todos",
+            &format!(
+                r#"{{"name": "Vacuum", "completed": false, "{}": 42}}"#,
+                key("parent_id")
+            ),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(list(&mut app, "/todos").await, Vec::<u32>::new());
+    }
+
+    #[tokio::test]
+    async fn rejects_making_a_todo_its_own_subtask() {
+        let mut app = app_with_subtask().await;
+
+        let response = send_json(
+            &mut app,
+            "PUT",
+            "/todos",
+            &format!(
+                r#"{{"id": 1, "name": "Clean the house", "completed": false, "{}": 2}}"#,
+                key("parent_id")
+            ),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(list(&mut app, "/todos?top_level=true").await, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_parent_needs_cascade() {
+        let mut app = app_with_subtask().await;
+
+        let response = send_json(&mut app, "DELETE", "/todos/1", "").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(list(&mut app, "/todos").await, vec![1, 2]);
+
+        let response = send_json(&mut app, "DELETE", "/todos/1?cascade=true", "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(list(&mut app, "/todos").await, Vec::<u32>::new());
+    }
+}
+
+mod completed_at {
+    use super::*;
+
+    async fn toggle(app: &mut axum::Router) {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/toggle/1")
+                .method(axum::http::Method::POST)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    async fn get_todos(app: &mut axum::Router, uri: &str) -> Vec<Todo> {
+        let response = send(
+            app,
+            Request::builder().uri(uri).body(Body::empty()).unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_set_when_completed_and_cleared_when_not() {
+        let mut app = app_in_memory().await;
+        let before = chrono::Utc::now();
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        assert_eq!(get_todos(&mut app, "/todos").await[0].completed_at, None);
+
+        toggle(&mut app).await;
+
+        let completed_at = get_todos(&mut app, "/todos").await[0]
+            .completed_at
+            .expect("toggling to done sets completed_at");
+        assert!(completed_at >= before - chrono::Duration::seconds(1));
+        assert!(completed_at <= chrono::Utc::now());
+
+        toggle(&mut app).await;
+
+        assert_eq!(get_todos(&mut app, "/todos").await[0].completed_at, None);
+    }
+
+    #[tokio::test]
+    async fn filters_by_completion_time() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        toggle(&mut app).await;
+
+        let completed = get_todos(&mut app, "/todos?completed_after=2000-01-01T00:00:00Z").await;
+        assert_eq!(completed.len(), 1);
+
+        let completed = get_todos(&mut app, "/todos?completed_after=2999-01-01T00:00:00Z").await;
+        assert!(completed.is_empty());
+    }
+}
+
+mod put_by_id {
+    use super::*;
+
+    async fn put(app: &mut axum::Router, uri: &str, body: &str) -> StatusCode {
+        send(
+            app,
+            Request::builder()
+                .uri(uri)
+                .method(axum::http::Method::PUT)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(Body::from(body.to_owned()))
+                .unwrap(),
+        )
+        .await
+        .status()
+    }
+
+    async fn get_todo(app: &mut axum::Router) -> Todo {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/todos/1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn app_with_todo() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        app
+    }
+
+    #[tokio::test]
+    async fn updates_the_todo_in_the_path() {
+        let mut app = app_with_todo().await;
+
+        let status = put(
+            &mut app,
+            "/todos/1",
+            r#"{"name": "Walk the cat", "completed": true}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let todo = get_todo(&mut app).await;
+        assert_eq!(todo.name, "Walk the cat");
+        assert!(todo.completed);
+        assert_eq!(todo.version, 1);
+    }
+
+    #[tokio::test]
+    async fn accepts_a_matching_body_id() {
+        let mut app = app_with_todo().await;
+
+        let status = put(
+            &mut app,
+            "/todos/1",
+            r#"{"id": 1, "name": "Walk the cat", "completed": false}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(get_todo(&mut app).await.name, "Walk the cat");
+    }
+
+    #[tokio::test]
+    async fn rejects_a_conflicting_body_id() {
+        let mut app = app_with_todo().await;
+
+        let status = put(
+            &mut app,
+            "/todos/1",
+            r#"{"id": 2, "name": "Walk the cat", "completed": false}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(get_todo(&mut app).await, default_todo());
+    }
+
+    #[tokio::test]
+    async fn creates_a_missing_todo() {
+        let mut app = app_in_memory().await;
+
+        let status = put(
+            &mut app,
+            "/todos/1",
+            r#"{"name": "Walk the cat", "completed": true}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let todo = get_todo(&mut app).await;
+        assert_eq!(todo.name, "Walk the cat");
+        assert!(todo.completed);
+        assert!(todo.completed_at.is_some());
+        assert_eq!(todo.version, 0);
+    }
+
+    #[tokio::test]
+    async fn repeating_a_put_replaces_what_it_created() {
+        let mut app = app_in_memory().await;
+        let body = r#"{"name": "Walk the cat", "completed": false, "tags": ["pets"]}"#;
+
+        assert_eq!(put(&mut app, "/todos/1", body).await, StatusCode::CREATED);
+        let created = get_todo(&mut app).await;
+
+        let body =
+            r#"{"name": "Walk the cat", "completed": false, "tags": ["pets"], "version": 0}"#;
+        assert_eq!(
+            put(&mut app, "/todos/1", body).await,
+            StatusCode::NO_CONTENT
+        );
+        let replaced = get_todo(&mut app).await;
+
+        assert_eq!(replaced.version, created.version + 1);
+        assert_eq!(
+            Todo {
+                version: created.version,
+                ..replaced
+            },
+            created
+        );
+    }
+
+    #[tokio::test]
+    async fn a_deleted_todo_is_not_recreated() {
+        let mut app = app_with_todo().await;
+
+        send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .method(axum::http::Method::DELETE)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        let status = put(
+            &mut app,
+            "/todos/1",
+            r#"{"name": "Walk the cat", "completed": false}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn a_stale_version_is_a_conflict() {
+        let mut app = app_with_todo().await;
+
+        let status = put(
+            &mut app,
+            "/todos/1",
+            r#"{"name": "Walk the cat", "completed": false, "version": 3}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(get_todo(&mut app).await, default_todo());
+    }
+}
+
+mod html {
+    use super::*;
+
+    const BROWSER_ACCEPT: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+
+    async fn app_with_todos(names: &[&str]) -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        for (index, name) in names.iter().enumerate() {
+            let todo = Todo {
+                id: index as u32 + 1,
+                name: (*name).into(),
+                completed: index == 0,
+                ..Default::default()
+            };
+            let response = send(&mut app, post_todo_request(todo)).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        app
+    }
+
+    async fn get_todos(app: &mut axum::Router, accept: &str) -> (String, String) {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/todos")
+                .header(axum::http::header::ACCEPT, accept)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let content_type = response.headers()[axum::http::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = read_body(response).await;
+        (content_type, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn renders_a_page_for_browsers() {
+        let mut app = app_with_todos(&["Walk the dog", "Buy milk"]).await;
+
+        let (content_type, body) = get_todos(&mut app, BROWSER_ACCEPT).await;
+
+        assert!(content_type.starts_with("text/html"));
+        assert!(body.contains("<ul class=\"todos\">"));
+        assert!(body.contains(
+            "<li id=\"todo-1\"><label><input type=\"checkbox\" disabled checked> Walk the dog"
+        ));
+        assert!(
+            body.contains("<li id=\"todo-2\"><label><input type=\"checkbox\" disabled> Buy milk")
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_json_when_asked_for_json() {
+        let mut app = app_with_todos(&["Walk the dog"]).await;
+
+        let (content_type, body) = get_todos(&mut app, "application/json").await;
+
+        assert_eq!(content_type, "application/json");
+        let todos: Vec<Todo> = serde_json::from_str(&body).unwrap();
+        assert_eq!(todos[0].name, "Walk the dog");
+    }
+
+    #[tokio::test]
+    async fn returns_json_when_accepting_anything() {
+        let mut app = app_with_todos(&["Walk the dog"]).await;
+
+        let (content_type, _) = get_todos(&mut app, "*/*").await;
+
+        assert_eq!(content_type, "application/json");
+    }
+
+    #[tokio::test]
+    async fn escapes_todo_names() {
+        let mut app = app_with_todos(&["<script>alert('hi')</script> & more"]).await;
+
+        let (_, body) = get_todos(&mut app, "text/html").await;
+
+        assert!(!body.contains("<script>"));
+        assert!(body.contains("&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt; &amp; more"));
+    }
+}
+
+mod recurrence {
+    use super::*;
+    use chrono::TimeZone;
+    use todo_server_workshop::Recurrence;
+
+    async fn toggle(app: &mut axum::Router, id: u32) {
+        let response = send(
+            app,
+            Request::builder()
+                .uri(format!("/toggle/{id}"))
+                .method(axum::http::Method::POST)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    async fn get_todos(app: &mut axum::Router) -> Vec<Todo> {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/todos")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn app_with_daily_todo() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        let todo = Todo {
+            due_date: Some(chrono::Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap()),
+            tags: vec!["home".into()],
+            recurrence: Some(Recurrence::Daily),
+            ..default_todo()
+        };
+        let response = send(&mut app, post_todo_request(todo)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        app
+    }
+
+    #[tokio::test]
+    async fn completing_creates_the_next_occurrence() {
+        let mut app = app_with_daily_todo().await;
+
+        toggle(&mut app, 1).await;
+
+        let todos = get_todos(&mut app).await;
+        assert_eq!(todos.len(), 2);
+
+        let completed = &todos[0];
+        assert!(completed.completed);
+        assert_eq!(completed.recurrence, None);
+
+        let next = &todos[1];
+        assert_eq!(next.name, completed.name);
+        assert!(!next.completed);
+        assert_eq!(
+            next.due_date,
+            Some(chrono::Utc.with_ymd_and_hms(2024, 3, 2, 9, 0, 0).unwrap())
+        );
+        assert_eq!(next.tags, vec!["home".to_owned()]);
+        assert_eq!(next.recurrence, Some(Recurrence::Daily));
+    }
+
+    #[tokio::test]
+    async fn completing_again_creates_no_more_copies() {
+        let mut app = app_with_daily_todo().await;
+
+        toggle(&mut app, 1).await;
+        toggle(&mut app, 1).await;
+        toggle(&mut app, 1).await;
+
+        assert_eq!(get_todos(&mut app).await.len(), 2);
+    }
+
+    #[test]
+    fn monthly_keeps_to_the_end_of_shorter_months() {
+        let due_date = chrono::Utc.with_ymd_and_hms(2024, 1, 31, 9, 0, 0).unwrap();
+
+        assert_eq!(
+            Recurrence::Monthly.next_due_date(due_date),
+            chrono::Utc.with_ymd_and_hms(2024, 2, 29, 9, 0, 0).unwrap()
+        );
+    }
+}
+
+mod reorder {
+    use super::*;
+
+    async fn app_with_todos(count: u32) -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=count {
+            let todo = Todo {
+                id,
+                name: format!("Todo {id}"),
+                ..Default::default()
+            };
+            let response = send(&mut app, post_todo_request(todo)).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        app
+    }
+
+    async fn reorder(app: &mut axum::Router, body: &str) -> StatusCode {
+        send(
+            app,
+            Request::builder()
+                .uri("/todos/reorder")
+                .method(axum::http::Method::PUT)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(Body::from(body.to_owned()))
+                .unwrap(),
+        )
+        .await
+        .status()
+    }
+
+    async fn listed_ids(app: &mut axum::Router) -> Vec<u32> {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/todos")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        let body = read_body(response).await;
+        let todos: Vec<Todo> = serde_json::from_slice(&body).unwrap();
+        todos.into_iter().map(|todo| todo.id).collect()
+    }
+
+    #[tokio::test]
+    async fn lists_todos_in_the_new_order() {
+        let mut app = app_with_todos(3).await;
+
+        assert_eq!(reorder(&mut app, "[3, 1, 2]").await, StatusCode::NO_CONTENT);
+
+        assert_eq!(listed_ids(&mut app).await, vec![3, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn keeps_todos_that_are_left_out_in_place() {
+        let mut app = app_with_todos(4).await;
+
+        assert_eq!(reorder(&mut app, "[3, 1]").await, StatusCode::NO_CONTENT);
+
+        assert_eq!(listed_ids(&mut app).await, vec![3, 2, 1, 4]);
+    }
+
+    #[tokio::test]
+    async fn lists_new_todos_last() {
+        let mut app = app_with_todos(2).await;
+        assert_eq!(reorder(&mut app, "[2, 1]").await, StatusCode::NO_CONTENT);
+
+        let response = send(
+            &mut app,
+            post_todo_request(Todo {
+                id: 3,
+                name: "Todo 3".into(),
+                ..Default::default()
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        assert_eq!(listed_ids(&mut app).await, vec![2, 1, 3]);
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_and_repeated_ids() {
+        let mut app = app_with_todos(2).await;
+
+        assert_eq!(reorder(&mut app, "[2, 7]").await, StatusCode::NOT_FOUND);
+        assert_eq!(
+            reorder(&mut app, "[2, 1, 2]").await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+
+        assert_eq!(listed_ids(&mut app).await, vec![1, 2]);
+    }
+
+    async fn move_todo(app: &mut axum::Router, uri: &str) -> StatusCode {
+        send(app, post_request(uri)).await.status()
+    }
+
+    #[tokio::test]
+    async fn moves_a_todo_to_the_top_or_bottom() {
+        let mut app = app_with_todos(3).await;
+
+        assert_eq!(
+            move_todo(&mut app, "/todos/2/move?to=top").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(listed_ids(&mut app).await, vec![2, 1, 3]);
+
+        assert_eq!(
+            move_todo(&mut app, "/todos/1/move?to=bottom").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(listed_ids(&mut app).await, vec![2, 3, 1]);
+
+        // Moving a todo where it already is changes nothing
+        assert_eq!(
+            move_todo(&mut app, "/todos/2/move?to=top").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(listed_ids(&mut app).await, vec![2, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn moving_the_only_todo_keeps_it() {
+        let mut app = app_with_todos(1).await;
+
+        assert_eq!(
+            move_todo(&mut app, "/todos/1/move?to=bottom").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(listed_ids(&mut app).await, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_todos_and_places() {
+        let mut app = app_with_todos(0).await;
+
+        assert_eq!(
+            move_todo(&mut app, "/todos/1/move?to=top").await,
+            StatusCode::NOT_FOUND
+        );
+
+        let mut app = app_with_todos(2).await;
+
+        assert_eq!(
+            move_todo(&mut app, "/todos/1/move?to=middle").await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            move_todo(&mut app, "/todos/1/move").await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(listed_ids(&mut app).await, vec![1, 2]);
+    }
+}
+
+mod dry_run {
+    use super::*;
+
+    async fn app_with_todos() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        for (id, completed) in [(1, true), (2, false), (3, true)] {
+            let todo = Todo {
+                id,
+                name: format!("Todo {id}"),
+                completed,
+                ..Default::default()
+            };
+            let response = send(&mut app, post_todo_request(todo)).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        app
+    }
+
+    async fn delete(app: &mut axum::Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let response = send(
+            app,
+            Request::builder()
+                .uri(uri)
+                .method(axum::http::Method::DELETE)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        let status = response.status();
+        let body = read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn todo_count(app: &mut axum::Router) -> usize {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/todos")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        let body = read_body(response).await;
+        serde_json::from_slice::<Vec<Todo>>(&body).unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn clearing_lists_the_completed_todos_without_deleting_them() {
+        let mut app = app_with_todos().await;
+
+        let (status, body) = delete(&mut app, "/todos?completed=true&dry_run=true").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[key("would_delete")], 2);
+        assert_eq!(body["todos"][0]["id"], 1);
+        assert_eq!(body["todos"][1]["id"], 3);
+        assert_eq!(todo_count(&mut app).await, 3);
+    }
+
+    #[tokio::test]
+    async fn deleting_lists_the_todo_without_deleting_it() {
+        let mut app = app_with_todos().await;
+
+        let (status, body) = delete(&mut app, "/todos/2?dry_run=true").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[key("would_delete")], 1);
+        assert_eq!(body["todos"][0]["id"], 2);
+        assert_eq!(todo_count(&mut app).await, 3);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_missing_todo_still_fails() {
+        let mut app = app_with_todos().await;
+
+        let (status, _) = delete(&mut app, "/todos/7?dry_run=true").await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
+
+mod search_endpoint {
+    use super::*;
+
+    async fn app_with_todos() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        let todos = [
+            ("Buy milk", true),
+            ("Buy bread", false),
+            ("Walk the dog", false),
+            ("Buy eggs", false),
+            ("Buy cheese", false),
+        ];
+        for (index, (name, completed)) in todos.into_iter().enumerate() {
+            let todo = Todo {
+                id: index as u32 + 1,
+                name: name.into(),
+                completed,
+                due_date: Some(
+                    chrono::DateTime::parse_from_rfc3339(&format!(
+                        "2024-03-0{}T12:00:00Z",
+                        index + 1
+                    ))
+                    .unwrap()
+                    .into(),
+                ),
+                ..Default::default()
+            };
+            let response = send(&mut app, post_todo_request(todo)).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        app
+    }
+
+    async fn search(app: &mut axum::Router, query: &str) -> (StatusCode, Option<u64>, Vec<u32>) {
+        let response = send(
+            app,
+            Request::builder()
+                .uri(format!("/todos/search?{query}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        let status = response.status();
+        let total = response
+            .headers()
+            .get("x-total-count")
+            .map(|total| total.to_str().unwrap().parse().unwrap());
+        let body = read_body(response).await;
+        let ids = serde_json::from_slice::<Vec<Todo>>(&body)
+            .map(|todos| todos.into_iter().map(|todo| todo.id).collect())
+            .unwrap_or_default();
+        (status, total, ids)
+    }
+
+    #[tokio::test]
+    async fn combines_a_text_query_with_a_completed_filter() {
+        let mut app = app_with_todos().await;
+
+        let (status, total, ids) = search(&mut app, "q=buy&completed=false").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(total, Some(3));
+        assert_eq!(ids, vec![2, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn pages_through_the_results_with_the_total_of_all_pages() {
+        let mut app = app_with_todos().await;
+
+        let (_, total, ids) =
+            search(&mut app, "q=buy&completed=false&sort=name&limit=2&offset=0").await;
+        assert_eq!(total, Some(3));
+        assert_eq!(ids, vec![2, 5]);
+
+        let (_, total, ids) =
+            search(&mut app, "q=buy&completed=false&sort=name&limit=2&offset=2").await;
+        assert_eq!(total, Some(3));
+        assert_eq!(ids, vec![4]);
+    }
+
+    #[tokio::test]
+    async fn filters_by_due_date() {
+        let mut app = app_with_todos().await;
+
+        let (_, total, ids) = search(
+            &mut app,
+            "due_after=2024-03-02T00:00:00Z&due_before=2024-03-04T00:00:00Z",
+        )
+        .await;
+
+        assert_eq!(total, Some(2));
+        assert_eq!(ids, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_limit_that_is_too_large() {
+        let mut app = app_with_todos().await;
+
+        let (status, _, _) = search(&mut app, "limit=1000").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
+
+mod compression {
+    use super::*;
+
+    async fn app_with_todos(count: u32) -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=count {
+            let todo = Todo {
+                id,
+                name: format!("A todo with a long enough name to add up, number {id}"),
+                ..Default::default()
+            };
+            let response = send(&mut app, post_todo_request(todo)).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        app
+    }
+
+    async fn content_encoding(app: &mut axum::Router, accept_encoding: &str) -> Option<String> {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/todos")
+                .header(axum::http::header::ACCEPT_ENCODING, accept_encoding)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        response
+            .headers()
+            .get(axum::http::header::CONTENT_ENCODING)
+            .map(|encoding| encoding.to_str().unwrap().to_owned())
+    }
+
+    #[tokio::test]
+    async fn compresses_large_lists_with_gzip() {
+        let mut app = app_with_todos(50).await;
+
+        assert_eq!(
+            content_encoding(&mut app, "gzip").await.as_deref(),
+            Some("gzip")
+        );
+    }
+
+    #[tokio::test]
+    async fn compresses_large_lists_with_brotli() {
+        let mut app = app_with_todos(50).await;
+
+        assert_eq!(
+            content_encoding(&mut app, "br").await.as_deref(),
+            Some("br")
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_small_bodies_uncompressed() {
+        let mut app = app_with_todos(1).await;
+
+        assert_eq!(content_encoding(&mut app, "gzip").await, None);
+    }
+
+    #[tokio::test]
+    async fn leaves_bodies_uncompressed_unless_asked() {
+        let mut app = app_with_todos(50).await;
+
+        assert_eq!(content_encoding(&mut app, "identity").await, None);
+    }
+}
+
+mod complete_incomplete {
+    use super::*;
+
+    async fn app_with_todo() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        app
+    }
+
+    async fn post(app: &mut axum::Router, uri: &str) -> (StatusCode, Option<Todo>) {
+        let response = send(
+            app,
+            Request::builder()
+                .uri(uri)
+                .method(axum::http::Method::POST)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        let status = response.status();
+        let body = read_body(response).await;
+        (status, serde_json::from_slice(&body).ok())
+    }
+
+    #[tokio::test]
+    async fn repeated_completes_keep_the_todo_completed() {
+        let mut app = app_with_todo().await;
+
+        let (status, first) = post(&mut app, "/todos/1/complete").await;
+        assert_eq!(status, StatusCode::OK);
+        let first = first.unwrap();
+        assert!(first.completed);
+
+        let (status, second) = post(&mut app, "/todos/1/complete").await;
+        assert_eq!(status, StatusCode::OK);
+        let second = second.unwrap();
+        assert!(second.completed);
+        // Nothing changed the second time, so the todo was left as it was
+        assert_eq!(second, first);
+    }
+
+    #[tokio::test]
+    async fn repeated_incompletes_keep_the_todo_incomplete() {
+        let mut app = app_with_todo().await;
+
+        post(&mut app, "/todos/1/complete").await;
+
+        for _ in 0..2 {
+            let (status, todo) = post(&mut app, "/todos/1/incomplete").await;
+            assert_eq!(status, StatusCode::OK);
+            let todo = todo.unwrap();
+            assert!(!todo.completed);
+            assert_eq!(todo.completed_at, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_404_for_missing_todos() {
+        let mut app = app_in_memory().await;
+
+        assert_eq!(
+            post(&mut app, "/todos/1/complete").await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            post(&mut app, "/todos/1/incomplete").await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(put_completed(&mut app, true).await.0, StatusCode::NOT_FOUND);
+    }
+
+    async fn put_completed(app: &mut axum::Router, completed: bool) -> (StatusCode, Option<Todo>) {
+        let (status, body) = fetch(
+            app,
+            json_request(
+                axum::http::Method::PUT,
+                "/todos/1/completed",
+                &serde_json::json!({ "completed": completed }),
+            ),
+        )
+        .await;
+        (status, serde_json::from_slice(&body).ok())
+    }
+
+    #[tokio::test]
+    async fn repeated_puts_give_the_same_state() {
+        let mut app = app_with_todo().await;
+
+        let (status, first) = put_completed(&mut app, true).await;
+        assert_eq!(status, StatusCode::OK);
+        let first = first.unwrap();
+        assert!(first.completed);
+
+        let (status, second) = put_completed(&mut app, true).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(second.unwrap(), first);
+
+        for _ in 0..2 {
+            let (status, todo) = put_completed(&mut app, false).await;
+            assert_eq!(status, StatusCode::OK);
+            let todo = todo.unwrap();
+            assert!(!todo.completed);
+            assert_eq!(todo.completed_at, None);
+        }
+    }
+}
+
+mod invalid_bodies {
+    use super::*;
+
+    async fn send(
+        method: axum::http::Method,
+        uri: &str,
+        content_type: &str,
+        body: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = app_in_memory()
+            .await
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .method(method)
+                    .header(axum::http::header::CONTENT_TYPE, content_type)
+                    .body(Body::from(body.to_owned()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    async fn post_todo(body: &str) -> (StatusCode, serde_json::Value) {
+        send(axum::http::Method::POST, "/todos", "application/json", body).await
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_described() {
+        let (status, body) = post_todo(r#"{"name": "Walk the dog", "completed": fal"#).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "invalid_json");
+        assert!(body["error"].as_str().unwrap().starts_with("invalid JSON"));
+    }
+
+    #[tokio::test]
+    async fn a_missing_field_is_named() {
+        let (status, body) = post_todo(r#"{"completed": false}"#).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "invalid_body");
+        assert_eq!(body["field"], "name");
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("missing field `name`"));
+    }
+
+    #[tokio::test]
+    async fn a_field_of_the_wrong_type_is_named() {
+        let (status, body) = post_todo(r#"{"name": "Walk the dog", "completed": "yes"}"#).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field"], "completed");
+    }
+
+    #[tokio::test]
+    async fn fields_in_lists_are_named_with_their_index() {
+        let (status, body) =
+            post_todo(r#"[{"name": "Walk the dog"}, {"name": "Buy milk", "completed": "yes"}]"#)
+                .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field"], "[1].completed");
+    }
+
+    #[tokio::test]
+    async fn updates_are_validated_too() {
+        let (status, body) = send(
+            axum::http::Method::PUT,
+            "/todos",
+            "application/json",
+            r#"{"id": 1, "name": "Walk the dog", "completed": false, "priority": "urgent"}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field"], "priority");
+    }
+
+    #[tokio::test]
+    async fn other_content_types_are_refused() {
+        let (status, body) = send(
+            axum::http::Method::POST,
+            "/todos",
+            "text/plain",
+            r#"{"name": "Walk the dog", "completed": false}"#,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["code"], "unsupported_media_type");
+    }
+}
+
+mod app_config {
+    use super::*;
+    use todo_server_workshop::{DEFAULT_DB_PATH, DEFAULT_MAX_BODY_BYTES, DEFAULT_POOL_SIZE};
+
+    #[test]
+    fn the_builder_starts_from_the_defaults() {
+        let config = AppConfig::builder().build();
+
+        assert_eq!(config, AppConfig::default());
+        assert_eq!(config.db_path(), DEFAULT_DB_PATH);
+        assert_eq!(config.pool_size(), DEFAULT_POOL_SIZE);
+        assert_eq!(config.max_body_bytes(), DEFAULT_MAX_BODY_BYTES);
+        assert_eq!(config.api_key(), None);
+        assert_eq!(config.cors_origins(), None);
+    }
+
+    #[tokio::test]
+    async fn the_app_uses_the_built_config() {
+        let config = AppConfig::builder()
+            .db_path(":memory:")
+            .max_body_bytes(16)
+            .build();
+        let app = app(config).await.unwrap();
+
+        let response = app
+            .oneshot(post_todo_request(default_todo()))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
+
+mod random {
+    use super::*;
+
+    async fn get_random(app: &mut axum::Router) -> (StatusCode, Option<Todo>) {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/todos/random")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        let status = response.status();
+        let body = read_body(response).await;
+        (status, serde_json::from_slice(&body).ok())
+    }
+
+    #[tokio::test]
+    async fn only_picks_incomplete_todos() {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=10 {
+            let todo = Todo {
+                id,
+                name: format!("Todo {id}"),
+                completed: id % 2 == 0,
+                ..Default::default()
+            };
+            let response = send(&mut app, post_todo_request(todo)).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        for _ in 0..20 {
+            let (status, todo) = get_random(&mut app).await;
+
+            assert_eq!(status, StatusCode::OK);
+            let todo = todo.unwrap();
+            assert!(!todo.completed);
+            assert_eq!(todo.id % 2, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_404_when_every_todo_is_completed() {
+        let mut app = app_in_memory().await;
+
+        let todo = Todo {
+            completed: true,
+            ..default_todo()
+        };
+        send(&mut app, post_todo_request(todo)).await;
+
+        let (status, _) = get_random(&mut app).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
+
+mod head {
+    use super::*;
+
+    fn head_request(id: u32) -> Request<Body> {
+        Request::builder()
+            .uri(format!("/todos/{id}"))
+            .method(axum::http::Method::HEAD)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn checks_whether_a_todo_exists_without_a_body() {
+        let mut app = app_in_memory().await;
+
+        let response = send(&mut app, post_todo_request(default_todo())).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = send(&mut app, head_request(1)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = read_body(response).await;
+        assert!(body.is_empty());
+
+        let response = send(&mut app, head_request(2)).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = read_body(response).await;
+        assert!(body.is_empty());
+    }
+}
+
+mod seed {
+    use super::*;
+
+    /// Writes `contents` to a seed file only this test uses, removed again when dropped
+    struct SeedFile(String);
+
+    impl SeedFile {
+        fn new(contents: &str) -> Self {
+            let uuid = uuid::Uuid::new_v4();
+            let path = format!("tests/db/seed_{uuid}.json");
+            std::fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for SeedFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    async fn get_todos(app: axum::Router) -> Vec<Todo> {
+        let response = app.oneshot(get_todos_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn loads_the_seed_file_into_an_empty_table() {
+        let seed = SeedFile::new(
+            r#"[
+                { "id": 1, "name": "Try the demo", "completed": false },
+                { "id": 2, "name": "Star the repo", "completed": true }
+            ]"#,
+        );
+
+        let app = app(AppConfig::builder()
+            .db_path(":memory:")
+            .seed_path(&seed.0)
+            .build())
+        .await
+        .unwrap();
+        let todos = get_todos(app).await;
+
+        assert_eq!(todos.len(), 2);
+        assert_eq!((todos[0].id, todos[0].name.as_str()), (1, "Try the demo"));
+        assert!(!todos[0].completed);
+        assert_eq!((todos[1].id, todos[1].name.as_str()), (2, "Star the repo"));
+        assert!(todos[1].completed);
+    }
+
+    #[tokio::test]
+    async fn leaves_a_table_with_todos_alone() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/leaves_a_table_with_todos_alone_{uuid}.db");
+        let seed = SeedFile::new(r#"[{ "id": 5, "name": "Seeded", "completed": false }]"#);
+
+        {
+            let app = app(AppConfig::builder().db_path(db_path.clone()).build())
+                .await
+                .unwrap();
+            let response = app
+                .oneshot(post_todo_request(default_todo()))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let app = app(AppConfig::builder()
+            .db_path(db_path)
+            .seed_path(&seed.0)
+            .build())
+        .await
+        .unwrap();
+
+        assert_eq!(get_todos(app).await, vec![default_todo()]);
+    }
+
+    #[tokio::test]
+    async fn starts_empty_with_a_malformed_seed_file() {
+        let seed = SeedFile::new(r#"[{ "id": 1, "name": "Missing a bracket""#);
+
+        let app = app(AppConfig::builder()
+            .db_path(":memory:")
+            .seed_path(&seed.0)
+            .build())
+        .await
+        .unwrap();
+
+        assert_eq!(get_todos(app).await, vec![]);
+    }
+}
+
+mod assignee {
+    use super::*;
+
+    async fn create(app: &mut axum::Router, todo: serde_json::Value) -> Todo {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/todos")
+                .method(axum::http::Method::POST)
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(todo.to_string()))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        read_created(response).await
+    }
+
+    async fn get_todos(app: &mut axum::Router, uri: &str) -> Vec<Todo> {
+        let response = send(
+            app,
+            Request::builder().uri(uri).body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn filters_todos_by_assignee() {
+        let mut app = app_in_memory().await;
+
+        let dishes = create(
+            &mut app,
+            serde_json::json!({ "name": "Do the dishes", "completed": false, "assignee": "alice" }),
+        )
+        .await;
+        assert_eq!(dishes.assignee.as_deref(), Some("alice"));
+        create(
+            &mut app,
+            serde_json::json!({ "name": "Take out the trash", "completed": false, "assignee": "bob" }),
+        )
+        .await;
+        create(
+            &mut app,
+            serde_json::json!({ "name": "Water the plants", "completed": false }),
+        )
+        .await;
+
+        let todos = get_todos(&mut app, "/todos?assignee=alice").await;
+
+        assert_eq!(todos, vec![dishes]);
+    }
+
+    #[tokio::test]
+    async fn an_empty_assignee_leaves_the_todo_unassigned() {
+        let mut app = app_in_memory().await;
+
+        let todo = create(
+            &mut app,
+            serde_json::json!({ "name": "Do the dishes", "completed": false, "assignee": "" }),
+        )
+        .await;
+        assert_eq!(todo.assignee, None);
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .method(axum::http::Method::PATCH)
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{ "assignee": "alice" }"#))
+                .unwrap(),
+        )
+        .await;
+        let body = read_body(response).await;
+        let patched: Todo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(patched.assignee.as_deref(), Some("alice"));
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .method(axum::http::Method::PATCH)
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{ "assignee": "" }"#))
+                .unwrap(),
+        )
+        .await;
+        let body = read_body(response).await;
+        let patched: Todo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(patched.assignee, None);
+    }
+}
+
+mod description {
+    use super::*;
+
+    async fn get_todo(app: &mut axum::Router, id: u32) -> Todo {
+        let response = send(
+            app,
+            Request::builder()
+                .uri(format!("/todos/{id}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn keeps_a_multiline_description_intact() {
+        let mut app = app_in_memory().await;
+        let description = "Things to buy:\n  - milk\n  - eggs\r\n\n\"fresh\" if they have it 🥚  ";
+
+        let todo = Todo {
+            description: Some(description.into()),
+            ..default_todo()
+        };
+        let response = send(&mut app, post_todo_request(todo)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let todo = get_todo(&mut app, 1).await;
+
+        assert_eq!(todo.name, default_todo().name);
+        assert_eq!(todo.description.as_deref(), Some(description));
+    }
+
+    #[tokio::test]
+    async fn todos_need_no_description() {
+        let mut app = app_in_memory().await;
+
+        let response = send(
+            &mut app,
+            Request::builder()
+                .uri("/todos")
+                .method(axum::http::Method::POST)
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    r#"{ "name": "Walk the dog", "completed": false }"#,
+                ))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        assert_eq!(get_todo(&mut app, 1).await.description, None);
+    }
+}
+
+mod archive {
+    use super::*;
+
+    fn post(uri: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .method(axum::http::Method::POST)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn list(app: &mut axum::Router, uri: &str) -> Vec<u32> {
+        let (status, body) = fetch(
+            app,
+            Request::builder().uri(uri).body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        serde_json::from_slice::<Vec<Todo>>(&body)
+            .unwrap()
+            .into_iter()
+            .map(|todo| todo.id)
+            .collect()
+    }
+
+    async fn app_with_todos() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=2 {
+            let todo = Todo {
+                id,
+                ..default_todo()
+            };
+            let (status, _) = fetch(&mut app, post_todo_request(todo)).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        app
+    }
+
+    #[tokio::test]
+    async fn archived_todos_are_hidden_by_default() {
+        let mut app = app_with_todos().await;
+
+        let (status, body) = fetch(&mut app, post("/todos/1/archive")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(serde_json::from_slice::<Todo>(&body).unwrap().archived);
+
+        assert_eq!(list(&mut app, "/todos").await, vec![2]);
+        assert_eq!(list(&mut app, "/todos?archived=true").await, vec![1]);
+
+        // Archived todos can still be fetched directly
+        let (status, _) = fetch(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn unarchiving_brings_a_todo_back() {
+        let mut app = app_with_todos().await;
+
+        fetch(&mut app, post("/todos/1/archive")).await;
+        let (status, body) = fetch(&mut app, post("/todos/1/unarchive")).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(!serde_json::from_slice::<Todo>(&body).unwrap().archived);
+        assert_eq!(list(&mut app, "/todos").await, vec![1, 2]);
+        assert_eq!(
+            list(&mut app, "/todos?archived=true").await,
+            Vec::<u32>::new()
+        );
+    }
+
+    #[tokio::test]
+    async fn archiving_a_missing_todo_returns_404() {
+        let mut app = app_in_memory().await;
+
+        let (status, _) = fetch(&mut app, post("/todos/1/archive")).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
+
+mod envelope {
+    use super::*;
+
+    async fn app_with_todos(count: u32) -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=count {
+            let todo = Todo {
+                id,
+                completed: id % 2 == 0,
+                ..default_todo()
+            };
+            let response = send(&mut app, post_todo_request(todo)).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        app
+    }
+
+    async fn get_json(app: &mut axum::Router, uri: &str) -> serde_json::Value {
+        let response = send(
+            app,
+            Request::builder().uri(uri).body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn wraps_the_page_when_asked_to() {
+        let mut app = app_with_todos(7).await;
+
+        let body = get_json(
+            &mut app,
+            "/todos?completed=false&limit=2&offset=1&envelope=true",
+        )
+        .await;
+
+        assert_eq!(body["total"], 4);
+        assert_eq!(body["limit"], 2);
+        assert_eq!(body["offset"], 1);
+        let ids: Vec<u32> = serde_json::from_value::<Vec<Todo>>(body["data"].clone())
+            .unwrap()
+            .iter()
+            .map(|todo| todo.id)
+            .collect();
+        assert_eq!(ids, vec![3, 5]);
+    }
+
+    #[tokio::test]
+    async fn returns_a_bare_array_by_default() {
+        let mut app = app_with_todos(3).await;
+
+        let body = get_json(&mut app, "/todos?limit=2").await;
+
+        let todos: Vec<Todo> = serde_json::from_value(body).unwrap();
+        assert_eq!(todos.len(), 2);
+    }
+}
+
+mod max_todos {
+    use super::*;
+
+    async fn app_with_cap(max_todos: u64) -> axum::Router {
+        app(AppConfig::builder()
+            .db_path(":memory:")
+            .max_todos(max_todos)
+            .build())
+        .await
+        .unwrap()
+    }
+
+    async fn create(app: &mut axum::Router, id: u32) -> StatusCode {
+        let todo = Todo {
+            id,
+            ..default_todo()
+        };
+        send(app, post_todo_request(todo)).await.status()
+    }
+
+    #[tokio::test]
+    async fn rejects_todos_over_the_cap() {
+        let mut app = app_with_cap(2).await;
+
+        assert_eq!(create(&mut app, 1).await, StatusCode::CREATED);
+        assert_eq!(create(&mut app, 2).await, StatusCode::CREATED);
+        assert_eq!(create(&mut app, 3).await, StatusCode::INSUFFICIENT_STORAGE);
+
+        let response = send(&mut app, get_todos_request()).await;
+        let body = read_body(response).await;
+        assert_eq!(serde_json::from_slice::<Vec<Todo>>(&body).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_are_answered_once_the_cap_is_reached() {
+        let mut app = app_with_cap(1).await;
+
+        let mut created = Vec::new();
+        for _ in 0..2 {
+            let mut request = post_todo_request(default_todo());
+            request
+                .headers_mut()
+                .insert("Idempotency-Key", "retry-me".parse().unwrap());
+            let response = send(&mut app, request).await;
+
+            assert_eq!(response.status(), StatusCode::CREATED);
+            created.push(read_created(response).await);
+        }
+
+        assert_eq!(created[0], created[1]);
+    }
+
+    #[tokio::test]
+    async fn rejects_lists_that_would_go_over_the_cap() {
+        let mut app = app_with_cap(2).await;
+        assert_eq!(create(&mut app, 1).await, StatusCode::CREATED);
+
+        let response = send(&mut app, Request::builder()
+                    .uri("/todos")
+                    .method(axum::http::Method::POST)
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"[{"name": "Buy milk", "completed": false}, {"name": "Buy bread", "completed": false}]"#,
+                    ))
+                    .unwrap(),).await;
+
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+        let body = read_body(response).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "too_many_todos");
+    }
+
+    #[tokio::test]
+    async fn deleting_a_todo_makes_room() {
+        let mut app = app_with_cap(1).await;
+        assert_eq!(create(&mut app, 1).await, StatusCode::CREATED);
+
+        send(
+            &mut app,
+            Request::builder()
+                .uri("/todos/1")
+                .method(axum::http::Method::DELETE)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(create(&mut app, 2).await, StatusCode::CREATED);
+    }
+}
+
+mod users {
+    use super::*;
+
+    /// Makes a request as `user`, or without the header if there is no user
+    fn request(method: &str, uri: &str, user: Option<&str>, body: Option<&Todo>) -> Request<Body> {
+        let mut builder = Request::builder().uri(uri).method(method);
+        if let Some(user) = user {
+            builder = builder.header("x-user-id", user);
+        }
+
+        match body {
+            Some(todo) => builder
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(new_todo_body(todo).to_string()))
+                .unwrap(),
+            None => builder.body(Body::empty()).unwrap(),
+        }
+    }
+
+    async fn list(app: &mut axum::Router, user: Option<&str>) -> Vec<u32> {
+        let (status, body) = fetch(app, request("GET", "/todos", user, None)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        serde_json::from_slice::<Vec<Todo>>(&body)
+            .unwrap()
+            .into_iter()
+            .map(|todo| todo.id)
+            .collect()
+    }
+
+    async fn app_with_todo_of_alice() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        let (status, _) = fetch(
+            &mut app,
+            request("POST", "/todos", Some("alice"), Some(&default_todo())),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        app
+    }
+
+    #[tokio::test]
+    async fn users_only_list_their_own_todos() {
+        let mut app = app_with_todo_of_alice().await;
+
+        assert_eq!(list(&mut app, Some("alice")).await, vec![1]);
+        assert_eq!(list(&mut app, Some("bob")).await, Vec::<u32>::new());
+        assert_eq!(list(&mut app, None).await, Vec::<u32>::new());
+    }
+
+    #[tokio::test]
+    async fn another_users_todo_is_not_found() {
+        let mut app = app_with_todo_of_alice().await;
+
+        let (status, _) = fetch(&mut app, request("GET", "/todos/1", Some("bob"), None)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = fetch(&mut app, request("GET", "/todos/1", Some("alice"), None)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn users_cannot_change_or_delete_another_users_todo() {
+        let mut app = app_with_todo_of_alice().await;
+        let updated = Todo {
+            name: "Taken over".into(),
+            ..default_todo()
+        };
+
+        let (status, _) = fetch(
+            &mut app,
+            request("PUT", "/todos", Some("bob"), Some(&updated)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = fetch(
+            &mut app,
+            request("PUT", "/todos/1", Some("bob"), Some(&updated)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = fetch(&mut app, request("POST", "/toggle/1", Some("bob"), None)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = fetch(&mut app, request("DELETE", "/todos/1", Some("bob"), None)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = fetch(&mut app, request("GET", "/todos/1", Some("alice"), None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_slice::<Todo>(&body).unwrap(),
+            default_todo()
+        );
+    }
+
+    #[tokio::test]
+    async fn subtasks_need_a_parent_of_the_same_user() {
+        let mut app = app_with_todo_of_alice().await;
+        let subtask = Todo {
+            id: 2,
+            parent_id: Some(1),
+            ..default_todo()
+        };
+
+        let (status, _) = fetch(
+            &mut app,
+            request("POST", "/todos", Some("bob"), Some(&subtask)),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+mod toggle_many {
+    use super::*;
+
+    fn toggle_request(body: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/todos/toggle")
+            .method(axum::http::Method::POST)
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_owned()))
+            .unwrap()
+    }
+
+    async fn app_with_todos() -> axum::Router {
+        let mut app = app_in_memory().await;
+
+        for id in 1..=3 {
+            let todo = Todo {
+                id,
+                completed: id == 2,
+                ..default_todo()
+            };
+            let (status, _) = fetch(&mut app, post_todo_request(todo)).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        app
+    }
+
+    #[tokio::test]
+    async fn toggles_existing_todos_and_reports_missing_ones() {
+        let mut app = app_with_todos().await;
+
+        let (status, body) = fetch(&mut app, toggle_request(r#"{"ids": [2, 7, 1, 9]}"#)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+        let toggled: Vec<(u64, bool)> = body["todos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|todo| {
+                (
+                    todo["id"].as_u64().unwrap(),
+                    todo["completed"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(toggled, vec![(2, false), (1, true)]);
+        assert_eq!(body["missing"], serde_json::json!([7, 9]));
+
+        let (_, body) = fetch(&mut app, get_todos_request()).await;
+        let completed: Vec<bool> = serde_json::from_slice::<Vec<Todo>>(&body)
+            .unwrap()
+            .into_iter()
+            .map(|todo| todo.completed)
+            .collect();
+        assert_eq!(completed, vec![true, false, false]);
+    }
+
+    #[tokio::test]
+    async fn rejects_an_id_listed_twice() {
+        let mut app = app_with_todos().await;
+
+        let (status, _) = fetch(&mut app, toggle_request(r#"{"ids": [1, 1]}"#)).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+mod initial_completion {
+    use super::*;
+
+    async fn create(body: &str) -> Todo {
+        let mut app = TestApp::new().await;
+
+        let response = app
+            .send(
+                Request::builder()
+                    .uri("/todos")
+                    .method(axum::http::Method::POST)
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_owned()))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // Read the todo back, so this checks what was stored rather than what was echoed
+        app.get_todos().await.remove(0)
+    }
+
+    #[tokio::test]
+    async fn stores_a_todo_created_as_completed() {
+        let todo = create(r#"{"name": "Already done", "completed": true}"#).await;
+
+        assert!(todo.completed);
+        assert!(todo.completed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn todos_are_not_completed_by_default() {
+        let todo = create(r#"{"name": "Still to do"}"#).await;
+
+        assert!(!todo.completed);
+        assert_eq!(todo.completed_at, None);
+    }
+}
+
+mod stats {
+    use super::*;
+
+    async fn get_stats(app: &mut axum::Router) -> serde_json::Value {
+        let response = send(
+            app,
+            Request::builder()
+                .uri("/stats")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = read_body(response).await;
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn no_todos_give_no_days() {
+        let mut app = app_in_memory().await;
+
+        assert_eq!(get_stats(&mut app).await, serde_json::json!({}));
+    }
+
+    #[tokio::test]
+    async fn counts_created_and_completed_todos_per_day() {
+        let uuid = uuid::Uuid::new_v4();
+        let db_path = format!("tests/db/counts_created_and_completed_todos_per_day_{uuid}.db");
+        let mut app = app(AppConfig::builder().db_path(db_path.clone()).build())
+            .await
+            .unwrap();
+
+        for id in 1..=3 {
+            let response = send(
+                &mut app,
+                post_todo_request(Todo {
+                    id,
+                    ..default_todo()
+                }),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        // Move the todos back in time, the API has no way to set when they were created
+        let connection = rusqlite::Connection::open(&db_path).unwrap();
+        connection
+            .execute_batch(
+                "UPDATE todos SET created_at = '2024-01-01 09:00:00' WHERE id IN (1, 2);
+                 UPDATE todos SET created_at = '2024-01-02 10:00:00' WHERE id = 3;
+                 UPDATE todos SET completed = 1, completed_at = '2024-01-02 23:30:00+00:00'
+                   WHERE id = 1;
+                 UPDATE todos SET completed = 1, completed_at = '2024-01-03 08:00:00+00:00'
+                   WHERE id = 3;",
+            )
+            .unwrap();
+
+        assert_eq!(
+            get_stats(&mut app).await,
+            serde_json::json!({
+                "2024-01-01": { "created": 2, "completed": 0 },
+                "2024-01-02": { "created": 1, "completed": 1 },
+                "2024-01-03": { "created": 0, "completed": 1 },
+            })
+        );
+    }
+}
+
+mod delete_many {
+    use super::*;
+
+    fn delete_request(query: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .uri(format!("/todos{query}"))
+            .method(axum::http::Method::DELETE)
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_owned()))
+            .unwrap()
+    }
+
+    async fn app_with_todos() -> TestApp {
+        let mut app = TestApp::new().await;
+        for id in 1..=4 {
+            app.create_todo(Todo {
+                id,
+                ..default_todo()
+            })
+            .await;
+        }
+        app
+    }
+
+    #[tokio::test]
+    async fn deletes_the_listed_todos_and_counts_only_existing_ones() {
+        let mut app = app_with_todos().await;
+
+        let (status, body) = fetch(
+            &mut app.router,
+            delete_request("", r#"{"ids": [1, 3, 8, 9]}"#),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "deleted": 2 })
+        );
+        let remaining: Vec<u32> = app.get_todos().await.iter().map(|todo| todo.id).collect();
+        assert_eq!(remaining, vec![2, 4]);
+    }
+
+    #[tokio::test]
+    async fn todos_that_are_already_deleted_are_not_counted() {
+        let mut app = app_with_todos().await;
+        app.send(delete_request("", r#"{"ids": [1]}"#)).await;
+
+        let (status, body) = fetch(&mut app.router, delete_request("", r#"{"ids": [1, 2]}"#)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "deleted": 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn a_dry_run_lists_the_todos_without_deleting_them() {
+        let mut app = app_with_todos().await;
+
+        let (status, body) = fetch(
+            &mut app.router,
+            delete_request("?dry_run=true", r#"{"ids": [1, 3, 3, 8]}"#),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+        assert_eq!(body[key("would_delete")], 2);
+        let listed: Vec<Todo> = serde_json::from_value(body["todos"].clone()).unwrap();
+        assert_eq!(
+            listed.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(app.get_todos().await.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_told_about_each_deleted_todo() {
+        let mut app = app_with_todos().await;
+        let mut events = app.subscribe().await;
+
+        app.send(delete_request("", r#"{"ids": [1, 3, 8]}"#)).await;
+
+        assert_eq!(
+            events.take(2).await,
+            vec![("deleted".into(), 1), ("deleted".into(), 3)]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_ids_together_with_a_filter() {
+        let mut app = app_with_todos().await;
+
+        let (status, _) = fetch(
+            &mut app.router,
+            delete_request("?completed=true", r#"{"ids": [1]}"#),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(app.get_todos().await.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_body_that_is_not_a_list_of_ids() {
+        let mut app = app_with_todos().await;
+
+        let (status, _) = fetch(&mut app.router, delete_request("", r#"{"ids": "all"}"#)).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(app.get_todos().await.len(), 4);
+    }
+}
+
+mod pretty {
+    use super::*;
+
+    #[tokio::test]
+    async fn indents_the_json_when_asked_to() {
+        let mut app = TestApp::new().await;
+        app.create_todo(default_todo()).await;
+
+        let response = app.send(get_request("/todos?pretty=true")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[axum::http::header::CONTENT_TYPE],
+            "application/json"
+        );
+        let body = read_body(response).await;
+        assert!(body.contains(&b'\n'));
+        assert_eq!(
+            serde_json::from_slice::<Vec<Todo>>(&body).unwrap(),
+            app.get_todos().await
+        );
+    }
+
+    #[tokio::test]
+    async fn is_compact_by_default() {
+        let mut app = TestApp::new().await;
+        app.create_todo(default_todo()).await;
+
+        let (status, body) = fetch(&mut app.router, get_todos_request()).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(!body.contains(&b'\n'));
+    }
+}
+
+mod request_id {
+    use super::*;
+
+    #[tokio::test]
+    async fn echoes_the_request_id() {
+        let mut app = TestApp::new().await;
+
+        let response = app
+            .send(
+                Request::builder()
+                    .uri("/todos")
+                    .header("x-request-id", "my-request-42")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-request-id"], "my-request-42");
+    }
+
+    #[tokio::test]
+    async fn generates_a_request_id_if_there_is_none() {
+        let mut app = TestApp::new().await;
+
+        let first = app.send(get_todos_request()).await;
+        let second = app.send(get_todos_request()).await;
+
+        let first = first.headers()["x-request-id"].to_str().unwrap();
+        let second = second.headers()["x-request-id"].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(first).is_ok());
+        assert_ne!(first, second);
+    }
+}
+
+mod find_replace {
+    use super::*;
+
+    async fn app_with_names(names: &[&str]) -> TestApp {
+        let mut app = TestApp::new().await;
+
+        for (id, name) in (1..).zip(names) {
+            app.create_todo(Todo {
+                id,
+                name: (*name).into(),
+                ..default_todo()
+            })
+            .await;
+        }
+
+        app
+    }
+
+    async fn replace(
+        app: &mut TestApp,
+        find: &str,
+        replace: &str,
+    ) -> (StatusCode, axum::body::Bytes) {
+        fetch(
+            &mut app.router,
+            json_request(
+                axum::http::Method::POST,
+                "/todos/replace",
+                &serde_json::json!({ "find": find, "replace": replace }),
+            ),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn replaces_text_in_every_name() {
+        let mut app = app_with_names(&["Buy milk", "Walk the dog", "Buy more milk"]).await;
+
+        let (status, body) = replace(&mut app, "milk", "oat milk").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "updated": 2 })
+        );
+
+        let names: Vec<String> = app
+            .get_todos()
+            .await
+            .into_iter()
+            .map(|todo| todo.name)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["Buy oat milk", "Walk the dog", "Buy more oat milk"]
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_told_about_each_renamed_todo() {
+        let mut app = app_with_names(&["Buy milk", "Walk the dog", "Buy more milk"]).await;
+        let mut events = app.subscribe().await;
+
+        replace(&mut app, "milk", "oat milk").await;
+
+        let (action, todo) = events.next().await;
+        assert_eq!((action.as_str(), todo.id), ("updated", 1));
+        assert_eq!(todo.name, "Buy oat milk");
+        let (action, todo) = events.next().await;
+        assert_eq!((action.as_str(), todo.id), ("updated", 3));
+        assert_eq!(todo.name, "Buy more oat milk");
+    }
+
+    #[tokio::test]
+    async fn rejects_an_empty_find() {
+        let mut app = app_with_names(&["Buy milk"]).await;
+
+        let (status, _) = replace(&mut app, "", "x").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(app.get_todos().await[0].name, "Buy milk");
+    }
+
+    #[tokio::test]
+    async fn leaves_names_that_would_become_blank() {
+        let mut app = app_with_names(&["milk", "Buy milk"]).await;
+
+        let (status, body) = replace(&mut app, "milk", " ").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "updated": 1 })
+        );
+
+        let names: Vec<String> = app
+            .get_todos()
+            .await
+            .into_iter()
+            .map(|todo| todo.name)
+            .collect();
+        assert_eq!(names, vec!["milk", "Buy  "]);
+    }
+
+    #[tokio::test]
+    async fn rejects_names_that_would_get_too_long() {
+        let mut app = app_with_names(&["Buy milk"]).await;
+
+        let (status, body) = replace(&mut app, "milk", &"m".repeat(300)).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()["field"],
+            "name"
+        );
+        assert_eq!(app.get_todos().await[0].name, "Buy milk");
+    }
+}
+
+mod export {
+    use super::*;
+
+    async fn export(app: &mut TestApp) -> serde_json::Value {
+        let response = app.send(get_request("/export")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        read_json(response).await
+    }
+
+    #[tokio::test]
+    async fn round_trips_through_import() {
+        let mut app = TestApp::new().await;
+        app.create_todo(Todo {
+            id: 1,
+            name: "Plan the trip".into(),
+            tags: vec!["travel".into()],
+            due_date: Some("2024-06-01T09:00:00Z".parse().unwrap()),
+            description: Some("Somewhere warm".into()),
+            ..default_todo()
+        })
+        .await;
+        app.create_todo(Todo {
+            id: 2,
+            name: "Book the flights".into(),
+            completed: true,
+            parent_id: Some(1),
+            assignee: Some("alice".into()),
+            ..default_todo()
+        })
+        .await;
+        app.create_todo(Todo {
+            id: 3,
+            name: "Cancel the gym".into(),
+            ..default_todo()
+        })
+        .await;
+        let response = app
+            .send(
+                Request::builder()
+                    .uri("/todos/3")
+                    .method(axum::http::Method::DELETE)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert!(response.status().is_success());
+
+        let exported = export(&mut app).await;
+
+        assert_eq!(exported["version"], 1);
+        assert!(exported[key("exported_at")].is_string());
+        assert_eq!(exported["todos"].as_array().unwrap().len(), 3);
+
+        let mut fresh = TestApp::new().await;
+        let response = fresh
+            .send(json_request(
+                axum::http::Method::POST,
+                "/todos/import",
+                &exported,
+            ))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(export(&mut fresh).await["todos"], exported["todos"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_export_versions() {
+        let mut app = TestApp::new().await;
+
+        let response = app
+            .send(json_request(
+                axum::http::Method::POST,
+                "/todos/import",
+                &serde_json::json!({
+                    "version": 2,
+                    key("exported_at"): "2024-01-01T00:00:00Z",
+                    "todos": [default_todo()],
+                }),
+            ))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(app.get_todos().await.is_empty());
+    }
+}
+
+mod unique_names {
+    use super::*;
+
+    fn named(id: u32, name: &str) -> Todo {
+        Todo {
+            id,
+            name: name.into(),
+            ..default_todo()
+        }
+    }
+
+    async fn create(app: &mut TestApp, uri: &str, todo: Todo) -> (StatusCode, serde_json::Value) {
+        let (status, body) = fetch(
+            &mut app.router,
+            json_request(axum::http::Method::POST, uri, &new_todo_body(&todo)),
+        )
+        .await;
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn rejects_a_duplicate_name_ignoring_case() {
+        let mut app = TestApp::new().await;
+        app.create_todo(named(1, "Buy milk")).await;
+
+        let (status, body) = create(&mut app, "/todos?unique=true", named(2, "buy MILK")).await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "duplicate_name");
+        assert_eq!(app.get_todos().await.len(), 1);
+
+        let (status, _) = create(&mut app, "/todos?unique=true", named(3, "Buy bread")).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn allows_duplicate_names_by_default() {
+        let mut app = TestApp::new().await;
+        app.create_todo(named(1, "Buy milk")).await;
+
+        let (status, _) = create(&mut app, "/todos", named(2, "Buy milk")).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(app.get_todos().await.len(), 2);
+    }
+}
+
+mod purge {
+    use super::*;
+    use std::time::Duration;
+    use todo_server_workshop::{
+        app_with_pool, open_database, purge_completed, SqliteRepository, TodoChange,
+    };
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn db_path(test: &str) -> String {
+        let uuid = uuid::Uuid::new_v4();
+        format!("tests/db/{test}_{uuid}.db")
+    }
+
+    /// Creates todos 1 to 5, where 1, 2 and 4 are completed and 5 is a subtask of 4
+    async fn create_todos(app: &mut TestApp) {
+        for (id, completed, parent_id) in [
+            (1, true, None),
+            (2, true, None),
+            (3, false, None),
+            (4, true, None),
+            (5, false, Some(4)),
+        ] {
+            app.create_todo(Todo {
+                id,
+                completed,
+                parent_id,
+                ..default_todo()
+            })
+            .await;
+        }
+    }
+
+    /// Pretends the todos were completed years ago
+    fn complete_long_ago(db_path: &str, ids: &[u32]) {
+        let connection = rusqlite::Connection::open(db_path).unwrap();
+        for id in ids {
+            connection
+                .execute(
+                    "UPDATE todos SET completed_at = '2020-01-01 00:00:00+00:00' WHERE id = ?1",
+                    [id],
+                )
+                .unwrap();
+        }
+    }
+
+    async fn stored_ids(app: &mut TestApp) -> Vec<u32> {
+        let response = app.send(get_request("/todos?include_deleted=true")).await;
+        read_json::<Vec<Todo>>(response)
+            .await
+            .into_iter()
+            .map(|todo| todo.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn deletes_todos_completed_before_the_retention() {
+        let db_path = db_path("deletes_todos_completed_before_the_retention");
+        let config = AppConfig::builder().db_path(db_path.clone()).build();
+        let pool = open_database(&config).await.unwrap();
+        let mut app = TestApp::from(app_with_pool(pool.clone(), config.clone()).await.unwrap());
+        create_todos(&mut app).await;
+        complete_long_ago(&db_path, &[1, 4]);
+
+        let repository = SqliteRepository::new(pool, config.table_name().clone());
+        let purged = purge_completed(&repository, 30 * DAY).await.unwrap();
+
+        // 4 is kept, as its subtask still refers to it
+        assert_eq!(purged, 1);
+        assert_eq!(stored_ids(&mut app).await, vec![2, 3, 4, 5]);
+
+        let response = app.send(get_request("/todos/1/history")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app.send(get_request("/todos/2/history")).await;
+        assert_eq!(read_json::<Vec<TodoChange>>(response).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn purges_in_the_background_when_configured() {
+        let db_path = db_path("purges_in_the_background_when_configured");
+        let config = AppConfig::builder()
+            .db_path(db_path.clone())
+            .purge_completed_after(DAY)
+            .purge_interval(Duration::from_millis(10))
+            .build();
+        let mut app = TestApp::from(app(config).await.unwrap());
+        create_todos(&mut app).await;
+        complete_long_ago(&db_path, &[1]);
+
+        for _ in 0..100 {
+            if !stored_ids(&mut app).await.contains(&1) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(stored_ids(&mut app).await, vec![2, 3, 4, 5]);
+    }
+}
+
+mod json_keys {
+    use super::*;
+
+    async fn created_json() -> serde_json::Value {
+        let mut app = TestApp::new().await;
+        let response = app
+            .send(post_todo_request(Todo {
+                due_date: Some("2024-01-31T12:30:00Z".parse().unwrap()),
+                ..default_todo()
+            }))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let [created] = read_json::<[serde_json::Value; 1]>(response).await;
+        created
+    }
+
+    #[cfg(not(feature = "camel-case"))]
+    #[tokio::test]
+    async fn uses_snake_case_keys_by_default() {
+        let json = created_json().await;
+
+        assert_eq!(json["due_date"], "2024-01-31T12:30:00Z");
+        assert!(json.get("completed_at").is_some());
+        assert!(json.get("dueDate").is_none());
+    }
+
+    #[cfg(feature = "camel-case")]
+    #[tokio::test]
+    async fn uses_camel_case_keys_with_the_feature() {
+        let json = created_json().await;
+
+        assert_eq!(json["dueDate"], "2024-01-31T12:30:00Z");
+        assert!(json.get("completedAt").is_some());
+        assert!(json.get("parentId").is_some());
+        assert!(json.get("due_date").is_none());
+    }
+}
+
+mod reset {
+    use super::*;
+
+    #[tokio::test]
+    async fn marks_every_todo_as_not_completed() {
+        let mut app = TestApp::new().await;
+        for id in 1..=3 {
+            app.create_todo(Todo {
+                id,
+                name: format!("Todo number {id}"),
+                completed: id != 2,
+                ..default_todo()
+            })
+            .await;
+        }
+
+        let response = app.send(post_request("/todos/reset")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let returned: Vec<Todo> = read_json(response).await;
+        assert_eq!(
+            returned.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(returned, app.get_todos().await);
+        for todo in returned {
+            assert!(!todo.completed);
+            assert_eq!(todo.completed_at, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_told_about_each_reset_todo() {
+        let mut app = TestApp::new().await;
+        for id in 1..=2 {
+            app.create_todo(Todo {
+                id,
+                completed: true,
+                ..default_todo()
+            })
+            .await;
+        }
+        let mut events = app.subscribe().await;
+
+        app.send(post_request("/todos/reset")).await;
+
+        let (action, todo) = events.next().await;
+        assert_eq!(
+            (action.as_str(), todo.id, todo.completed),
+            ("toggled", 1, false)
+        );
+        let (action, todo) = events.next().await;
+        assert_eq!(
+            (action.as_str(), todo.id, todo.completed),
+            ("toggled", 2, false)
+        );
+    }
+}
+
+mod cursor_pagination {
+    use super::*;
+
+    /// The `after` cursor of the next page, from the `Link` header
+    fn next_cursor(response: &axum::http::Response<Body>) -> Option<u32> {
+        let link = response.headers().get(axum::http::header::LINK)?;
+        let link = link.to_str().unwrap();
+        let after = link.split("after=").nth(1)?.split('&').next()?;
+        Some(after.parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn pages_through_every_todo_without_overlap_or_gaps() {
+        let mut app = TestApp::new().await;
+        // Gaps in the ids, so the cursor can't be mistaken for an offset
+        let seeded: Vec<u32> = (1..=47).map(|n| n * 3).collect();
+        for &id in &seeded {
+            app.create_todo(Todo {
+                id,
+                name: format!("Todo number {id}"),
+                ..default_todo()
+            })
+            .await;
+        }
+
+        let mut seen = Vec::new();
+        let mut after = Some(0);
+        let mut pages = 0;
+        while let Some(cursor) = after {
+            let response = app
+                .send(get_request(&format!("/todos?after={cursor}&limit=10")))
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            after = next_cursor(&response);
+            let todos: Vec<Todo> = read_json(response).await;
+            assert!(todos.iter().all(|todo| todo.id > cursor));
+            seen.extend(todos.into_iter().map(|todo| todo.id));
+            pages += 1;
+        }
+
+        assert_eq!(seen, seeded);
+        assert_eq!(pages, 5);
+    }
+
+    #[tokio::test]
+    async fn has_no_next_page_when_the_last_page_is_exactly_full() {
+        let mut app = TestApp::new().await;
+        for id in 1..=4 {
+            app.create_todo(Todo {
+                id,
+                ..default_todo()
+            })
+            .await;
+        }
+
+        let response = app.send(get_request("/todos?after=0&limit=2")).await;
+        assert_eq!(
+            response.headers()[axum::http::header::LINK],
+            "</todos?after=2&limit=2>; rel=\"next\""
+        );
+
+        let response = app.send(get_request("/todos?after=2&limit=2")).await;
+        assert_eq!(next_cursor(&response), None);
+        let todos: Vec<Todo> = read_json(response).await;
+        assert_eq!(
+            todos.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+    }
+
+    #[tokio::test]
+    async fn applies_the_other_filters() {
+        let mut app = TestApp::new().await;
+        for id in 1..=5 {
+            app.create_todo(Todo {
+                id,
+                completed: id == 2,
+                ..default_todo()
+            })
+            .await;
+        }
+        app.send(post_request("/todos/4/archive")).await;
+
+        let response = app
+            .send(get_request("/todos?completed=false&after=0&limit=2"))
+            .await;
+
+        assert_eq!(
+            response.headers()[axum::http::header::LINK],
+            "</todos?completed=false&after=3&limit=2>; rel=\"next\""
+        );
+        let todos: Vec<Todo> = read_json(response).await;
+        assert_eq!(
+            todos.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+
+        let response = app
+            .send(get_request("/todos?completed=false&after=3&limit=2"))
+            .await;
+        assert_eq!(next_cursor(&response), None);
+        let todos: Vec<Todo> = read_json(response).await;
+        assert_eq!(
+            todos.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![5]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_after_together_with_sorting_or_an_envelope() {
+        let mut app = TestApp::new().await;
+
+        for query in ["sort=name", "order=desc", "envelope=true"] {
+            let response = app
+                .send(get_request(&format!("/todos?after=0&{query}")))
+                .await;
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{query}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_after_together_with_offset() {
+        let mut app = TestApp::new().await;
+
+        let response = app.send(get_request("/todos?after=0&offset=10")).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+mod method_not_allowed {
+    use super::*;
+
+    #[tokio::test]
+    async fn lists_the_allowed_methods() {
+        let mut app = TestApp::new().await;
+
+        let response = app
+            .send(
+                Request::builder()
+                    .uri("/")
+                    .method(axum::http::Method::DELETE)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        // Routes that answer GET answer HEAD as well
+        assert_eq!(response.headers()[axum::http::header::ALLOW], "GET,HEAD");
+        let body: serde_json::Value = read_json(response).await;
+        assert_eq!(body["code"], "method_not_allowed");
+    }
+
+    #[tokio::test]
+    async fn lists_every_method_of_a_todo() {
+        let mut app = TestApp::new().await;
+
+        let response = app.send(post_request("/todos/1")).await;
+
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let allow = response.headers()[axum::http::header::ALLOW]
+            .to_str()
+            .unwrap();
+        let mut allow: Vec<&str> = allow.split(',').collect();
+        allow.sort_unstable();
+        assert_eq!(allow, vec!["DELETE", "GET", "HEAD", "PATCH", "PUT"]);
+    }
+}
+
+mod not_found {
+    use super::*;
+
+    #[tokio::test]
+    async fn unknown_paths_have_an_error_body() {
+        let mut app = TestApp::new().await;
+
+        let response = app.send(get_request("/nonexistent")).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = read_json(response).await;
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["path"], "/nonexistent");
+        assert!(body["error"].is_string());
+    }
+}
+
+mod fetch_by_ids {
+    use super::*;
+
+    async fn get_ids(app: &mut TestApp, query: &str) -> Vec<u32> {
+        let response = app.send(get_request(&format!("/todos?ids={query}"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let todos: Vec<Todo> = read_json(response).await;
+        todos.into_iter().map(|todo| todo.id).collect()
+    }
+
+    #[tokio::test]
+    async fn returns_only_the_todos_that_exist() {
+        let mut app = TestApp::new().await;
+        for id in 1..=5 {
+            app.create_todo(Todo {
+                id,
+                ..default_todo()
+            })
+            .await;
+        }
+
+        let ids = get_ids(&mut app, "4,2,7,5,100").await;
+
+        assert_eq!(ids, vec![2, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn combines_with_other_filters() {
+        let mut app = TestApp::new().await;
+        for id in 1..=4 {
+            app.create_todo(Todo {
+                id,
+                completed: id % 2 == 0,
+                ..default_todo()
+            })
+            .await;
+        }
+
+        let ids = get_ids(&mut app, "1,2,3&completed=true").await;
+
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn rejects_ids_that_are_not_numbers() {
+        let mut app = TestApp::new().await;
+
+        let response = app.send(get_request("/todos?ids=1,two,3")).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = read_json(response).await;
+        assert_eq!(body["code"], "invalid_ids");
+    }
+}
+
+mod strict_json {
+    use super::*;
+
+    async fn post_with_typo() -> axum::http::Response<Body> {
+        let mut app = TestApp::new().await;
+        app.send(json_request(
+            axum::http::Method::POST,
+            "/todos",
+            &serde_json::json!({ "name": "Walk the dog", "compelted": true }),
+        ))
+        .await
+    }
+
+    #[cfg(not(feature = "strict-json"))]
+    #[tokio::test]
+    async fn ignores_unknown_fields_by_default() {
+        let response = post_with_typo().await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[cfg(feature = "strict-json")]
+    #[tokio::test]
+    async fn rejects_unknown_fields_naming_them() {
+        let response = post_with_typo().await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = read_json(response).await;
+        assert_eq!(body["code"], "invalid_body");
+        assert_eq!(body["field"], "compelted");
+    }
+}
@@ -0,0 +1,87 @@
+//! Tests for serving the app on a Unix domain socket.
+
+#![cfg(unix)]
+
+use std::{
+    io::{Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+};
+
+use todo_server_workshop::{app_in_memory, serve_unix};
+use tokio::sync::oneshot;
+
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("todos-{}-{name}.sock", std::process::id()))
+}
+
+/// Sends a request over the socket, returning the raw response
+async fn request(path: &Path, request_line: &str) -> String {
+    let path = path.to_owned();
+    let request = format!("{request_line}\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    tokio::task::spawn_blocking(move || {
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    })
+    .await
+    .unwrap()
+}
+
+async fn wait_for_socket(path: &Path) {
+    while UnixStream::connect(path).is_err() {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn answers_health_checks_over_the_socket() {
+    let path = socket_path("health");
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_unix(path.clone(), app_in_memory().await, async {
+        let _ = stopped.await;
+    }));
+    wait_for_socket(&path).await;
+
+    let response = request(&path, "GET /health HTTP/1.1").await;
+
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+
+    stop.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn replaces_a_stale_socket() {
+    let path = socket_path("stale");
+    // Binding and dropping a listener leaves the socket file behind, like a crashed server would
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_unix(path.clone(), app_in_memory().await, async {
+        let _ = stopped.await;
+    }));
+    wait_for_socket(&path).await;
+
+    let response = request(&path, "GET /health HTTP/1.1").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+
+    stop.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn refuses_a_socket_another_server_listens_on() {
+    let path = socket_path("in-use");
+    let _ = std::fs::remove_file(&path);
+    let _listener = UnixListener::bind(&path).unwrap();
+
+    let result = serve_unix(&path, app_in_memory().await, async {}).await;
+
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::AddrInUse);
+    std::fs::remove_file(&path).unwrap();
+}